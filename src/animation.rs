@@ -0,0 +1,196 @@
+//! Rotation, skew and backdrops of the floating emotes. `Image` can't be rotated,
+//! emotes are drawn as image quads with moved corners instead.
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+use strum::{VariantArray, VariantNames};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Rotation {
+    #[default]
+    None,
+    /// Constant spin, half a turn per second
+    Spin,
+    /// Swings back and forth
+    Pendulum,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Skew {
+    #[default]
+    None,
+    /// Horizontal shear that swings back and forth
+    Wobble,
+}
+
+/// Drawn behind emotes so light emotes stay visible over bright skyboxes
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Backdrop {
+    #[default]
+    None,
+    /// Soft shadow towards the bottom right
+    Shadow,
+    /// 1px dark outline
+    Outline,
+}
+
+impl Backdrop {
+    /// Offsets and tints of the silhouettes drawn behind the emote
+    pub fn layers(&self) -> &'static [([f32; 2], [f32; 4])] {
+        const SHADOW: [f32; 4] = [0.0, 0.0, 0.0, 0.25];
+        const OUTLINE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+        match self {
+            Backdrop::None => &[],
+            Backdrop::Shadow => &[
+                ([1.0, 1.0], SHADOW),
+                ([2.0, 2.0], SHADOW),
+                ([3.0, 3.0], SHADOW),
+            ],
+            Backdrop::Outline => &[
+                ([-1.0, -1.0], OUTLINE),
+                ([0.0, -1.0], OUTLINE),
+                ([1.0, -1.0], OUTLINE),
+                ([-1.0, 0.0], OUTLINE),
+                ([1.0, 0.0], OUTLINE),
+                ([-1.0, 1.0], OUTLINE),
+                ([0.0, 1.0], OUTLINE),
+                ([1.0, 1.0], OUTLINE),
+            ],
+        }
+    }
+
+    /// Extra room needed on each side so the backdrop isn't clipped
+    pub fn padding(&self) -> f32 {
+        self.layers()
+            .iter()
+            .map(|([x, y], _)| x.abs().max(y.abs()))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Appearance of emotes, emote sets can override the global one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Style {
+    #[serde(default)]
+    pub animation: Animation,
+    #[serde(default)]
+    pub backdrop: Backdrop,
+    /// Multiplied with the emote colors, white keeps them as they are
+    #[serde(default = "white")]
+    pub color: [f32; 4],
+    /// Fading copies along the recent path
+    #[serde(default)]
+    pub trail: bool,
+}
+
+fn white() -> [f32; 4] {
+    [1.0; 4]
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            animation: Animation::default(),
+            backdrop: Backdrop::default(),
+            color: white(),
+            trail: false,
+        }
+    }
+}
+
+/// Number of copies in a trail
+const TRAIL_LENGTH: usize = 6;
+/// Time between two trail points, independent of the frame rate
+const TRAIL_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Ring buffer of the recent centers of an emote
+#[derive(Debug, Clone, Default)]
+pub struct Trail {
+    points: VecDeque<[f32; 2]>,
+    last: Option<Instant>,
+}
+
+impl Trail {
+    pub fn record(&mut self, center: [f32; 2]) {
+        if self
+            .last
+            .is_some_and(|last| last.elapsed() < TRAIL_INTERVAL)
+        {
+            return;
+        }
+        self.last = Some(Instant::now());
+        if self.points.len() == TRAIL_LENGTH {
+            self.points.pop_front();
+        }
+        self.points.push_back(center);
+    }
+
+    /// Past centers with their opacity, oldest and faintest first
+    pub fn ghosts(&self) -> impl Iterator<Item = ([f32; 2], f32)> + '_ {
+        self.points
+            .iter()
+            .enumerate()
+            .map(|(i, center)| (*center, 0.4 * (i + 1) as f32 / (TRAIL_LENGTH + 1) as f32))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    pub rotation: Rotation,
+    pub skew: Skew,
+}
+
+impl Animation {
+    pub fn is_static(&self) -> bool {
+        self.rotation == Rotation::None && self.skew == Skew::None
+    }
+
+    fn angle(&self, t: f32) -> f32 {
+        match self.rotation {
+            Rotation::None => 0.0,
+            Rotation::Spin => t * PI,
+            Rotation::Pendulum => (t * 2.0).sin() * 0.4,
+        }
+    }
+
+    fn shear(&self, t: f32) -> f32 {
+        match self.skew {
+            Skew::None => 0.0,
+            Skew::Wobble => (t * 3.0).sin() * 0.2,
+        }
+    }
+
+    /// Space the transformed emote can take up, rotated emotes need room for their diagonal
+    pub fn bounds(&self, [width, height]: [f32; 2]) -> [f32; 2] {
+        if self.is_static() {
+            return [width, height];
+        }
+        // shear is at most 0.2, so half the height widens it by at most 0.1 * height
+        let width = width + height * 0.2;
+        if self.rotation == Rotation::None {
+            [width, height]
+        } else {
+            let diagonal = width.hypot(height);
+            [diagonal, diagonal]
+        }
+    }
+
+    /// Corners of an image of `size` around `center` at `t` seconds,
+    /// in the order top left, top right, bottom right, bottom left
+    pub fn corners(&self, center: [f32; 2], [width, height]: [f32; 2], t: f32) -> [[f32; 2]; 4] {
+        let (sin, cos) = self.angle(t).sin_cos();
+        let shear = self.shear(t);
+        let (w, h) = (width / 2.0, height / 2.0);
+        [[-w, -h], [w, -h], [w, h], [-w, h]].map(|[x, y]| {
+            let x = x + shear * y;
+            [center[0] + x * cos - y * sin, center[1] + x * sin + y * cos]
+        })
+    }
+}
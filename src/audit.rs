@@ -0,0 +1,147 @@
+//! Append-only log of settings changes, to answer "why did my emotes stop showing yesterday"
+use crate::settings::Settings;
+use crate::util::e;
+use nexus::imgui::Ui;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use time::UtcDateTime;
+
+const AUDIT_FILE: &str = "audit.log";
+/// Entries shown in the settings, the file keeps all of them
+const RECENT_ENTRIES: usize = 50;
+/// Bookkeeping and secrets, never logged
+const IGNORED: &[&str] = &["modified", "synced", "gist_token", "proxy"];
+
+/// Settings as they were last loaded or saved
+static BASELINE: Mutex<Option<Value>> = const { Mutex::new(None) };
+static RECENT: Mutex<VecDeque<String>> = const { Mutex::new(VecDeque::new()) };
+
+/// Remembers the loaded settings and reads the recent history from `dir`
+pub fn baseline(settings: &Settings, dir: &Path) {
+    rebase(settings);
+    let history = std::fs::read_to_string(dir.join(AUDIT_FILE)).unwrap_or_default();
+    let mut recent = RECENT.lock().unwrap();
+    recent.clear();
+    for line in history.lines() {
+        push(&mut recent, line.to_string());
+    }
+}
+
+/// Takes `settings` as the new baseline without logging how they changed
+pub fn rebase(settings: &Settings) {
+    *BASELINE.lock().unwrap() = serde_json::to_value(settings).ok();
+}
+
+/// Appends the changes since the last load or save to the audit file in `dir`
+pub fn record(settings: &Settings, dir: &Path) {
+    let Ok(current) = serde_json::to_value(settings) else {
+        return;
+    };
+    let Some(previous) = BASELINE.lock().unwrap().replace(current.clone()) else {
+        return;
+    };
+    let (Value::Object(previous), Value::Object(current)) = (previous, current) else {
+        return;
+    };
+    let mut changes = Vec::new();
+    for (key, value) in &current {
+        if IGNORED.contains(&key.as_str()) {
+            continue;
+        }
+        let old = previous.get(key).unwrap_or(&Value::Null);
+        changes.extend(describe(key, old, value));
+    }
+    if changes.is_empty() {
+        return;
+    }
+    let timestamp = timestamp();
+    let lines = changes
+        .into_iter()
+        .map(|change| format!("{timestamp} {change}"))
+        .collect::<Vec<_>>();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(AUDIT_FILE));
+    if let Err(e) = file.and_then(|mut file| writeln!(file, "{}", lines.join("\n"))) {
+        log::error!("Failed to write the audit log: {e}");
+    }
+    let mut recent = RECENT.lock().unwrap();
+    for line in lines {
+        push(&mut recent, line);
+    }
+}
+
+fn push(recent: &mut VecDeque<String>, line: String) {
+    if recent.len() >= RECENT_ENTRIES {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+fn timestamp() -> String {
+    let now = UtcDateTime::now();
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute()
+    )
+}
+
+/// Lists, like emote sets or filters, log the added and removed entries instead of the whole list
+fn describe(key: &str, old: &Value, new: &Value) -> Vec<String> {
+    if old == new {
+        return Vec::new();
+    }
+    match (old, new) {
+        (Value::Array(old), Value::Array(new)) => {
+            let added = new
+                .iter()
+                .filter(|v| !old.contains(v))
+                .map(|v| format!("{key}: added {v}"));
+            let removed = old
+                .iter()
+                .filter(|v| !new.contains(v))
+                .map(|v| format!("{key}: removed {v}"));
+            added.chain(removed).collect()
+        }
+        (Value::Object(old), Value::Object(new)) => {
+            let mut changes = Vec::new();
+            for (k, v) in new {
+                changes.extend(describe(
+                    &format!("{key}.{k}"),
+                    old.get(k).unwrap_or(&Value::Null),
+                    v,
+                ));
+            }
+            for (k, v) in old.iter().filter(|(k, _)| !new.contains_key(*k)) {
+                changes.extend(describe(&format!("{key}.{k}"), v, &Value::Null));
+            }
+            changes
+        }
+        _ => vec![format!("{key}: {old} -> {new}")],
+    }
+}
+
+/// Recent settings changes, newest first
+pub fn history_ui(ui: &Ui) {
+    let recent = RECENT.lock().unwrap();
+    if recent.is_empty() {
+        ui.text_disabled(e("No settings changes recorded yet"));
+        return;
+    }
+    ui.child_window("##audithistory")
+        .size([0.0, 200.0])
+        .build(|| {
+            for line in recent.iter().rev() {
+                ui.text(line);
+            }
+        });
+}
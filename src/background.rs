@@ -1,50 +1,325 @@
-use std::sync::mpsc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-type Job = Box<dyn FnOnce() + Send>;
+pub type Job = Box<dyn FnOnce() + Send>;
+/// Called with whether the job ran, `false` if it was cancelled before it started
+type FinishCallback = Box<dyn FnOnce(bool) + Send>;
+
+/// Jobs of a higher priority run first, jobs of the same priority in the order they were spawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Bulk work nobody waits for, e.g. preloads and cache maintenance
+    Low,
+    Normal,
+    /// Directly triggered by the user, e.g. adding an emote set in the options
+    High,
+}
+
+#[derive(Default)]
+struct JobStatus {
+    cancelled: bool,
+    started: bool,
+    /// Whether the job ran, set once it ran or was skipped
+    finished: Option<bool>,
+    callbacks: Vec<FinishCallback>,
+}
+
+#[derive(Default)]
+struct JobState {
+    status: Mutex<JobStatus>,
+    finished: Condvar,
+}
+
+/// Handle of a spawned job, dropping it leaves the job running
+#[derive(Clone, Default)]
+pub struct JobHandle(Arc<JobState>);
+
+impl JobHandle {
+    /// Skips the job if it didn't start yet, returns whether it will be skipped
+    pub fn cancel(&self) -> bool {
+        let mut status = self.0.status.lock().unwrap();
+        status.cancelled = true;
+        !status.started
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.status.lock().unwrap().finished.is_some()
+    }
+
+    /// Blocks until the job ran or was skipped, returns whether it ran
+    pub fn wait(&self) -> bool {
+        let mut status = self.0.status.lock().unwrap();
+        loop {
+            if let Some(ran) = status.finished {
+                return ran;
+            }
+            status = self.0.finished.wait(status).unwrap();
+        }
+    }
+
+    /// Runs `f` on the worker thread once the job ran or was skipped, right away if it already did.
+    /// `f` gets whether the job ran
+    pub fn on_finish(&self, f: impl FnOnce(bool) + Send + 'static) {
+        let mut status = self.0.status.lock().unwrap();
+        match status.finished {
+            Some(ran) => {
+                drop(status);
+                f(ran);
+            }
+            None => status.callbacks.push(Box::new(f)),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.status.lock().unwrap().cancelled
+    }
+
+    /// Returns false if the job was cancelled and must not run
+    fn start(&self) -> bool {
+        let mut status = self.0.status.lock().unwrap();
+        status.started = !status.cancelled;
+        status.started
+    }
+
+    fn finish(&self, ran: bool) {
+        let callbacks = {
+            let mut status = self.0.status.lock().unwrap();
+            status.finished = Some(ran);
+            std::mem::take(&mut status.callbacks)
+        };
+        self.0.finished.notify_all();
+        for callback in callbacks {
+            callback(ran);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    /// One queue per priority, indexed by `Priority as usize`
+    jobs: [VecDeque<(Job, JobHandle)>; 3],
+    closed: bool,
+}
+
+impl Queue {
+    fn pop(&mut self) -> Option<(Job, JobHandle)> {
+        self.jobs.iter_mut().rev().find_map(VecDeque::pop_front)
+    }
+}
+
+/// Job that runs again every `every`, e.g. the emote set refresh
+struct Task {
+    name: &'static str,
+    every: Duration,
+    next: Instant,
+    job: Arc<dyn Fn() + Send + Sync>,
+    /// Last run, a task still queued or running is not queued again
+    last: Option<JobHandle>,
+}
+
+#[derive(Default)]
+struct Timers {
+    tasks: Vec<Task>,
+    closed: bool,
+}
+
+/// Queue shared by the threads of a worker
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+    timers: Mutex<Timers>,
+    /// Wakes the timer thread when tasks change or the worker shuts down
+    timers_changed: Condvar,
+}
+
+impl Shared {
+    fn push(&self, priority: Priority, job: Job) -> JobHandle {
+        let handle = JobHandle::default();
+        self.queue.lock().unwrap().jobs[priority as usize].push_back((job, handle.clone()));
+        self.available.notify_one();
+        handle
+    }
+
+    /// Queues the due tasks until the worker shuts down
+    fn run_timers(&self) {
+        let mut timers = self.timers.lock().unwrap();
+        while !timers.closed {
+            let now = Instant::now();
+            for task in &mut timers.tasks {
+                if task.next > now {
+                    continue;
+                }
+                task.next = now + task.every;
+                if task.last.as_ref().is_some_and(|last| !last.is_finished()) {
+                    log::debug!("Skipping {}, the last run didn't finish", task.name);
+                    continue;
+                }
+                log::trace!("Queueing {}", task.name);
+                let job = task.job.clone();
+                task.last = Some(self.push(Priority::Low, Box::new(move || job())));
+            }
+            let next = timers.tasks.iter().map(|task| task.next).min();
+            timers = match next {
+                Some(next) => {
+                    let timeout = next.saturating_duration_since(Instant::now());
+                    self.timers_changed.wait_timeout(timers, timeout).unwrap().0
+                }
+                None => self.timers_changed.wait(timers).unwrap(),
+            };
+        }
+    }
+
+    /// Blocks until a job is queued, `None` once the worker is dropped and the queue is empty
+    fn next(&self) -> Option<(Job, JobHandle)> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(job) = queue.pop() {
+                return Some(job);
+            }
+            if queue.closed {
+                return None;
+            }
+            queue = self.available.wait(queue).unwrap();
+        }
+    }
+}
 
 pub struct Worker {
-    input_queue: Option<mpsc::Receiver<Job>>,
-    tx: Option<mpsc::Sender<Job>>,
-    thread: Option<thread::JoinHandle<()>>,
+    shared: Arc<Shared>,
+    threads: Vec<thread::JoinHandle<()>>,
+    name: String,
+    thread_count: usize,
+    interval: Duration,
 }
 
 pub struct RunningWorker {
     worker: Worker,
+    /// Started with the first recurring task
+    timer: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+/// Thread count for `threads(0)`, leaves most cores to the game
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism().map_or(1, |cores| (cores.get() / 4).clamp(1, 4))
 }
 
 impl Worker {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel();
         Self {
-            input_queue: Some(rx),
-            tx: Some(tx),
-            thread: None,
+            shared: Arc::new(Shared::default()),
+            threads: Vec::new(),
+            name: "Background Worker".to_string(),
+            thread_count: 1,
+            interval: Duration::ZERO,
         }
     }
 
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Number of threads working on the queue in parallel, 0 uses [`default_thread_count`]
+    pub fn threads(mut self, thread_count: usize) -> Self {
+        self.thread_count = match thread_count {
+            0 => default_thread_count(),
+            n => n,
+        };
+        self
+    }
+
+    /// Minimum time between the start of two jobs
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
     pub fn run(mut self) -> RunningWorker {
-        let rx = self.input_queue.take().expect("Queue to exist");
-        let thread = thread::Builder::new()
-            .name("Background Worker".to_string())
-            .spawn(move || {
-                while let Ok(job) = rx.recv() {
-                    log::trace!("Received job");
-                    job();
-                    log::trace!("Finished job");
-                }
-                log::trace!("Worker thread exiting");
-            })
-            .unwrap();
-        self.thread = Some(thread);
-        RunningWorker { worker: self }
+        let next_start = Arc::new(Mutex::new(Instant::now()));
+        for i in 0..self.thread_count {
+            let shared = self.shared.clone();
+            let next_start = next_start.clone();
+            let interval = self.interval;
+            let thread = thread::Builder::new()
+                .name(format!("{} #{i}", self.name))
+                .spawn(move || {
+                    loop {
+                        let Some((job, handle)) = shared.next() else {
+                            break;
+                        };
+                        log::trace!("Received job");
+                        // cancelled jobs don't take up a start slot
+                        if !interval.is_zero() && !handle.is_cancelled() {
+                            thread::sleep(reserve_start(&next_start, interval));
+                        }
+                        let ran = handle.start();
+                        if ran {
+                            job();
+                            log::trace!("Finished job");
+                        } else {
+                            log::trace!("Skipped cancelled job");
+                        }
+                        handle.finish(ran);
+                    }
+                    log::trace!("Worker thread exiting");
+                })
+                .unwrap();
+            self.threads.push(thread);
+        }
+        RunningWorker {
+            worker: self,
+            timer: Mutex::new(None),
+        }
     }
 }
 
+/// Reserves the next start slot, returns how long to wait for it
+fn reserve_start(next_start: &Mutex<Instant>, interval: Duration) -> Duration {
+    let mut next_start = next_start.lock().unwrap();
+    let now = Instant::now();
+    let start = (*next_start).max(now);
+    *next_start = start + interval;
+    start - now
+}
+
 impl RunningWorker {
-    pub fn spawn(&self, job: Job) {
-        if let Some(tx) = self.worker.tx.as_ref() {
-            tx.send(job).unwrap();
+    pub fn spawn(&self, job: Job) -> JobHandle {
+        self.spawn_with_priority(Priority::Normal, job)
+    }
+
+    pub fn spawn_with_priority(&self, priority: Priority, job: Job) -> JobHandle {
+        self.worker.shared.push(priority, job)
+    }
+
+    /// Runs `job` every `every` with low priority until the worker is dropped, the first run
+    /// is after one interval. A run is skipped while the previous one is still queued or running
+    pub fn schedule(
+        &self,
+        name: &'static str,
+        every: Duration,
+        job: impl Fn() + Send + Sync + 'static,
+    ) {
+        let shared = &self.worker.shared;
+        shared.timers.lock().unwrap().tasks.push(Task {
+            name,
+            every,
+            next: Instant::now() + every,
+            job: Arc::new(job),
+            last: None,
+        });
+        shared.timers_changed.notify_all();
+        let mut timer = self.timer.lock().unwrap();
+        if timer.is_none() {
+            let shared = shared.clone();
+            *timer = Some(
+                thread::Builder::new()
+                    .name(format!("{} timer", self.worker.name))
+                    .spawn(move || shared.run_timers())
+                    .unwrap(),
+            );
         }
     }
 
@@ -53,8 +328,23 @@ impl RunningWorker {
 
 impl Drop for RunningWorker {
     fn drop(&mut self) {
-        drop(self.worker.tx.take());
-        if let Some(t) = self.worker.thread.take() {
+        let mut timers = self.worker.shared.timers.lock().unwrap();
+        timers.closed = true;
+        // a queued refresh would only delay the unload
+        for task in &timers.tasks {
+            if let Some(last) = &task.last {
+                last.cancel();
+            }
+        }
+        drop(timers);
+        self.worker.shared.timers_changed.notify_all();
+        if let Some(timer) = self.timer.get_mut().unwrap().take() {
+            timer.join().unwrap();
+        }
+        // the threads finish the queued jobs before they exit
+        self.worker.shared.queue.lock().unwrap().closed = true;
+        self.worker.shared.available.notify_all();
+        for t in self.worker.threads.drain(..) {
             t.join().unwrap();
         }
     }
@@ -0,0 +1,39 @@
+//! Tiny counter of active and held back emotes, so hitting the limits doesn't look like the addon broke
+use crate::util::e;
+use nexus::imgui::{Condition, Ui, Window};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Spawns dropped because the screen was full, since the load
+static SUPPRESSED: AtomicUsize = const { AtomicUsize::new(0) };
+
+pub fn suppressed(count: usize) {
+    SUPPRESSED.fetch_add(count, Ordering::Relaxed);
+}
+
+pub fn clear() {
+    SUPPRESSED.store(0, Ordering::Relaxed);
+}
+
+pub fn render(ui: &Ui, active: usize, waiting: usize) {
+    let suppressed = SUPPRESSED.load(Ordering::Relaxed);
+    Window::new("##emotecounter")
+        .no_decoration()
+        .always_auto_resize(true)
+        .bg_alpha(0.4)
+        .focus_on_appearing(false)
+        .no_nav()
+        .position([10.0, 10.0], Condition::FirstUseEver)
+        .build(ui, || {
+            ui.text(format!(
+                "{active} {} / {waiting} {} / {suppressed} {}",
+                e("emotes active"),
+                e("waiting"),
+                e("suppressed by limits")
+            ));
+            if ui.is_item_hovered() {
+                ui.tooltip_text(e(
+                    "Emotes above the on screen limit or from loading screens wait until there is room. With the overflow set to drop, the ones that don't fit are suppressed",
+                ));
+            }
+        });
+}
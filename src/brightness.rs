@@ -0,0 +1,67 @@
+//! Rough guess of how bright the scene behind the emotes is.
+//! There's no cheap way to sample the frame, so this only looks at the
+//! tyrian day/night cycle and the kind of map the player is on.
+use nexus::data_link::read_mumble_link;
+use time::UtcDateTime;
+
+/// Mumble map type of dungeons, fractals, story instances, ...
+const MAP_TYPE_INSTANCE: u32 = 4;
+
+/// Crystal desert maps, bright enough that they stay bright at dusk
+const BRIGHT_MAPS: &[u32] = &[
+    1210, // Crystal Oasis
+    1211, // Desert Highlands
+    1226, // The Desolation
+    1228, // Elon Riverlands
+    1248, // Domain of Vabbi
+];
+
+/// Scene brightness between 0 (dark) and 1 (bright)
+pub fn scene_brightness() -> f32 {
+    let map = read_mumble_link().map(|link| (link.context.map_id, link.context.map_type));
+    if let Some((_, MAP_TYPE_INSTANCE)) = map {
+        return 0.3;
+    }
+    let daylight = daylight(UtcDateTime::now());
+    if let Some((map_id, _)) = map
+        && BRIGHT_MAPS.contains(&map_id)
+    {
+        return (daylight + 0.3).min(1.0);
+    }
+    daylight
+}
+
+/// A tyrian day lasts two hours: 5 minutes dawn, 70 day, 5 dusk and 40 night
+fn daylight(now: UtcDateTime) -> f32 {
+    let minute = (now.hour() % 2) as u32 * 60 + now.minute() as u32;
+    match minute {
+        25..30 => 0.6,
+        30..100 => 1.0,
+        100..105 => 0.6,
+        _ => 0.3,
+    }
+}
+
+/// How emotes get adjusted to the scene
+#[derive(Debug, Clone, Copy)]
+pub struct Tint {
+    /// Opacity of the emote itself
+    pub opacity: f32,
+    /// Multiplier for the alpha of shadows and outlines
+    pub backdrop: f32,
+}
+
+impl Tint {
+    pub const NONE: Tint = Tint {
+        opacity: 1.0,
+        backdrop: 1.0,
+    };
+
+    /// Dims emotes slightly in the dark and strengthens their backdrop in bright scenes
+    pub fn for_brightness(brightness: f32) -> Self {
+        Self {
+            opacity: 1.0 - 0.15 * (1.0 - brightness),
+            backdrop: 0.5 + brightness,
+        }
+    }
+}
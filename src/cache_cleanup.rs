@@ -0,0 +1,227 @@
+//! Keeps the disk caches in check. Removes cached files of emotes that no configured set
+//! contains anymore, e.g. after an emote was removed from a set or a whole set was dropped,
+//! the least recently used files once the caches grow past the size limit, and damaged files
+//! when the cache is verified.
+use crate::background::{JobHandle, Priority};
+use crate::file_cache;
+use crate::frame_cache::{self, file_name};
+use crate::provider::EmoteSet;
+use crate::settings::Settings;
+use crate::util::e;
+use crate::{WORKER, file_cache_dir, frame_cache_dir, handoff, safe_mode};
+use nexus::imgui::Ui;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Files and bytes removed in this session
+static RECLAIMED: Mutex<Option<(usize, u64)>> = const { Mutex::new(None) };
+/// Copy of the size limit in bytes, the downloads check it without the settings lock
+static MAX_BYTES: AtomicU64 = const { AtomicU64::new(0) };
+/// Bytes stored since the caches were last shrunk
+static WRITTEN: AtomicU64 = const { AtomicU64::new(0) };
+static SHRINKING: AtomicBool = const { AtomicBool::new(false) };
+/// Queued and running cleanups, unloading skips the ones that didn't start
+static JOBS: Mutex<Vec<JobHandle>> = const { Mutex::new(Vec::new()) };
+/// Checked and removed files of the last verification, `None` while it runs
+static VERIFIED: Mutex<Option<Option<(usize, usize)>>> = const { Mutex::new(None) };
+
+/// Checks a single cache file
+type Verify = fn(&Path) -> bool;
+
+fn track(job: JobHandle) {
+    let mut jobs = JOBS.lock().unwrap();
+    jobs.retain(|job| !job.is_finished());
+    jobs.push(job);
+}
+
+/// Skips the cleanups that didn't start yet, a full scan of the caches would delay the unload.
+/// Waits for a running one
+pub fn cancel() {
+    let jobs = std::mem::take(&mut *JOBS.lock().unwrap());
+    for job in jobs {
+        if !job.cancel() {
+            job.wait();
+        }
+    }
+}
+
+pub fn set_max_size_mib(mib: u32) {
+    MAX_BYTES.store(mib as u64 * 1024 * 1024, Ordering::Relaxed);
+}
+
+fn reclaimed(files: usize, bytes: u64) {
+    if files == 0 {
+        return;
+    }
+    let mut reclaimed = RECLAIMED.lock().unwrap();
+    let (total_files, total_bytes) = reclaimed.get_or_insert_default();
+    *total_files += files;
+    *total_bytes += bytes;
+}
+
+/// Queues the cleanup on the worker, `emote_sets` are the sets the reload before it downloaded
+pub fn run(settings: Settings, emote_sets: Vec<EmoteSet>) {
+    if safe_mode::is_active() {
+        return;
+    }
+    let lock = WORKER.wait().lock().unwrap();
+    let Some(worker) = lock.as_ref() else {
+        return;
+    };
+    track(worker.spawn_with_priority(
+        Priority::Low,
+        Box::new(move || {
+            if settings.cache_max_age_days > 0 {
+                remove_orphans(&settings, &emote_sets);
+            }
+            shrink();
+        }),
+    ));
+}
+
+fn remove_orphans(settings: &Settings, emote_sets: &[EmoteSet]) {
+    // a set that failed to download would make all of its emotes look orphaned
+    if let Some(missing) = settings
+        .emote_set_ids
+        .iter()
+        .find(|id| !emote_sets.iter().any(|set| &set.id == *id))
+    {
+        log::info!("Skipping the cache cleanup, emote set {missing} is not loaded");
+        return;
+    }
+    let custom = EmoteSet::custom(&settings.custom_emotes);
+    let keep = emote_sets
+        .iter()
+        .chain(std::iter::once(&custom))
+        .flat_map(|set| &set.emotes)
+        .map(|emote| file_name(emote.url.as_str()))
+        .collect::<HashSet<_>>();
+    let max_age = Duration::from_secs(settings.cache_max_age_days as u64 * 24 * 60 * 60);
+    let (frames, frame_bytes) = frame_cache::prune(&frame_cache_dir(), &keep, max_age);
+    let (files, file_bytes) = frame_cache::prune(&file_cache_dir(), &keep, max_age);
+    log::info!(
+        "Removed {} orphaned cache files, {} bytes",
+        frames + files,
+        frame_bytes + file_bytes
+    );
+    reclaimed(frames + files, frame_bytes + file_bytes);
+}
+
+/// Removes the least recently used files until both caches fit into the size limit
+pub fn shrink() {
+    let max_bytes = MAX_BYTES.load(Ordering::Relaxed);
+    if max_bytes == 0 || SHRINKING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    WRITTEN.store(0, Ordering::Relaxed);
+    let (files, bytes) = frame_cache::shrink(&[frame_cache_dir(), file_cache_dir()], max_bytes);
+    if files > 0 {
+        log::info!("Removed {files} cache files over the size limit, {bytes} bytes");
+    }
+    reclaimed(files, bytes);
+    SHRINKING.store(false, Ordering::Relaxed);
+}
+
+/// Counts a file stored in one of the caches. Runs on the download threads, so every tenth
+/// of the limit written shrinks the caches right there instead of on the worker.
+pub fn record_write(bytes: u64) {
+    let max_bytes = MAX_BYTES.load(Ordering::Relaxed);
+    if max_bytes == 0 {
+        return;
+    }
+    if WRITTEN.fetch_add(bytes, Ordering::Relaxed) + bytes >= max_bytes / 10 {
+        shrink();
+    }
+}
+
+/// One-off for caches that grew without a limit in earlier versions. Removes the temporary
+/// files crashes left behind and brings the caches down to the limit. Queued ahead of the
+/// startup downloads, but they may run next to it on the other worker threads.
+pub fn migrate() {
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    track(worker.spawn_with_priority(
+        Priority::Low,
+        Box::new(|| {
+            let before =
+                frame_cache::size(&frame_cache_dir()) + frame_cache::size(&file_cache_dir());
+            log::info!("Migrating the disk caches, {before} bytes");
+            let (frames, frame_bytes) = frame_cache::remove_temporary(&frame_cache_dir());
+            let (files, file_bytes) = frame_cache::remove_temporary(&file_cache_dir());
+            reclaimed(frames + files, frame_bytes + file_bytes);
+            shrink();
+            // the render thread owns the settings
+            handoff::send(handoff::Update::CacheMigrated);
+        }),
+    ));
+}
+
+/// Checks every cached file in the background and removes the damaged ones,
+/// their emotes get downloaded and decoded again on the next use
+pub fn verify() {
+    *VERIFIED.lock().unwrap() = Some(None);
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    let job = worker.spawn(Box::new(|| {
+        let mut checked = 0;
+        let mut removed = 0;
+        let caches: [(PathBuf, Verify); 2] = [
+            (frame_cache_dir(), frame_cache::verify),
+            (file_cache_dir(), file_cache::verify),
+        ];
+        for (dir, verify) in caches {
+            let entries = fs::read_dir(&dir).into_iter().flatten().flatten();
+            for entry in entries {
+                let path = entry.path();
+                // still being written
+                if path.extension().is_some_and(|ext| ext == "tmp") {
+                    continue;
+                }
+                checked += 1;
+                if verify(&path) {
+                    continue;
+                }
+                log::warn!("Removing damaged cache file {}", path.display());
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += 1,
+                    Err(e) => log::error!("Failed to remove {}: {e}", path.display()),
+                }
+            }
+        }
+        log::info!("Verified {checked} cache files, removed {removed}");
+        *VERIFIED.lock().unwrap() = Some(Some((checked, removed)));
+    }));
+    // cancelled on unload before it started
+    job.on_finish(|ran| {
+        if !ran {
+            VERIFIED.lock().unwrap().take();
+        }
+    });
+    track(job);
+}
+
+pub fn status_ui(ui: &Ui) {
+    match *VERIFIED.lock().unwrap() {
+        Some(Some((checked, removed))) => ui.text_disabled(format!(
+            "{}: {checked} {}, {removed} {}",
+            e("Verified"),
+            e("files"),
+            e("damaged")
+        )),
+        Some(None) => ui.text_disabled(e("Verifying the cache...")),
+        None => {}
+    }
+    let Some((files, bytes)) = *RECLAIMED.lock().unwrap() else {
+        return;
+    };
+    ui.text_disabled(format!(
+        "{}: {files} {}, {:.1}MiB",
+        e("Cleaned up this session"),
+        e("files"),
+        bytes as f32 / (1024.0 * 1024.0)
+    ));
+}
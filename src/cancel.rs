@@ -0,0 +1,102 @@
+//! Cancellation of background downloads whose result is no longer wanted,
+//! e.g. the emotes of a set removed while they are still downloading
+use crate::background::JobHandle;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Emote sets being downloaded, keyed by set id
+pub static SET_DOWNLOADS: Tokens = Tokens::new();
+/// Emotes being downloaded and decoded, keyed by texture identifier
+pub static EMOTE_DOWNLOADS: Tokens = Tokens::new();
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Running downloads with their token and, once attached, the worker job running them
+pub struct Tokens(Mutex<Vec<(String, CancelToken, Option<JobHandle>)>>);
+
+impl Tokens {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    /// Token for a new download of `key`, cancels a previous one
+    pub fn issue(&self, key: &str) -> CancelToken {
+        let token = CancelToken::default();
+        let mut tokens = self.0.lock().unwrap();
+        if let Some((_, previous, job)) = tokens.iter_mut().find(|(k, _, _)| k == key) {
+            previous.0.store(true, Ordering::Relaxed);
+            *previous = token.clone();
+            if let Some(job) = job.take() {
+                job.cancel();
+            }
+        } else {
+            tokens.push((key.to_string(), token.clone(), None));
+        }
+        token
+    }
+
+    /// Token for a new download of `key`, `None` while another one runs, e.g. a preload that
+    /// must not cancel the download of an emote on screen
+    pub fn issue_if_idle(&self, key: &str) -> Option<CancelToken> {
+        let mut tokens = self.0.lock().unwrap();
+        if tokens.iter().any(|(k, _, _)| k == key) {
+            return None;
+        }
+        let token = CancelToken::default();
+        tokens.push((key.to_string(), token.clone(), None));
+        Some(token)
+    }
+
+    /// Remembers the job of the download, cancelling skips it if it didn't start yet
+    pub fn attach(&self, key: &str, token: &CancelToken, job: JobHandle) {
+        let mut tokens = self.0.lock().unwrap();
+        match tokens
+            .iter_mut()
+            .find(|(k, t, _)| k == key && Arc::ptr_eq(&t.0, &token.0))
+        {
+            Some((_, _, attached)) => *attached = Some(job),
+            // cancelled before the job was spawned
+            None => {
+                job.cancel();
+            }
+        }
+    }
+
+    /// Returns true if a download of `key` was running
+    pub fn cancel(&self, key: &str) -> bool {
+        let mut tokens = self.0.lock().unwrap();
+        let Some(i) = tokens.iter().position(|(k, _, _)| k == key) else {
+            return false;
+        };
+        let (_, token, job) = tokens.swap_remove(i);
+        token.0.store(true, Ordering::Relaxed);
+        if let Some(job) = job {
+            job.cancel();
+        }
+        true
+    }
+
+    /// Forgets the token once its download is done
+    pub fn finish(&self, key: &str, token: &CancelToken) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|(k, t, _)| k != key || !Arc::ptr_eq(&t.0, &token.0));
+    }
+
+    pub fn clear(&self) {
+        for (_, token, job) in self.0.lock().unwrap().drain(..) {
+            token.0.store(true, Ordering::Relaxed);
+            if let Some(job) = job {
+                job.cancel();
+            }
+        }
+    }
+}
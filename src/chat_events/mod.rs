@@ -243,6 +243,22 @@ impl MessageSource {
             Self::Emote { .. } | Self::EmoteCustom { .. } => None,
         }
     }
+
+    pub fn generic(&self) -> Option<&GenericMessage> {
+        match self {
+            Self::Guild { message, .. } | Self::TeamWvW { message, .. } => Some(message),
+            Self::Local(message)
+            | Self::Map(message)
+            | Self::Party(message)
+            | Self::Squad(message)
+            | Self::TeamPvP(message)
+            | Self::Whisper(message) => Some(message),
+            Self::GuildMotD { .. }
+            | Self::SquadMessage(_)
+            | Self::Emote { .. }
+            | Self::EmoteCustom { .. } => None,
+        }
+    }
 }
 
 // TODO: flags
@@ -255,6 +271,20 @@ impl Message {
     pub fn content(&self) -> Option<&str> {
         self.source.content()
     }
+
+    pub fn account_name(&self) -> Option<&str> {
+        self.source.generic()?.account_name.as_deref()
+    }
+
+    /// Account name of the sender, falls back to the character name
+    pub fn author(&self) -> Option<&str> {
+        let message = self.source.generic()?;
+        message
+            .account_name
+            .as_deref()
+            .or(Some(message.character_name.as_str()))
+            .filter(|author| !author.is_empty())
+    }
 }
 
 impl TryFrom<RawMessage> for Message {
@@ -0,0 +1,91 @@
+//! Emote names that are also everyday chat words, like `ok` or `gg`. Every normal sentence
+//! containing them spawns the emote, so the options suggest an alias instead.
+use crate::provider::EmoteSet;
+use crate::settings::ChatLanguage;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+
+const ENGLISH: &str = include_str!("wordlists/en.txt");
+const GERMAN: &str = include_str!("wordlists/de.txt");
+const FRENCH: &str = include_str!("wordlists/fr.txt");
+const SPANISH: &str = include_str!("wordlists/es.txt");
+
+/// Collisions of the last build, rebuilt when the emote sets or the language change
+static INDEX: Mutex<Option<Index>> = const { Mutex::new(None) };
+
+struct Index {
+    key: u64,
+    collisions: Vec<Collision>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Collision {
+    /// Emote name, as written in its set
+    pub name: String,
+    /// Name of the first set containing the emote
+    pub set: String,
+}
+
+impl ChatLanguage {
+    fn words(self) -> HashSet<&'static str> {
+        let list = match self {
+            ChatLanguage::English => ENGLISH,
+            ChatLanguage::German => GERMAN,
+            ChatLanguage::French => FRENCH,
+            ChatLanguage::Spanish => SPANISH,
+        };
+        list.lines()
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .collect()
+    }
+}
+
+/// Emote names of `emote_sets` that are common words in `language`, ignoring case
+pub fn find(emote_sets: &[&EmoteSet], language: ChatLanguage) -> Vec<Collision> {
+    let mut hasher = DefaultHasher::new();
+    language.hash(&mut hasher);
+    for set in emote_sets {
+        set.id.hash(&mut hasher);
+        set.emotes.len().hash(&mut hasher);
+    }
+    let key = hasher.finish();
+    let mut index = INDEX.lock().unwrap();
+    if let Some(index) = index.as_ref().filter(|index| index.key == key) {
+        return index.collisions.clone();
+    }
+    let words = language.words();
+    let mut collisions = Vec::<Collision>::new();
+    for set in emote_sets {
+        for emote in &set.emotes {
+            if collisions.iter().any(|c| c.name == emote.name)
+                || !words.contains(emote.name.to_lowercase().as_str())
+            {
+                continue;
+            }
+            collisions.push(Collision {
+                name: emote.name.clone(),
+                set: set.name.clone(),
+            });
+        }
+    }
+    log::info!(
+        "Found {} emote names that are common {language:?} words",
+        collisions.len()
+    );
+    *index = Some(Index {
+        key,
+        collisions: collisions.clone(),
+    });
+    collisions
+}
+
+/// Alias suggested for a colliding emote, not a word anyone writes by accident
+pub fn suggested_alias(name: &str) -> String {
+    format!(":{name}:")
+}
+
+pub fn clear() {
+    INDEX.lock().unwrap().take();
+}
@@ -0,0 +1,206 @@
+//! Chat commands to share emote sets with other players running the addon,
+//! e.g. `!emoteset add <id>`, `!emoteset sync <id>...` from the commander
+//! or `!emoteset personal <user id>` to link the personal emotes.
+//! Guilds can offer their emote set with `7tv:<id>` in the message of the day
+use crate::chat_events::{Message, MessageSource};
+use crate::settings::Settings;
+use crate::util::e;
+use crate::{fetch_emote_set, filter, personal, privacy, setting_path, squad};
+use nexus::alert::send_alert;
+use nexus::imgui::{Ui, Window};
+use std::sync::Mutex;
+
+/// Emote sets shared in chat, waiting for confirmation
+static PENDING: Mutex<Vec<SharedSet>> = const { Mutex::new(Vec::new()) };
+
+struct SharedSet {
+    origin: Origin,
+    id: String,
+}
+
+enum Origin {
+    Player(String),
+    /// Index of the guild whose message of the day named the set
+    Guild(u32),
+}
+
+impl Origin {
+    fn label(&self) -> String {
+        match self {
+            Origin::Player(author) => privacy::display_name(author),
+            Origin::Guild(index) => format!("{} {}", e("Guild"), index + 1),
+        }
+    }
+}
+
+enum Command<'a> {
+    AddEmoteSet(&'a str),
+    /// Emote sets of the commander, adopted for the duration of the squad
+    SyncEmoteSets(Vec<&'a str>),
+    /// Links the sender to their 7tv user for personal emotes
+    Personal(&'a str),
+}
+
+/// Only 7tv ids, everything else could point to a local file
+pub fn is_valid_id(id: &str) -> bool {
+    id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Chat command to share the given emote sets with the squad
+pub fn sync_command(ids: &[String]) -> String {
+    let ids = ids
+        .iter()
+        .filter(|id| is_valid_id(id))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    format!("!emoteset sync {}", ids.join(" "))
+}
+
+fn parse(content: &str) -> Option<Command<'_>> {
+    let mut words = content.split_whitespace();
+    if words.next()? != "!emoteset" {
+        return None;
+    }
+    match (words.next()?, words.collect::<Vec<_>>()) {
+        ("add", ids) if ids.len() == 1 && is_valid_id(ids[0]) => Some(Command::AddEmoteSet(ids[0])),
+        ("personal", ids) if ids.len() == 1 && is_valid_id(ids[0]) => {
+            Some(Command::Personal(ids[0]))
+        }
+        ("sync", ids) if !ids.is_empty() && ids.iter().all(|id| is_valid_id(id)) => {
+            Some(Command::SyncEmoteSets(ids))
+        }
+        _ => None,
+    }
+}
+
+/// Handles chat commands, returns true if the message was a command
+pub fn handle(chat: &Message, content: &str, settings: &Settings) -> bool {
+    let Some(command) = parse(content) else {
+        return false;
+    };
+    match command {
+        Command::AddEmoteSet(id) => {
+            // own shares are for the others, we already have the set
+            if filter::is_own_message(chat) {
+                log::info!("Shared emote set {id}");
+                send_alert(format!("{} {id}", e("Shared the emote set")));
+                return true;
+            }
+            // anyone could spam the prompt from map chat, only groups we joined can share
+            let from_group = matches!(
+                chat.source,
+                MessageSource::Squad(_) | MessageSource::Party(_) | MessageSource::Guild { .. }
+            );
+            if !settings.accept_shared_sets
+                || !from_group
+                || settings.emote_set_ids.iter().any(|i| i == id)
+            {
+                return true;
+            }
+            let mut pending = PENDING.lock().unwrap();
+            if !pending.iter().any(|p| p.id == id) {
+                log::info!("Emote set {id} was shared in chat");
+                pending.push(SharedSet {
+                    origin: Origin::Player(chat.author().unwrap_or_default().to_string()),
+                    id: id.to_string(),
+                });
+            }
+        }
+        Command::SyncEmoteSets(ids) => {
+            let from_commander = matches!(chat.source, MessageSource::Squad(_))
+                && chat.account_name().is_some_and(squad::is_commander);
+            if settings.squad_sync && from_commander && !filter::is_own_message(chat) {
+                squad::adopt(&ids, &settings.emote_set_ids);
+            }
+        }
+        Command::Personal(user_id) => {
+            if settings.personal_emotes
+                && let Some(account_name) = chat.account_name()
+            {
+                personal::link(account_name, user_id);
+            }
+        }
+    }
+    true
+}
+
+/// Offers the emote sets a guild named in its message of the day, see [`crate::rules`]
+pub fn offer_guild_sets(ids: &[String], guild_index: u32, settings: &Settings) {
+    let mut pending = PENDING.lock().unwrap();
+    for id in ids {
+        if settings.emote_set_ids.iter().any(|i| i == id)
+            || settings.ignored_guild_sets.iter().any(|i| i == id)
+            || pending.iter().any(|p| p.id == *id)
+        {
+            continue;
+        }
+        log::info!("Guild {guild_index} offers the emote set {id}");
+        pending.push(SharedSet {
+            origin: Origin::Guild(guild_index),
+            id: id.to_string(),
+        });
+    }
+}
+
+/// Confirmation prompt for emote sets shared in chat
+pub fn render(ui: &Ui) {
+    let mut pending = PENDING.lock().unwrap();
+    if pending.is_empty() {
+        return;
+    }
+    let mut accepted = None;
+    let mut dismissed = None;
+    Window::new(e("Shared Emote Sets"))
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(ui, || {
+            for (i, shared) in pending.iter().enumerate() {
+                ui.text(format!(
+                    "{} {} {}",
+                    shared.origin.label(),
+                    e("shared the emote set"),
+                    shared.id
+                ));
+                ui.same_line();
+                if ui.button(e("Add") + &format!("##sharedsetadd{i}")) {
+                    accepted = Some(i);
+                }
+                ui.same_line();
+                if ui.button(e("Ignore") + &format!("##sharedsetignore{i}")) {
+                    dismissed = Some(i);
+                }
+            }
+        });
+    let dismissed = dismissed.map(|i| pending.remove(i));
+    let accepted = accepted.map(|i| pending.remove(i));
+    drop(pending);
+    // guilds repeat their message of the day on every login, remember the ignored sets
+    if let Some(SharedSet {
+        origin: Origin::Guild(_),
+        id,
+    }) = dismissed
+    {
+        let mut settings = Settings::get();
+        settings.ignored_guild_sets.push(id);
+        if let Err(e) = settings.save(&setting_path()) {
+            log::error!("Failed to save settings: {e}");
+        }
+    }
+    let Some(shared) = accepted else {
+        return;
+    };
+    let mut settings = Settings::get();
+    if settings.emote_set_ids.contains(&shared.id) {
+        return;
+    }
+    settings.emote_set_ids.push(shared.id.clone());
+    if let Err(e) = settings.save(&setting_path()) {
+        log::error!("Failed to save settings: {e}");
+    }
+    drop(settings);
+    fetch_emote_set(shared.id);
+}
+
+pub fn clear() {
+    PENDING.lock().unwrap().clear();
+}
@@ -0,0 +1,69 @@
+//! Secrets in the Windows credential manager, so they never end up in the settings file
+use anyhow::Result;
+use windows::Win32::Foundation::ERROR_NOT_FOUND;
+use windows::Win32::Security::Credentials::{
+    CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC, CREDENTIALW, CredDeleteW, CredFree, CredReadW,
+    CredWriteW,
+};
+use windows::core::{HSTRING, PWSTR};
+
+fn target(name: &str) -> String {
+    format!("nexus-emotes/{name}")
+}
+
+fn is_not_found(error: &windows::core::Error) -> bool {
+    error.code() == ERROR_NOT_FOUND.to_hresult()
+}
+
+/// Secret stored under `name`, `None` if there is none
+pub fn read(name: &str) -> Result<Option<String>> {
+    let mut credential = std::ptr::null_mut::<CREDENTIALW>();
+    match unsafe {
+        CredReadW(
+            &HSTRING::from(target(name)),
+            CRED_TYPE_GENERIC,
+            None,
+            &mut credential,
+        )
+    } {
+        Ok(()) => {}
+        Err(e) if is_not_found(&e) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    // SAFETY: a successful read returns a valid credential that we free after copying the blob
+    let secret = unsafe {
+        let blob = std::slice::from_raw_parts(
+            (*credential).CredentialBlob,
+            (*credential).CredentialBlobSize as usize,
+        );
+        let secret = String::from_utf8_lossy(blob).into_owned();
+        CredFree(credential.cast());
+        secret
+    };
+    Ok(Some(secret))
+}
+
+/// Stores `secret` under `name`, an empty secret removes it
+pub fn write(name: &str, secret: &str) -> Result<()> {
+    if secret.is_empty() {
+        return match unsafe { CredDeleteW(&HSTRING::from(target(name)), CRED_TYPE_GENERIC, None) } {
+            Err(e) if !is_not_found(&e) => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let mut target_name = target(name)
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+    let credential = CREDENTIALW {
+        Type: CRED_TYPE_GENERIC,
+        TargetName: PWSTR(target_name.as_mut_ptr()),
+        CredentialBlobSize: secret.len() as u32,
+        // only read by CredWriteW
+        CredentialBlob: secret.as_ptr().cast_mut(),
+        Persist: CRED_PERSIST_LOCAL_MACHINE,
+        ..Default::default()
+    };
+    unsafe { CredWriteW(&credential, 0) }?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::giftex::Gif;
+use crate::util::e;
+use crate::{ACTIVE_EMOTES, LOADED_EMOTES, SPAWN_QUEUE, idle, setting_dir, source_compare};
+use nexus::AddonApi;
+use nexus::imgui::{TreeNodeFlags, Ui};
+use std::cell::RefCell;
+
+pub fn render(ui: &Ui, dev_mode: bool) {
+    thread_local! {
+        static SELECTED: RefCell<usize> = const { RefCell::new(0) };
+    }
+    if !ui.collapsing_header(e("Diagnostics"), TreeNodeFlags::empty()) {
+        return;
+    }
+    ui.text(format!(
+        "{}: {}",
+        e("State"),
+        if idle::is_idle() {
+            e("Idle")
+        } else {
+            e("Active")
+        }
+    ));
+    ui.text(format!(
+        "{}: {}",
+        e("Active emotes"),
+        ACTIVE_EMOTES.lock().unwrap().len()
+    ));
+    let queue = SPAWN_QUEUE.lock().unwrap();
+    if !queue.is_empty() {
+        ui.text(format!("{}: {}", e("Queued emotes"), queue.len()));
+    }
+    drop(queue);
+    source_compare::render(ui);
+    let loaded = LOADED_EMOTES.lock().unwrap();
+    let emotes = loaded
+        .iter()
+        .filter_map(|(identifier, gif)| Some((identifier.as_str(), gif.as_ref()?)))
+        .collect::<Vec<_>>();
+    if emotes.is_empty() {
+        ui.text_disabled(e("No emotes loaded yet"));
+        return;
+    }
+    SELECTED.with_borrow_mut(|selected| {
+        if *selected >= emotes.len() {
+            *selected = 0;
+        }
+        let identifiers = emotes.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        ui.combo_simple_string(e("Loaded Emote"), selected, &identifiers);
+        let (identifier, gif) = emotes[*selected];
+        frame_info(ui, gif);
+        if dev_mode && ui.button(e("Dump frames to disk")) {
+            dump_frames(identifier, gif);
+        }
+    });
+}
+
+fn frame_info(ui: &Ui, gif: &Gif) {
+    let total: f32 = gif.frames.iter().map(|f| f.delay).sum();
+    ui.text(format!("{}: {}x{}", e("Size"), gif.width, gif.height));
+    ui.text(format!("{}: {}", e("Frames"), gif.frames.len()));
+    ui.text(format!("{}: {:?}", e("Format"), gif.format));
+    if gif.stream.is_some() {
+        ui.text(e("Streamed into a single texture"));
+    } else {
+        ui.text(format!("{}: {}", e("Atlas textures"), gif.pages));
+    }
+    ui.text(format!("{}: {total:.0}ms", e("Total duration")));
+    ui.text(format!(
+        "{}: {:.2}MiB",
+        e("Memory"),
+        gif.vram_bytes() as f32 / (1024.0 * 1024.0)
+    ));
+    let delays = gif
+        .frames
+        .iter()
+        .map(|f| format!("{:.0}", f.delay))
+        .collect::<Vec<_>>()
+        .join(", ");
+    ui.text_wrapped(format!("{} (ms): {delays}", e("Frame delays")));
+}
+
+fn dump_frames(identifier: &str, gif: &Gif) {
+    let Some(device) = AddonApi::get().get_d3d11_device() else {
+        log::error!("No d3d11 device to read back {identifier}");
+        return;
+    };
+    let dir = setting_dir().join("dumps").join(identifier);
+    if let Err(e) = crate::giftex::dump_frames(&device, gif, &dir) {
+        log::error!("Failed to dump frames of {identifier}: {e}");
+    }
+}
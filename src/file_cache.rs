@@ -0,0 +1,91 @@
+//! Downloaded emote files on disk, so emotes load without the cdn on the next session.
+//! Like the frame cache, files are named after a hash of the url and start with the url itself,
+//! followed by a hash of the file so damaged entries are noticed and downloaded again.
+use crate::frame_cache::{self, stable_hash};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"NEDL";
+/// Bump when the layout below changes
+const VERSION: u32 = 2;
+
+fn path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(frame_cache::file_name(url))
+}
+
+/// Whether `bytes` are an entry of another cache version, those are replaced without a warning
+fn is_other_version(bytes: &[u8]) -> bool {
+    bytes
+        .strip_prefix(MAGIC)
+        .and_then(|rest| rest.first_chunk::<4>())
+        .is_some_and(|version| u32::from_le_bytes(*version) != VERSION)
+}
+
+/// Url and file of a cache entry, `None` if the entry is damaged or of an older version
+fn parse(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = bytes.strip_prefix(MAGIC)?;
+    let (version, rest) = rest.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*version) != VERSION {
+        return None;
+    }
+    let (len, rest) = rest.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    let url = rest.get(..len)?;
+    let (hash, file) = rest[len..].split_first_chunk::<8>()?;
+    (u64::from_le_bytes(*hash) == stable_hash(file)).then_some((url, file))
+}
+
+/// File previously downloaded from `url`, `None` on a miss.
+/// Damaged and outdated entries are removed, so the next download replaces them
+pub fn load(dir: &Path, url: &str) -> Option<Vec<u8>> {
+    let path = path(dir, url);
+    let bytes = fs::read(&path).ok()?;
+    let Some((cached_url, file)) = parse(&bytes) else {
+        if !is_other_version(&bytes) {
+            log::warn!("Discarding damaged file cache of {url}");
+        }
+        remove(dir, url);
+        return None;
+    };
+    // another url with the same hash, not damaged
+    if cached_url != url.as_bytes() {
+        return None;
+    }
+    frame_cache::touch(&path);
+    Some(file.to_vec())
+}
+
+/// Whether a file of `url` is cached, without reading it
+pub fn contains(dir: &Path, url: &str) -> bool {
+    path(dir, url).exists()
+}
+
+/// Writes a downloaded file, goes through a temporary file so a crash can't leave half a file
+pub fn store(dir: &Path, url: &str, file: &[u8]) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = path(dir, url);
+    let tmp = path.with_extension("tmp");
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 16 + url.len() + file.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(url.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(url.as_bytes());
+    bytes.extend_from_slice(&stable_hash(file).to_le_bytes());
+    bytes.extend_from_slice(file);
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+pub fn remove(dir: &Path, url: &str) {
+    if let Err(e) = fs::remove_file(path(dir, url))
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        log::error!("Failed to remove the cached file of {url}: {e}");
+    }
+}
+
+/// Whether the cache file at `path` is intact
+pub fn verify(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|bytes| parse(&bytes).is_some())
+}
@@ -0,0 +1,87 @@
+use crate::chat_events::Message;
+use crate::settings::Settings;
+use crate::{privacy, squad};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use time::UtcDateTime;
+
+/// Recently seen (author, content) hashes with the time they were first seen
+static RECENT_MESSAGES: Mutex<Vec<(u64, Instant)>> = const { Mutex::new(Vec::new()) };
+/// Account name of the local player, reported by arcdps
+static SELF_ACCOUNT: Mutex<Option<String>> = const { Mutex::new(None) };
+
+pub fn set_self_account(account_name: &str) {
+    log::info!("Local account is {}", privacy::display_name(account_name));
+    *SELF_ACCOUNT.lock().unwrap() = Some(normalize_account(account_name).to_string());
+}
+
+pub fn self_account() -> Option<String> {
+    SELF_ACCOUNT.lock().unwrap().clone()
+}
+
+/// arcdps prefixes account names with a colon, the chat sources don't necessarily
+pub fn normalize_account(account_name: &str) -> &str {
+    account_name.trim_start_matches(':')
+}
+
+pub fn is_own_message(chat: &Message) -> bool {
+    let Some(account_name) = chat.account_name() else {
+        return false;
+    };
+    SELF_ACCOUNT
+        .lock()
+        .unwrap()
+        .as_deref()
+        .is_some_and(|own| own == normalize_account(account_name))
+}
+
+/// Decides if emotes of a message should be spawned at all
+pub fn should_process(chat: &Message, content: &str, settings: &Settings) -> bool {
+    if settings.max_message_age_secs > 0 {
+        let age = UtcDateTime::now() - chat.timestamp;
+        if age > time::Duration::seconds(settings.max_message_age_secs as i64) {
+            log::debug!("Dropping message that is {age} old");
+            return false;
+        }
+    }
+    if settings.ignore_own_messages && is_own_message(chat) {
+        return false;
+    }
+    if settings.min_group_size > 0 && squad::size() < settings.min_group_size as usize {
+        return false;
+    }
+    if settings.only_when_commanding && !self_account().is_some_and(|own| squad::is_commander(&own))
+    {
+        return false;
+    }
+    if settings.duplicate_window_secs > 0
+        && is_duplicate(
+            chat.author().unwrap_or_default(),
+            content,
+            Duration::from_secs(settings.duplicate_window_secs as u64),
+        )
+    {
+        log::debug!("Dropping duplicate message");
+        return false;
+    }
+    true
+}
+
+fn is_duplicate(author: &str, content: &str, window: Duration) -> bool {
+    let mut hasher = DefaultHasher::new();
+    (author, content).hash(&mut hasher);
+    let hash = hasher.finish();
+    let mut recent = RECENT_MESSAGES.lock().unwrap();
+    recent.retain(|(_, seen)| seen.elapsed() < window);
+    if recent.iter().any(|(h, _)| *h == hash) {
+        return true;
+    }
+    recent.push((hash, Instant::now()));
+    false
+}
+
+pub fn clear() {
+    RECENT_MESSAGES.lock().unwrap().clear();
+}
@@ -0,0 +1,317 @@
+//! Decoded frames of animated emotes on disk, so big gifs only get decoded once.
+//! Files are named after a hash of the source url and contain the url itself,
+//! a different url or cache version simply counts as a miss and gets overwritten.
+use crate::giftex::{PixelFormat, RawGif};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const MAGIC: &[u8; 4] = b"NEFC";
+/// Bump when the layout below changes
+const VERSION: u32 = 2;
+/// Larger than any frame, a damaged length must not allocate gigabytes
+const MAX_CHUNK: usize = 256 * 1024 * 1024;
+
+/// FNV-1a, unlike `DefaultHasher` it stays the same across builds
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Name of the cache file of `url`, the same in both caches
+pub fn file_name(url: &str) -> String {
+    format!("{:016x}.bin", stable_hash(url.as_bytes()))
+}
+
+fn path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(file_name(url))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_bytes(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    if len > MAX_CHUNK {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Marks a cache file as used, the size limit removes the least recently used files first
+pub fn touch(path: &Path) {
+    if let Err(e) = File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+    {
+        log::trace!("Failed to touch {}: {e}", path.display());
+    }
+}
+
+/// Frames previously stored for `url`, `None` on a miss.
+/// Damaged entries are removed, so the next decode replaces them
+pub fn load(dir: &Path, url: &str) -> Option<RawGif> {
+    let path = path(dir, url);
+    let file = File::open(&path).ok()?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).ok()?;
+    if &magic != MAGIC || read_u32(&mut reader).ok()? != VERSION {
+        return None;
+    }
+    // another url with the same hash, not damaged
+    if read_bytes(&mut reader).ok()? != url.as_bytes() {
+        return None;
+    }
+    let Some(gif) = read_frames(&mut reader) else {
+        log::warn!("Discarding damaged frame cache of {url}");
+        drop(reader);
+        if let Err(e) = fs::remove_file(&path) {
+            log::error!("Failed to remove {}: {e}", path.display());
+        }
+        return None;
+    };
+    touch(&path);
+    Some(gif)
+}
+
+/// Whether the cache file at `path` is intact, entries of older versions count as damaged
+pub fn verify(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).is_ok()
+        && &magic == MAGIC
+        && read_u32(&mut reader).is_ok_and(|version| version == VERSION)
+        && read_bytes(&mut reader).is_ok()
+        && read_frames(&mut reader).is_some()
+}
+
+/// The part after the url, `None` if it is cut off or the frames don't match the size
+fn read_frames(reader: &mut impl Read) -> Option<RawGif> {
+    let width = read_u32(reader).ok()?;
+    let height = read_u32(reader).ok()?;
+    // 0 loops forever
+    let plays = Some(read_u32(reader).ok()?).filter(|plays| *plays > 0);
+    let count = read_u32(reader).ok()?;
+    // a damaged size must neither overflow nor allocate more than a frame can take
+    let frame_bytes = (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .and_then(|bytes| usize::try_from(bytes).ok())
+        .filter(|bytes| *bytes <= MAX_CHUNK)?;
+    let frames = (0..count)
+        .map(|_| {
+            let delay = f32::from_bits(read_u32(reader)?);
+            if read_u32(reader)? as usize != frame_bytes {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            let mut data = vec![0; frame_bytes];
+            reader.read_exact(&mut data)?;
+            Ok((data, delay))
+        })
+        .collect::<std::io::Result<Vec<_>>>()
+        .ok()?;
+    Some(RawGif {
+        frames,
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+        plays,
+    })
+}
+
+/// Writes the decoded frames of `url`, goes through a temporary file so a crash can't leave half a file
+pub fn store(dir: &Path, url: &str, gif: &RawGif) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        gif.format == PixelFormat::Rgba8,
+        "Only decoded frames are cached"
+    );
+    fs::create_dir_all(dir)?;
+    let path = path(dir, url);
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    write_bytes(&mut writer, url.as_bytes())?;
+    writer.write_all(&gif.width.to_le_bytes())?;
+    writer.write_all(&gif.height.to_le_bytes())?;
+    writer.write_all(&gif.plays.unwrap_or(0).to_le_bytes())?;
+    writer.write_all(&(gif.frames.len() as u32).to_le_bytes())?;
+    for (data, delay) in &gif.frames {
+        writer.write_all(&delay.to_bits().to_le_bytes())?;
+        write_bytes(&mut writer, data)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Total size of a cache directory in bytes
+pub fn size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+/// Deletes the files not in `keep` that weren't used for `max_age`.
+/// Returns the number of deleted files and their size
+pub fn prune(dir: &Path, keep: &HashSet<String>, max_age: Duration) -> (usize, u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    let mut removed = (0, 0);
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if keep.contains(&name) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        if age.is_none_or(|age| age < max_age) {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => {
+                removed.0 += 1;
+                removed.1 += metadata.len();
+            }
+            Err(e) => log::error!("Failed to remove {}: {e}", entry.path().display()),
+        }
+    }
+    removed
+}
+
+/// Deletes the least recently used files of `dirs` until they fit into `max_bytes` together.
+/// Returns the number of deleted files and their size
+pub fn shrink(dirs: &[PathBuf], max_bytes: u64) -> (usize, u64) {
+    let mut files = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    let mut total = files.iter().map(|(_, len, _)| len).sum::<u64>();
+    if total <= max_bytes {
+        return (0, 0);
+    }
+    files.sort_by_key(|(modified, _, _)| *modified);
+    let mut removed = (0, 0);
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        // files in use can't be removed on windows, the next run gets them
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                total -= len;
+                removed.0 += 1;
+                removed.1 += len;
+            }
+            Err(e) => log::error!("Failed to remove {}: {e}", path.display()),
+        }
+    }
+    removed
+}
+
+/// Removes temporary files a crash left behind while storing.
+/// Returns the number of deleted files and their size
+pub fn remove_temporary(dir: &Path) -> (usize, u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    let mut removed = (0, 0);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "tmp") {
+            continue;
+        }
+        let len = entry.metadata().map(|m| m.len()).unwrap_or_default();
+        if fs::remove_file(&path).is_ok() {
+            removed.0 += 1;
+            removed.1 += len;
+        }
+    }
+    removed
+}
+
+pub fn clear(dir: &Path) {
+    if let Err(e) = fs::remove_dir_all(dir)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        log::error!("Failed to clear {}: {e}", dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_frames;
+    use std::io::Cursor;
+
+    /// Everything after the url: size, loop count and frames of `(delay, length, data)`
+    fn entry(width: u32, height: u32, frames: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in [width, height, 0, frames.len() as u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for (length, data) in frames {
+            bytes.extend_from_slice(&100f32.to_bits().to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_intact_frames() {
+        let bytes = entry(1, 2, &[(8, &[7; 8]), (8, &[9; 8])]);
+        let gif = read_frames(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!([gif.width, gif.height], [1, 2]);
+        assert_eq!(gif.frames, vec![(vec![7; 8], 100.0), (vec![9; 8], 100.0)]);
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        let bytes = entry(u32::MAX, u32::MAX, &[(0, &[])]);
+        assert!(read_frames(&mut Cursor::new(bytes)).is_none());
+    }
+
+    #[test]
+    fn rejects_lengths_that_dont_match_the_size() {
+        let bytes = entry(1, 1, &[(u32::MAX, &[0; 4])]);
+        assert!(read_frames(&mut Cursor::new(bytes)).is_none());
+    }
+}
@@ -1,19 +1,144 @@
+use crate::cancel::CancelToken;
+use crate::net::{self, Retry};
+use crate::{cache_cleanup, file_cache, frame_cache};
+use image::codecs::webp::WebPDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, ImageDecoder, ImageFormat, RgbaImage};
+use intel_tex_2::{RgbaSurface, bc7};
 use nexus::imgui::Image;
 use nexus::imgui::TextureId;
-use nexus::imgui::Ui;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::ffi::c_void;
-use std::mem::ManuallyDrop;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
-use std::sync::Mutex;
-use std::{io::Read, time::Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use strum::{VariantArray, VariantNames};
 use windows::Win32::Graphics::Direct3D::*;
 use windows::Win32::Graphics::Direct3D11::*;
-use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
 use windows::Win32::Graphics::Dxgi::Common::DXGI_SAMPLE_DESC;
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT, DXGI_FORMAT_BC7_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM,
+};
 
+/// Mips of an atlas, each one more halves the frames and needs twice the gutter between them
+const ATLAS_MIP_LEVELS: u32 = 4;
+
+/// Layout of the frame data that gets uploaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    Rgba8,
+    /// 16 byte blocks of 4x4 pixels
+    Bc7,
+}
+
+impl PixelFormat {
+    fn dxgi(self) -> DXGI_FORMAT {
+        match self {
+            PixelFormat::Rgba8 => DXGI_FORMAT_R8G8B8A8_UNORM,
+            PixelFormat::Bc7 => DXGI_FORMAT_BC7_UNORM,
+        }
+    }
+
+    /// Texture sizes have to be a multiple of this
+    fn align(self, size: u32) -> u32 {
+        match self {
+            PixelFormat::Rgba8 => size,
+            PixelFormat::Bc7 => size.next_multiple_of(4),
+        }
+    }
+
+    /// Bytes of one row of pixels, or of one row of blocks
+    fn row_pitch(self, width: u32) -> u32 {
+        match self {
+            PixelFormat::Rgba8 => width * 4,
+            PixelFormat::Bc7 => width.div_ceil(4) * 16,
+        }
+    }
+
+    /// Rows of pixels, or rows of blocks, in `height` pixels
+    fn rows(self, height: u32) -> u32 {
+        match self {
+            PixelFormat::Rgba8 => height,
+            PixelFormat::Bc7 => height.div_ceil(4),
+        }
+    }
+
+    /// Transparent pixels after every frame of an atlas, so neither filtering nor the
+    /// smaller mips of a frame pick up its neighbours. Compressed atlases have no mips.
+    fn gutter(self) -> u32 {
+        match self {
+            PixelFormat::Rgba8 => 1 << (ATLAS_MIP_LEVELS - 1),
+            PixelFormat::Bc7 => 4,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> f32 {
+        match self {
+            PixelFormat::Rgba8 => 4.0,
+            PixelFormat::Bc7 => 1.0,
+        }
+    }
+}
+
+/// Which emotes get compressed before the upload
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Compression {
+    #[default]
+    Off,
+    /// Only static emotes, these compress in no time
+    Static,
+    /// Static emotes and every gif frame
+    All,
+}
+
+impl Compression {
+    fn applies(self, frame_count: usize) -> bool {
+        match self {
+            Compression::Off => false,
+            Compression::Static => frame_count == 1,
+            Compression::All => true,
+        }
+    }
+}
+
+/// Playback settings of animated emotes
+#[derive(Debug, Clone, Copy)]
+pub struct Playback {
+    pub mode: PerformanceMode,
+    /// Frame changes per second in `PerformanceMode::CapFps`
+    pub animation_fps: u32,
+    /// Instances of the same looping gif show the same frame instead of starting on their own
+    pub synced: bool,
+}
+
+/// How animated emotes are played, the cheaper modes are meant for slow machines
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum PerformanceMode {
+    #[default]
+    Off,
+    /// Frames change at most at the configured animation fps
+    CapFps,
+    /// Animations stay on their first frame
+    FirstFrame,
+}
+
+/// A frame is a region of an atlas texture shared by all frames of the gif,
+/// so long gifs don't need a shader resource view per frame.
 #[derive(Debug, Clone)]
 pub struct GifFrame {
     pub id: ID3D11ShaderResourceView,
+    /// Top left pixel of the frame in the atlas
+    pub offset: [u32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
     pub delay: f32,
 }
 
@@ -23,27 +148,137 @@ impl GifFrame {
             .as_ptr()
             .into()
     }
+
+    pub fn image(&self, size: [f32; 2]) -> Image {
+        Image::new(self.get_id(), size)
+            .uv0(self.uv_min)
+            .uv1(self.uv_max)
+    }
+
+    /// Texture coordinates of the corners, in the order top left, top right, bottom right, bottom left
+    pub fn uv_corners(&self) -> [[f32; 2]; 4] {
+        let [left, top] = self.uv_min;
+        let [right, bottom] = self.uv_max;
+        [[left, top], [right, top], [right, bottom], [left, bottom]]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Gif {
     pub frames: Vec<GifFrame>,
+    /// Number of atlas textures the frames are spread over
+    pub pages: usize,
+    /// Set when the frames stay in memory and are streamed into a single texture
+    pub stream: Option<Arc<FrameStream>>,
+    pub format: PixelFormat,
+    /// False while the remaining frames are still being uploaded
+    pub complete: bool,
+    /// How often the animation plays before it stops on the last frame, `None` loops forever
+    pub plays: Option<u32>,
+    /// Shared clock of synchronized playback, streamed gifs always follow it
+    pub clock: Instant,
     pub height: f32,
     pub width: f32,
 }
 
+/// Keeps the decoded frames in memory and copies the current one into a single
+/// texture, trading a copy per frame change for the vram of all other frames.
+/// Emotes share the texture, so the frame follows the clock of the gif for all of them.
+#[derive(Debug)]
+pub struct FrameStream {
+    texture: ID3D11Texture2D,
+    context: ID3D11DeviceContext,
+    frames: Vec<Vec<u8>>,
+    row_pitch: u32,
+    shown: AtomicUsize,
+}
+
+impl FrameStream {
+    fn show(&self, frame: usize) {
+        if self.shown.swap(frame, Ordering::Relaxed) == frame {
+            return;
+        }
+        unsafe {
+            self.context.UpdateSubresource(
+                &self.texture,
+                0,
+                None,
+                self.frames[frame].as_ptr() as *const _,
+                self.row_pitch,
+                0,
+            );
+        }
+    }
+}
+
 pub static TEXTURE_QUEUE: Mutex<Vec<(String, RawGif)>> = const { Mutex::new(Vec::new()) };
 
-pub fn process_queue(device: &ID3D11Device) -> anyhow::Result<Vec<(String, Gif)>> {
+/// Gifs that are partially uploaded, continued on the next render frames
+static PENDING_UPLOADS: Mutex<VecDeque<PendingUpload>> = const { Mutex::new(VecDeque::new()) };
+
+/// True while decoded gifs wait for `process_queue`
+pub fn has_uploads() -> bool {
+    !TEXTURE_QUEUE.lock().unwrap().is_empty() || !PENDING_UPLOADS.lock().unwrap().is_empty()
+}
+
+/// Uploads queued frames until `budget` is used up, at least one per call so uploads always progress.
+/// A gif is returned once its first frame is uploaded and again when it is complete.
+/// Gifs with more than `stream_above` frames are streamed, 0 uploads everything.
+/// Gifs that fail to upload are logged and dropped without holding up the others.
+pub fn process_queue(
+    device: &ID3D11Device,
+    stream_above: u32,
+    budget: Duration,
+) -> Vec<(String, Gif)> {
+    let start = Instant::now();
+    let mut uploaded = Vec::new();
+    let mut pending = PENDING_UPLOADS.lock().unwrap();
+    for (identifier, raw_gif) in TEXTURE_QUEUE.lock().unwrap().drain(..) {
+        if stream_above > 0 && raw_gif.frames.len() > stream_above as usize {
+            match stream_gif(device, raw_gif) {
+                Ok(gif) => uploaded.push((identifier, gif)),
+                Err(e) => log::error!("Failed to upload {identifier}: {e:#}"),
+            }
+        } else {
+            match PendingUpload::new(identifier.clone(), raw_gif) {
+                Ok(upload) => pending.push_back(upload),
+                Err(e) => log::error!("Failed to upload {identifier}: {e:#}"),
+            }
+        }
+    }
+    while let Some(upload) = pending.front_mut() {
+        if let Err(e) = upload.step(device) {
+            let upload = pending.pop_front().expect("Upload to exist");
+            log::error!("Failed to upload {}: {e:#}", upload.identifier);
+            continue;
+        }
+        if upload.is_done() {
+            let upload = pending.pop_front().expect("Upload to exist");
+            uploaded.push((upload.identifier.clone(), upload.gif()));
+        } else if upload.frames.len() == 1 {
+            uploaded.push((upload.identifier.clone(), upload.gif()));
+        }
+        if start.elapsed() >= budget {
+            break;
+        }
+    }
+    uploaded
+}
+
+/// Drops the queued and partial uploads of the given emotes
+pub fn discard_uploads(identifiers: &[String]) {
     TEXTURE_QUEUE
         .lock()
         .unwrap()
-        .drain(..)
-        .map(|(identifier, raw_gif)| {
-            let gif = upload_gif_to_gpu(device, raw_gif)?;
-            Ok((identifier, gif))
-        })
-        .collect()
+        .retain(|(identifier, _)| !identifiers.contains(identifier));
+    PENDING_UPLOADS
+        .lock()
+        .unwrap()
+        .retain(|upload| !identifiers.contains(&upload.identifier));
+}
+
+pub fn clear_pending_uploads() {
+    PENDING_UPLOADS.lock().unwrap().clear();
 }
 
 impl Gif {
@@ -51,74 +286,614 @@ impl Gif {
         [self.width, self.height]
     }
 
-    pub fn load(identifier: String, url: &str) -> anyhow::Result<()> {
-        let response = ureq::get(url).call()?;
-        let decoded = load_gif(response.into_body().into_reader())?;
+    /// Frame every synchronized instance shows right now
+    fn synced_frame(&self) -> usize {
+        let total: f32 = self.frames.iter().map(|f| f.delay).sum();
+        if total <= 0.0 {
+            return 0;
+        }
+        let mut t = self.clock.elapsed().as_millis() as f32 % total;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if t < frame.delay {
+                return i;
+            }
+            t -= frame.delay;
+        }
+        self.frames.len() - 1
+    }
+
+    /// Estimated video memory of the textures, atlases include their mip chain
+    pub fn vram_bytes(&self) -> usize {
+        let frame = self.width * self.height * self.format.bytes_per_pixel();
+        if self.stream.is_some() {
+            return frame as usize;
+        }
+        (self.frames.len() as f32 * frame * 4.0 / 3.0) as usize
+    }
+
+    /// Downloads, decodes and queues the frames for the upload, stops early once `cancel` is set
+    pub fn load(
+        identifier: String,
+        url: &str,
+        options: &LoadOptions,
+        cancel: &CancelToken,
+    ) -> anyhow::Result<()> {
+        if cancel.is_cancelled() {
+            return Ok(());
+        }
+        let cached = options
+            .cache_dir
+            .as_deref()
+            .and_then(|dir| frame_cache::load(dir, url));
+        let mut decoded = match cached {
+            Some(cached) => {
+                log::trace!("Loaded {identifier} from the frame cache");
+                cached
+            }
+            None => {
+                let file_cache_dir = options.file_cache_dir.as_deref();
+                let mut from_file_cache = None;
+                if let Some(dir) = file_cache_dir
+                    && let Some(bytes) = file_cache::load(dir, url)
+                {
+                    match decode(&bytes) {
+                        Ok(decoded) => from_file_cache = Some(decoded),
+                        // a cached file that doesn't decode would fail every session, fetch it again
+                        Err(e) => {
+                            log::warn!("Downloading {identifier} again, it didn't decode: {e}");
+                            file_cache::remove(dir, url);
+                        }
+                    }
+                }
+                let decoded = match from_file_cache {
+                    Some(decoded) => decoded,
+                    None => decode(&fetch(url, file_cache_dir, cancel)?)?,
+                };
+                // static images decode fast enough
+                if let Some(dir) = &options.cache_dir
+                    && decoded.frames.len() > 1
+                {
+                    match frame_cache::store(dir, url, &decoded) {
+                        Ok(()) => cache_cleanup::record_write(
+                            decoded
+                                .frames
+                                .iter()
+                                .map(|(data, _)| data.len() as u64)
+                                .sum(),
+                        ),
+                        Err(e) => log::error!("Failed to cache frames of {identifier}: {e}"),
+                    }
+                }
+                decoded
+            }
+        };
+        if options.max_dimension > 0 {
+            decoded.downscale(options.max_dimension)?;
+        }
+        if options.always_loop {
+            decoded.plays = None;
+        }
+        if options.clamp_delays {
+            decoded.clamp_delays();
+        }
+        if cancel.is_cancelled() {
+            log::info!("Dropping cancelled emote {identifier}");
+            return Ok(());
+        }
+        if options.compression.applies(decoded.frames.len()) {
+            decoded.compress();
+        }
         TEXTURE_QUEUE.lock().unwrap().push((identifier, decoded));
         Ok(())
     }
 }
 
+/// Downloads the file and stores it in the cache
+fn fetch(url: &str, cache_dir: Option<&Path>, cancel: &CancelToken) -> anyhow::Result<Vec<u8>> {
+    let bytes = Retry::default().run_cancellable(url, cancel, || {
+        net::agent().get(url).call()?.into_body().read_to_vec()
+    })?;
+    if let Some(dir) = cache_dir {
+        match file_cache::store(dir, url, &bytes) {
+            Ok(()) => cache_cleanup::record_write(bytes.len() as u64),
+            Err(e) => log::error!("Failed to cache {url}: {e}"),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Downloads a file into the file cache without decoding it
+pub fn prefetch(url: &str, cache_dir: &Path, cancel: &CancelToken) -> anyhow::Result<()> {
+    if file_cache::contains(cache_dir, url) {
+        return Ok(());
+    }
+    fetch(url, Some(cache_dir), cancel).map(drop)
+}
+
+/// How downloaded emotes are prepared for the upload
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Frames larger than this get downscaled, 0 keeps the size
+    pub max_dimension: u32,
+    pub compression: Compression,
+    /// Where decoded animations are cached, `None` disables the cache
+    pub cache_dir: Option<PathBuf>,
+    /// Where downloaded files are cached, `None` always downloads them
+    pub file_cache_dir: Option<PathBuf>,
+    /// Ignores the loop count of gifs that are meant to play only a few times
+    pub always_loop: bool,
+    /// Slows down frames with tiny delays like browsers do
+    pub clamp_delays: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct GifState {
-    pub frames: Gif,
+    /// Shared by every emote showing this gif, only the playback position is per emote
+    pub frames: Arc<Gif>,
     pub current_frame: usize,
+    /// Last time the playback position was updated
     pub timestamp: Option<Instant>,
+    /// Milliseconds spent on the current frame, carries over into the next frames
+    pub elapsed: f32,
+    /// Completed plays of the animation
+    pub plays: u32,
 }
 
 impl GifState {
-    pub fn new(frames: Gif) -> Self {
+    pub fn new(frames: Arc<Gif>) -> Self {
         Self {
             frames,
             current_frame: 0,
             timestamp: None,
+            elapsed: 0.0,
+            plays: 0,
         }
     }
-    pub fn advance(&mut self, ui: &Ui) {
-        if let Some(timestamp) = self.timestamp {
-            if timestamp.elapsed().as_millis() as f32 > self.frames.frames[self.current_frame].delay
-            {
-                self.current_frame = (self.current_frame + 1) % self.frames.frames.len();
-                self.timestamp = Some(Instant::now());
+
+    /// Frame to show with the given playback settings
+    pub fn frame(&mut self, playback: Playback) -> &GifFrame {
+        match playback.mode {
+            PerformanceMode::CapFps if playback.animation_fps > 0 => {
+                let interval = Duration::from_secs(1) / playback.animation_fps;
+                // the skipped time is caught up on the next update
+                if self.timestamp.is_some_and(|t| t.elapsed() < interval) {
+                    return &self.frames.frames[self.current_frame];
+                }
+                self.next_frame(playback.synced)
             }
-        } else {
+            PerformanceMode::FirstFrame => {
+                if let Some(stream) = &self.frames.stream {
+                    stream.show(0);
+                }
+                &self.frames.frames[0]
+            }
+            _ => self.next_frame(playback.synced),
+        }
+    }
+
+    /// Advances by the time passed since the last call, skipping frames at low frame rates.
+    /// Play-once gifs stay on their last frame, streamed ones loop as they share one clock.
+    /// Synced instances of looping gifs follow the clock of the gif.
+    fn next_frame(&mut self, synced: bool) -> &GifFrame {
+        if let Some(stream) = &self.frames.stream {
+            self.current_frame = self.frames.synced_frame();
+            stream.show(self.current_frame);
+            return &self.frames.frames[self.current_frame];
+        }
+        if synced && self.frames.plays.is_none() {
+            // keeps the fps cap working
             self.timestamp = Some(Instant::now());
+            self.current_frame = self.frames.synced_frame();
+            return &self.frames.frames[self.current_frame];
+        }
+        let now = Instant::now();
+        let Some(timestamp) = self.timestamp.replace(now) else {
+            return &self.frames.frames[self.current_frame];
+        };
+        let frames = &self.frames.frames;
+        self.current_frame = advance(
+            frames.len(),
+            |frame| frames[frame].delay,
+            self.frames.plays,
+            self.current_frame,
+            &mut self.elapsed,
+            &mut self.plays,
+            now.duration_since(timestamp).as_secs_f32() * 1000.0,
+        );
+        &self.frames.frames[self.current_frame]
+    }
+}
+
+/// Steps from `frame` over as many of the `count` frames with the given `delay` as `dt` more
+/// milliseconds cover and returns the frame it lands on. The time spent on that frame stays in `elapsed`, completed
+/// plays are counted in `plays` and stop on the last frame once `max_plays` is reached.
+fn advance(
+    count: usize,
+    delay: impl Fn(usize) -> f32,
+    max_plays: Option<u32>,
+    mut frame: usize,
+    elapsed: &mut f32,
+    plays: &mut u32,
+    dt: f32,
+) -> usize {
+    let total: f32 = (0..count).map(&delay).sum();
+    if total <= 0.0 {
+        return frame;
+    }
+    *elapsed += dt;
+    // whole loops don't change the frame of endless gifs
+    if max_plays.is_none() {
+        *elapsed %= total;
+    }
+    let finished = |frame: usize, plays: u32| {
+        frame + 1 == count && max_plays.is_some_and(|max| plays + 1 >= max)
+    };
+    while !finished(frame, *plays) && *elapsed >= delay(frame) {
+        *elapsed -= delay(frame);
+        if frame + 1 == count {
+            *plays += 1;
         }
-        Image::new(
-            self.frames.frames[self.current_frame].get_id(),
-            self.frames.size(),
-        )
-        .build(ui);
+        frame = (frame + 1) % count;
     }
+    frame
 }
 
 pub struct RawGif {
-    frames: Vec<(Vec<u8>, f32)>,
-    width: u32,
-    height: u32,
+    pub frames: Vec<(Vec<u8>, f32)>,
+    /// Visible size, compressed frames are padded to whole blocks
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    /// How often the animation plays, `None` loops forever
+    pub plays: Option<u32>,
 }
 
-fn upload_gif_to_gpu(device: &ID3D11Device, gif: RawGif) -> anyhow::Result<Gif> {
-    log::trace!("Uploading gif to gpu");
-    let now = Instant::now();
+impl RawGif {
+    /// Browsers show frames of 0 or 10ms for 100ms and nothing shorter than 20ms,
+    /// gifs authored against that would blaze through their frames otherwise
+    fn clamp_delays(&mut self) {
+        for (_, delay) in &mut self.frames {
+            *delay = match *delay {
+                d if d <= 10.0 => 100.0,
+                d => d.max(20.0),
+            };
+        }
+    }
+
+    /// Shrinks all frames so neither side exceeds `max_dimension`, keeping the aspect ratio
+    fn downscale(&mut self, max_dimension: u32) -> anyhow::Result<()> {
+        let longest = self.width.max(self.height);
+        if longest <= max_dimension {
+            return Ok(());
+        }
+        let factor = max_dimension as f32 / longest as f32;
+        let width = ((self.width as f32 * factor).round() as u32).max(1);
+        let height = ((self.height as f32 * factor).round() as u32).max(1);
+        log::trace!(
+            "Downscaling {} frames from {}x{} to {width}x{height}",
+            self.frames.len(),
+            self.width,
+            self.height
+        );
+        let now = Instant::now();
+        for (data, _) in &mut self.frames {
+            let frame = RgbaImage::from_raw(self.width, self.height, std::mem::take(data))
+                .ok_or_else(|| anyhow::anyhow!("Frame does not match the image size"))?;
+            *data = imageops::resize(&frame, width, height, FilterType::Triangle).into_raw();
+        }
+        log::trace!("Downscaling took {}us", now.elapsed().as_micros());
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Encodes all frames as bc7, padding them with transparent pixels to whole blocks
+    fn compress(&mut self) {
+        if self.format != PixelFormat::Rgba8 {
+            return;
+        }
+        let now = Instant::now();
+        let width = PixelFormat::Bc7.align(self.width);
+        let height = PixelFormat::Bc7.align(self.height);
+        let settings = bc7::alpha_basic_settings();
+        for (data, _) in &mut self.frames {
+            let mut padded = vec![0u8; (width * height * 4) as usize];
+            let row_bytes = (self.width * 4) as usize;
+            for (row, pixels) in data.chunks_exact(row_bytes).enumerate() {
+                let start = row * (width * 4) as usize;
+                padded[start..start + row_bytes].copy_from_slice(pixels);
+            }
+            let surface = RgbaSurface {
+                data: &padded,
+                width,
+                height,
+                stride: width * 4,
+            };
+            *data = bc7::compress_blocks(&settings, &surface);
+        }
+        self.format = PixelFormat::Bc7;
+        log::trace!(
+            "Compressing {} frames took {}us",
+            self.frames.len(),
+            now.elapsed().as_micros()
+        );
+    }
+}
+
+/// Where the frames of a gif go in its atlas textures.
+/// A texture array would need a shader picking the slice, imgui draws a single
+/// `TextureId` with the default shader, so the frames sit side by side in a 2D
+/// texture and are selected by their UV rectangle instead
+struct AtlasLayout {
+    format: PixelFormat,
+    cell_width: u32,
+    /// Distance between the frames, the cell and its gutter
+    stride_width: u32,
+    stride_height: u32,
+    columns: u32,
+    frames_per_page: usize,
+}
+
+impl AtlasLayout {
+    fn new(gif: &RawGif) -> anyhow::Result<Self> {
+        let max = D3D11_REQ_TEXTURE2D_U_OR_V_DIMENSION;
+        // compressed frames are padded to whole blocks
+        let cell_width = gif.format.align(gif.width);
+        let cell_height = gif.format.align(gif.height);
+        // frames start at multiples of the gutter, so a mip texel never covers two of them
+        let gutter = gif.format.gutter();
+        let stride_width = (cell_width + gutter).next_multiple_of(gutter);
+        let stride_height = (cell_height + gutter).next_multiple_of(gutter);
+        anyhow::ensure!(
+            stride_width <= max && stride_height <= max,
+            "{}x{} is too large for a texture",
+            gif.width,
+            gif.height
+        );
+        // roughly square atlases, split into pages once they'd exceed the texture size limit
+        let frame_count = gif.frames.len() as u32;
+        let columns = (frame_count as f32).sqrt().ceil() as u32;
+        let columns = columns.clamp(1, max / stride_width);
+        let rows_per_page = max / stride_height;
+        Ok(Self {
+            format: gif.format,
+            cell_width,
+            stride_width,
+            stride_height,
+            columns,
+            frames_per_page: (columns * rows_per_page) as usize,
+        })
+    }
+
+    /// Size of the atlas holding `frames` frames
+    fn page_size(&self, frames: usize) -> [u32; 2] {
+        let columns = self.columns.min(frames as u32);
+        let rows = (frames as u32).div_ceil(columns);
+        [columns * self.stride_width, rows * self.stride_height]
+    }
+
+    /// Top left pixel of the `index`th frame of a page
+    fn offset(&self, index: usize) -> [u32; 2] {
+        let index = index as u32;
+        [
+            index % self.columns * self.stride_width,
+            index / self.columns * self.stride_height,
+        ]
+    }
+
+    /// Frame `data` followed by its transparent gutter, uploading them together keeps
+    /// the gutter from holding whatever the new texture memory contained
+    fn pad(&self, data: &[u8]) -> Vec<u8> {
+        let pitch = self.format.row_pitch(self.cell_width) as usize;
+        let stride_pitch = self.format.row_pitch(self.stride_width) as usize;
+        let mut padded = vec![0; stride_pitch * self.format.rows(self.stride_height) as usize];
+        for (row, pixels) in data.chunks_exact(pitch).enumerate() {
+            padded[row * stride_pitch..][..pitch].copy_from_slice(pixels);
+        }
+        padded
+    }
+}
+
+/// A gif whose frames get uploaded over several render frames
+struct PendingUpload {
+    identifier: String,
+    gif: RawGif,
+    layout: AtlasLayout,
+    /// Texture of the page currently being filled
+    page: Option<(ID3D11Texture2D, ID3D11ShaderResourceView, [u32; 2])>,
+    pages: usize,
+    frames: Vec<GifFrame>,
+    /// Started with the first frame, the complete gif keeps the clock of the partial one
+    clock: Instant,
+}
+
+impl PendingUpload {
+    fn new(identifier: String, gif: RawGif) -> anyhow::Result<Self> {
+        Ok(Self {
+            identifier,
+            layout: AtlasLayout::new(&gif)?,
+            gif,
+            page: None,
+            pages: 0,
+            frames: Vec::new(),
+            clock: Instant::now(),
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        self.frames.len() == self.gif.frames.len()
+    }
+
+    /// Copies the next frame into its atlas page
+    fn step(&mut self, device: &ID3D11Device) -> anyhow::Result<()> {
+        let format = self.gif.format;
+        let index = self.frames.len();
+        let in_page = index % self.layout.frames_per_page;
+        if in_page == 0 {
+            let remaining = self.gif.frames.len() - index;
+            let [width, height] = self
+                .layout
+                .page_size(remaining.min(self.layout.frames_per_page));
+            let mips = format == PixelFormat::Rgba8;
+            let texture = create_texture(device, None, width, height, mips, format)?;
+            let srv = view_texture(device, &texture, format)?;
+            self.page = Some((texture, srv, [width, height]));
+            self.pages += 1;
+        }
+        let (texture, srv, [atlas_width, atlas_height]) =
+            self.page.as_ref().expect("Page to exist");
+        let [x, y] = self.layout.offset(in_page);
+        let (data, delay) = &self.gif.frames[index];
+        let region = D3D11_BOX {
+            left: x,
+            top: y,
+            front: 0,
+            right: x + self.layout.stride_width,
+            bottom: y + self.layout.stride_height,
+            back: 1,
+        };
+        let padded = self.layout.pad(data);
+        let context = unsafe { device.GetImmediateContext()? };
+        unsafe {
+            context.UpdateSubresource(
+                texture,
+                0,
+                Some(&region),
+                padded.as_ptr() as *const _,
+                format.row_pitch(self.layout.stride_width),
+                0,
+            );
+        }
+        let (atlas_width, atlas_height) = (*atlas_width as f32, *atlas_height as f32);
+        self.frames.push(GifFrame {
+            id: srv.clone(),
+            offset: [x, y],
+            uv_min: [x as f32 / atlas_width, y as f32 / atlas_height],
+            uv_max: [
+                (x + self.gif.width) as f32 / atlas_width,
+                (y + self.gif.height) as f32 / atlas_height,
+            ],
+            delay: *delay,
+        });
+        let page_full = in_page + 1 == self.layout.frames_per_page;
+        // the first frame is shown before the rest arrives, it needs its mips right away
+        if format == PixelFormat::Rgba8 && (index == 0 || page_full || self.is_done()) {
+            unsafe { context.GenerateMips(srv) };
+        }
+        Ok(())
+    }
+
+    /// The frames uploaded so far
+    fn gif(&self) -> Gif {
+        Gif {
+            frames: self.frames.clone(),
+            pages: self.pages,
+            stream: None,
+            format: self.gif.format,
+            complete: self.is_done(),
+            plays: self.gif.plays,
+            clock: self.clock,
+            width: self.gif.width as f32,
+            height: self.gif.height as f32,
+        }
+    }
+}
+
+fn stream_gif(device: &ID3D11Device, gif: RawGif) -> anyhow::Result<Gif> {
+    log::trace!("Creating streamed texture for {} frames", gif.frames.len());
+    let format = gif.format;
+    let width = format.align(gif.width);
+    let height = format.align(gif.height);
+    let texture = create_texture(device, Some(&gif.frames[0].0), width, height, false, format)?;
+    let srv = view_texture(device, &texture, format)?;
     let frames = gif
         .frames
-        .into_iter()
-        .map(|(data, delay)| {
-            let srv = create_shader_resource_view(device, &data, gif.width, gif.height)?;
-            Ok(GifFrame { id: srv, delay })
+        .iter()
+        .map(|(_, delay)| GifFrame {
+            id: srv.clone(),
+            offset: [0, 0],
+            uv_min: [0.0, 0.0],
+            uv_max: [
+                gif.width as f32 / width as f32,
+                gif.height as f32 / height as f32,
+            ],
+            delay: *delay,
         })
-        .collect::<anyhow::Result<Vec<_>>>()?;
-    log::trace!("Uploading gif to gpu took {}us", now.elapsed().as_micros());
+        .collect();
+    let stream = FrameStream {
+        texture,
+        context: unsafe { device.GetImmediateContext()? },
+        frames: gif.frames.into_iter().map(|(data, _)| data).collect(),
+        row_pitch: format.row_pitch(width),
+        shown: AtomicUsize::new(0),
+    };
     Ok(Gif {
         frames,
+        pages: 1,
+        stream: Some(Arc::new(stream)),
+        format,
+        complete: true,
+        plays: gif.plays,
+        clock: Instant::now(),
         width: gif.width as f32,
         height: gif.height as f32,
     })
 }
 
-fn size_of_member<T>(_: &Vec<T>) -> usize {
-    std::mem::size_of::<T>()
+/// Decodes animated and static images into the same frame representation,
+/// static images simply end up as a single frame.
+/// Avif is only supported with the `avif` feature.
+pub fn decode(bytes: &[u8]) -> anyhow::Result<RawGif> {
+    match image::guess_format(bytes)? {
+        ImageFormat::Gif => load_gif(bytes),
+        ImageFormat::WebP => load_webp(bytes),
+        _ => load_static(bytes),
+    }
+}
+
+pub fn load_webp(bytes: &[u8]) -> anyhow::Result<RawGif> {
+    let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+    if !decoder.has_animation() {
+        return load_static(bytes);
+    }
+    log::trace!("Decoding animated webp");
+    let now = Instant::now();
+    let (width, height) = decoder.dimensions();
+    let frames = decoder
+        .into_frames()
+        .map(|frame| {
+            let frame = frame?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay = numerator as f32 / denominator.max(1) as f32;
+            Ok((frame.into_buffer().into_raw(), delay))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    log::trace!(
+        "Decoding animated webp took {}us",
+        now.elapsed().as_micros()
+    );
+    Ok(RawGif {
+        frames,
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+        plays: None,
+    })
+}
+
+pub fn load_static(bytes: &[u8]) -> anyhow::Result<RawGif> {
+    log::trace!("Decoding static image");
+    let now = Instant::now();
+    let image = image::load_from_memory(bytes)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    log::trace!("Decoding static image took {}us", now.elapsed().as_micros());
+    Ok(RawGif {
+        frames: vec![(image.into_raw(), 0.0)],
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+        plays: None,
+    })
 }
 
 pub fn load_gif(bytes: impl Read) -> anyhow::Result<RawGif> {
@@ -129,6 +904,11 @@ pub fn load_gif(bytes: impl Read) -> anyhow::Result<RawGif> {
     gif_opts.set_color_output(gif::ColorOutput::Indexed);
 
     let decoder = gif_opts.read_info(bytes)?;
+    // the loop count of the netscape extension excludes the first play
+    let plays = match decoder.repeat() {
+        gif::Repeat::Infinite => None,
+        gif::Repeat::Finite(repeat) => Some(repeat as u32 + 1),
+    };
     let mut screen = gif_dispose::Screen::new_decoder(&decoder);
 
     let frames = decoder
@@ -136,15 +916,7 @@ pub fn load_gif(bytes: impl Read) -> anyhow::Result<RawGif> {
         .map(|frame| {
             let frame = frame?;
             screen.blit_frame(&frame)?;
-            let mut v = screen.pixels_rgba().to_contiguous_buf().0.to_vec();
-            v.shrink_to_fit();
-            let v = ManuallyDrop::new(v);
-            let ptr = v.as_ptr() as *mut u8;
-            let len = v.len() * size_of_member(&v);
-            let cap = v.capacity() * size_of_member(&v);
-            let data = unsafe { Vec::from_raw_parts(ptr, len, cap) };
-
-            Ok((data, 10.0 * frame.delay as f32))
+            Ok((rgba_bytes(&screen), 10.0 * frame.delay as f32))
         })
         .collect::<anyhow::Result<Vec<_>>>()?;
     log::trace!("Decoding gif took {}us", now.elapsed().as_micros());
@@ -152,61 +924,368 @@ pub fn load_gif(bytes: impl Read) -> anyhow::Result<RawGif> {
         frames,
         width: screen.width() as u32,
         height: screen.height() as u32,
+        format: PixelFormat::Rgba8,
+        plays,
     })
 }
 
-pub fn create_shader_resource_view(
+/// Pixels of the composed frame on `screen`, 4 bytes per pixel
+fn rgba_bytes(screen: &gif_dispose::Screen) -> Vec<u8> {
+    let (pixels, _, _) = screen.pixels_rgba().to_contiguous_buf();
+    pixels
+        .iter()
+        .flat_map(|px| [px.r, px.g, px.b, px.a])
+        .collect()
+}
+
+/// Creates an empty texture without `data`. Mipmapped textures have to be created empty
+/// and filled afterwards, initial data would be needed for every mip level otherwise.
+/// The gpu can't generate mips for compressed formats, those only get the full size.
+fn create_texture(
     device: &ID3D11Device,
-    data: &[u8],
+    data: Option<&[u8]>,
     width: u32,
     height: u32,
-) -> anyhow::Result<ID3D11ShaderResourceView> {
+    mips: bool,
+    format: PixelFormat,
+) -> anyhow::Result<ID3D11Texture2D> {
+    anyhow::ensure!(
+        !mips || data.is_none(),
+        "Mipmapped textures are filled after creation"
+    );
     // Create a texture description
     let texture_desc = D3D11_TEXTURE2D_DESC {
         Width: width,
         Height: height,
-        MipLevels: 1,
+        // the gutters of the atlas only cover a few mips
+        MipLevels: if mips {
+            ATLAS_MIP_LEVELS.min(u32::BITS - width.max(height).leading_zeros())
+        } else {
+            1
+        },
         ArraySize: 1,
-        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        Format: format.dxgi(),
         SampleDesc: DXGI_SAMPLE_DESC {
             Count: 1,
             Quality: 0,
         },
         Usage: D3D11_USAGE_DEFAULT,
-        BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+        BindFlags: if mips {
+            (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32
+        } else {
+            D3D11_BIND_SHADER_RESOURCE.0 as u32
+        },
         CPUAccessFlags: 0,
-        MiscFlags: 0,
+        MiscFlags: if mips {
+            D3D11_RESOURCE_MISC_GENERATE_MIPS.0 as u32
+        } else {
+            0
+        },
     };
 
     // Create the texture
-    let texture_data = D3D11_SUBRESOURCE_DATA {
+    let texture_data = data.map(|data| D3D11_SUBRESOURCE_DATA {
         pSysMem: data.as_ptr() as *const _,
-        SysMemPitch: (width * 4), // 4 bytes per pixel for RGBA
+        SysMemPitch: format.row_pitch(width),
         SysMemSlicePitch: 0,
-    };
+    });
 
     let mut texture: Option<ID3D11Texture2D> = None;
     unsafe {
-        device.CreateTexture2D(&texture_desc, Some(&texture_data), Some(&mut texture))?;
+        let initial_data = texture_data.as_ref().map(|data| data as *const _);
+        device.CreateTexture2D(&texture_desc, initial_data, Some(&mut texture))?;
     }
-    let texture = texture.ok_or_else(windows::core::Error::from_win32)?;
+    Ok(texture.ok_or_else(windows::core::Error::from_win32)?)
+}
 
+fn view_texture(
+    device: &ID3D11Device,
+    texture: &ID3D11Texture2D,
+    format: PixelFormat,
+) -> anyhow::Result<ID3D11ShaderResourceView> {
     // Create the shader resource view
     let mut srv: Option<ID3D11ShaderResourceView> = None;
     let srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
-        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        Format: format.dxgi(),
         ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2D,
         Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
             Texture2D: D3D11_TEX2D_SRV {
                 MostDetailedMip: 0,
-                MipLevels: 1,
+                // all levels the texture has
+                MipLevels: u32::MAX,
             },
         },
     };
 
     unsafe {
-        device.CreateShaderResourceView(&texture, Some(&srv_desc), Some(&mut srv))?;
+        device.CreateShaderResourceView(texture, Some(&srv_desc), Some(&mut srv))?;
     }
 
     Ok(srv.ok_or_else(windows::core::Error::from_win32)?)
 }
+
+/// Reads every frame of `gif` back from the gpu and writes them as png files into `dir`.
+/// Only meant for debugging color/alpha artifacts.
+pub fn dump_frames(device: &ID3D11Device, gif: &Gif, dir: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        gif.format == PixelFormat::Rgba8,
+        "Compressed frames can't be written as png"
+    );
+    std::fs::create_dir_all(dir)?;
+    let width = gif.width as u32;
+    let height = gif.height as u32;
+    if let Some(stream) = &gif.stream {
+        for (i, data) in stream.frames.iter().enumerate() {
+            image::save_buffer(
+                dir.join(format!("frame_{i:04}.png")),
+                data,
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?;
+        }
+        log::info!(
+            "Dumped {} streamed frames to {}",
+            stream.frames.len(),
+            dir.to_string_lossy()
+        );
+        return Ok(());
+    }
+    let staging_desc = D3D11_TEXTURE2D_DESC {
+        Width: width,
+        Height: height,
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Usage: D3D11_USAGE_STAGING,
+        BindFlags: 0,
+        CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+        MiscFlags: 0,
+    };
+    let mut staging: Option<ID3D11Texture2D> = None;
+    let context = unsafe {
+        device.CreateTexture2D(&staging_desc, None, Some(&mut staging))?;
+        device.GetImmediateContext()?
+    };
+    let staging = staging.ok_or_else(windows::core::Error::from_win32)?;
+
+    for (i, frame) in gif.frames.iter().enumerate() {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        unsafe {
+            let resource = frame.id.GetResource()?;
+            let [x, y] = frame.offset;
+            let region = D3D11_BOX {
+                left: x,
+                top: y,
+                front: 0,
+                right: x + width,
+                bottom: y + height,
+                back: 1,
+            };
+            context.CopySubresourceRegion(&staging, 0, 0, 0, 0, &resource, 0, Some(&region));
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            context.Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+            for row in 0..height {
+                let src = (mapped.pData as *const u8).add((row * mapped.RowPitch) as usize);
+                data.extend_from_slice(std::slice::from_raw_parts(src, (width * 4) as usize));
+            }
+            context.Unmap(&staging, 0);
+        }
+        image::save_buffer(
+            dir.join(format!("frame_{i:04}.png")),
+            &data,
+            width,
+            height,
+            image::ColorType::Rgba8,
+        )?;
+    }
+    log::info!(
+        "Dumped {} frames to {}",
+        gif.frames.len(),
+        dir.to_string_lossy()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AtlasLayout, PixelFormat, RawGif, advance, load_gif, rgba_bytes};
+    use std::borrow::Cow;
+    use std::mem::ManuallyDrop;
+
+    /// Frame, completed plays and remaining milliseconds after `dt` from the given position
+    fn step(
+        delays: &[f32],
+        max_plays: Option<u32>,
+        (frame, plays, elapsed): (usize, u32, f32),
+        dt: f32,
+    ) -> (usize, u32, f32) {
+        let (mut plays, mut elapsed) = (plays, elapsed);
+        let frame = advance(
+            delays.len(),
+            |frame| delays[frame],
+            max_plays,
+            frame,
+            &mut elapsed,
+            &mut plays,
+            dt,
+        );
+        (frame, plays, elapsed)
+    }
+
+    #[test]
+    fn carries_the_remainder_over() {
+        let delays = [100.0; 3];
+        let position = step(&delays, None, (0, 0, 0.0), 150.0);
+        assert_eq!(position, (1, 0, 50.0));
+        assert_eq!(step(&delays, None, position, 60.0), (2, 0, 10.0));
+    }
+
+    #[test]
+    fn short_delays_advance_several_frames_per_call() {
+        let delays = [10.0; 5];
+        let position = step(&delays, None, (0, 0, 0.0), 16.0);
+        assert_eq!(position, (1, 0, 6.0));
+        assert_eq!(step(&delays, None, position, 16.0), (3, 0, 2.0));
+    }
+
+    #[test]
+    fn zero_delays_are_skipped() {
+        let delays = [0.0, 100.0, 0.0, 100.0];
+        assert_eq!(step(&delays, None, (0, 0, 0.0), 0.0), (1, 0, 0.0));
+        assert_eq!(step(&delays, None, (1, 0, 0.0), 100.0), (3, 0, 0.0));
+    }
+
+    #[test]
+    fn only_zero_delays_stay_on_the_frame() {
+        assert_eq!(step(&[0.0; 3], None, (1, 0, 0.0), 500.0), (1, 0, 0.0));
+    }
+
+    #[test]
+    fn long_stall_skips_frames() {
+        let delays = [100.0; 4];
+        assert_eq!(step(&delays, None, (0, 0, 0.0), 250.0), (2, 0, 50.0));
+        // whole loops are dropped, the rest wraps around the end
+        assert_eq!(step(&delays, None, (3, 0, 0.0), 1150.0), (2, 1, 50.0));
+    }
+
+    #[test]
+    fn long_stall_stops_play_once_gifs_on_the_last_frame() {
+        let delays = [100.0; 4];
+        assert_eq!(step(&delays, Some(1), (0, 0, 0.0), 1000.0), (3, 0, 700.0));
+        assert_eq!(step(&delays, Some(2), (0, 0, 0.0), 450.0), (0, 1, 50.0));
+    }
+
+    /// How frames were converted before, reinterprets the pixel buffer in place
+    fn retagged_bytes(screen: &gif_dispose::Screen) -> Vec<u8> {
+        fn size_of_member<T>(_: &Vec<T>) -> usize {
+            std::mem::size_of::<T>()
+        }
+        let mut v = screen.pixels_rgba().to_contiguous_buf().0.to_vec();
+        v.shrink_to_fit();
+        let v = ManuallyDrop::new(v);
+        let ptr = v.as_ptr() as *mut u8;
+        let len = v.len() * size_of_member(&v);
+        let cap = v.capacity() * size_of_member(&v);
+        unsafe { Vec::from_raw_parts(ptr, len, cap) }
+    }
+
+    /// Gif of 4x4 frames given as palette indices
+    fn encode(palette: &[u8], frames: &[gif::Frame<'_>]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut bytes, 4, 4, palette).unwrap();
+        for frame in frames {
+            encoder.write_frame(frame).unwrap();
+        }
+        drop(encoder);
+        bytes
+    }
+
+    fn frame(indices: &'static [u8]) -> gif::Frame<'static> {
+        gif::Frame {
+            width: 4,
+            height: 4,
+            delay: 5,
+            buffer: Cow::Borrowed(indices),
+            ..Default::default()
+        }
+    }
+
+    /// Converts every frame of `bytes` both ways and checks `load_gif` returns the same
+    fn assert_same_output(bytes: &[u8]) {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let decoder = options.read_info(bytes).unwrap();
+        let mut screen = gif_dispose::Screen::new_decoder(&decoder);
+        let mut expected = Vec::new();
+        for frame in decoder {
+            screen.blit_frame(&frame.unwrap()).unwrap();
+            let data = rgba_bytes(&screen);
+            assert_eq!(data, retagged_bytes(&screen));
+            expected.push(data);
+        }
+        let gif = load_gif(bytes).unwrap();
+        assert_eq!(gif.frames.len(), expected.len());
+        for ((data, _), expected) in gif.frames.iter().zip(&expected) {
+            assert_eq!(data, expected);
+        }
+    }
+
+    const PALETTE: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+    const INDICES: [u8; 16] = [0, 1, 2, 3, 1, 2, 3, 0, 2, 3, 0, 1, 3, 0, 1, 2];
+
+    #[test]
+    fn paletted_frames_convert_the_same() {
+        let mut local = frame(&[3; 16]);
+        local.palette = Some(vec![10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120]);
+        assert_same_output(&encode(&PALETTE, &[frame(&INDICES), local]));
+    }
+
+    #[test]
+    fn transparent_frames_convert_the_same() {
+        let mut first = frame(&INDICES);
+        first.transparent = Some(0);
+        let mut second = frame(&[1, 1, 1, 1, 2, 2, 2, 2, 1, 1, 1, 1, 2, 2, 2, 2]);
+        second.transparent = Some(1);
+        second.dispose = gif::DisposalMethod::Keep;
+        assert_same_output(&encode(&PALETTE, &[first, second]));
+    }
+
+    #[test]
+    fn interlaced_frames_convert_the_same() {
+        let mut first = frame(&INDICES);
+        first.interlaced = true;
+        let mut second = frame(&[3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3]);
+        second.interlaced = true;
+        second.transparent = Some(2);
+        assert_same_output(&encode(&PALETTE, &[first, second]));
+    }
+
+    #[test]
+    fn atlas_frames_are_padded_with_transparent_gutters() {
+        let gif = RawGif {
+            frames: vec![(vec![255; 3 * 2 * 4], 100.0); 4],
+            width: 3,
+            height: 2,
+            format: PixelFormat::Rgba8,
+            plays: None,
+        };
+        let layout = AtlasLayout::new(&gif).unwrap();
+        // a whole gutter after the frame, rounded up to the next multiple of the gutter
+        let stride = 2 * PixelFormat::Rgba8.gutter();
+        assert_eq!([layout.stride_width, layout.stride_height], [stride; 2]);
+        assert_eq!(layout.offset(3), [stride, stride]);
+        let padded = layout.pad(&gif.frames[0].0);
+        let pitch = stride as usize * 4;
+        assert_eq!(padded.len(), pitch * stride as usize);
+        for (row, pixels) in padded.chunks_exact(pitch).enumerate() {
+            let visible = if row < 2 { 3 * 4 } else { 0 };
+            assert!(pixels[..visible].iter().all(|byte| *byte == 255));
+            assert!(pixels[visible..].iter().all(|byte| *byte == 0));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Import and export of the settings through github gists or raw paste urls
+use crate::net::{self, Retry};
+use crate::settings::{ProxyConfig, Settings};
+use crate::{WORKER, setting_path};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::sync::Mutex;
+
+const FILE_NAME: &str = "nexus-emotes.json";
+
+/// Result of the last import or export, shown in the settings
+static STATUS: Mutex<Option<Result<String, String>>> = const { Mutex::new(None) };
+/// Downloaded settings waiting to be applied on the render thread
+static IMPORTED: Mutex<Option<Settings>> = const { Mutex::new(None) };
+
+pub fn status() -> Option<Result<String, String>> {
+    STATUS.lock().unwrap().clone()
+}
+
+fn set_status(result: Result<String>) {
+    if let Err(e) = &result {
+        log::error!("Settings sync failed: {e:#}");
+    }
+    *STATUS.lock().unwrap() = Some(result.map_err(|e| format!("{e:#}")));
+}
+
+pub fn take_import() -> Option<Settings> {
+    IMPORTED.lock().unwrap().take()
+}
+
+/// Uploads the settings without the token, updates the last exported gist if there is one
+pub fn export(settings: &Settings) {
+    let token = settings.gist_token.clone();
+    let gist_id = settings.gist_id.clone();
+    let mut exported = settings.clone();
+    exported.gist_id.clear();
+    exported.sync_folder.clear();
+    exported.proxy = ProxyConfig::default();
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    worker.spawn(Box::new(move || {
+        set_status(upload(&exported, &token, &gist_id));
+    }));
+}
+
+fn upload(settings: &Settings, token: &str, gist_id: &str) -> Result<String> {
+    anyhow::ensure!(
+        !token.is_empty(),
+        "A github token with gist scope is required"
+    );
+    let body = json!({
+        "description": "Emote Chat settings",
+        "public": false,
+        "files": { FILE_NAME: { "content": serde_json::to_string_pretty(settings)? } },
+    });
+    let request = if gist_id.is_empty() {
+        net::agent().post("https://api.github.com/gists")
+    } else {
+        net::agent().patch(format!("https://api.github.com/gists/{gist_id}"))
+    };
+    let response: Value = request
+        .header("Authorization", format!("Bearer {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .send_json(body)?
+        .body_mut()
+        .read_json()?;
+    let id = response["id"]
+        .as_str()
+        .context("Gist response without id")?;
+    let mut settings = Settings::get();
+    settings.gist_id = id.to_string();
+    if let Err(e) = settings.save_quietly(&setting_path()) {
+        log::error!("Failed to save settings: {e}");
+    }
+    Ok(format!(
+        "Exported to {}",
+        response["html_url"].as_str().unwrap_or(id)
+    ))
+}
+
+/// Downloads settings from a gist url or any url serving the raw json
+pub fn import(url: String) {
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    worker.spawn(Box::new(move || {
+        let result = download(&url).map(|settings| {
+            *IMPORTED.lock().unwrap() = Some(settings);
+            format!("Imported {url}")
+        });
+        set_status(result);
+    }));
+}
+
+fn download(url: &str) -> Result<Settings> {
+    let content = match gist_id(url) {
+        Some(id) => {
+            let gist: Value = Retry::default().run(url, || {
+                net::agent()
+                    .get(format!("https://api.github.com/gists/{id}"))
+                    .call()?
+                    .body_mut()
+                    .read_json()
+            })?;
+            let files = gist["files"].as_object().context("Gist without files")?;
+            let file = files
+                .get(FILE_NAME)
+                .or_else(|| files.values().next())
+                .context("Gist without files")?;
+            file["content"]
+                .as_str()
+                .context("Gist file without content")?
+                .to_string()
+        }
+        None => Retry::default().run(url, || {
+            net::agent().get(url).call()?.body_mut().read_to_string()
+        })?,
+    };
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Id of `https://gist.github.com/<user>/<id>` urls
+fn gist_id(url: &str) -> Option<&str> {
+    let path = url
+        .trim()
+        .strip_prefix("https://gist.github.com/")?
+        .trim_end_matches('/');
+    path.rsplit('/').next().filter(|id| !id.is_empty())
+}
@@ -0,0 +1,130 @@
+//! Results of worker jobs on their way to the render thread. The jobs send them instead of
+//! locking the emote state themselves, so a long download never holds a lock the next frame
+//! waits for. The render thread applies everything sent so far once per frame.
+use crate::background::Priority;
+use crate::cancel::{self, CancelToken};
+use crate::provider::{Emote, EmoteSet};
+use crate::settings::Settings;
+use crate::{EMOTE_SETS, LOADED_EMOTES, load_emote_with_priority, progress, setting_path, squad};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Mutex, OnceLock};
+
+static CHANNEL: OnceLock<(Sender<Update>, Mutex<Receiver<Update>>)> = const { OnceLock::new() };
+
+pub enum Update {
+    /// All configured sets downloaded again, replaces the loaded ones
+    Reloaded(Vec<EmoteSet>),
+    /// Sets downloaded by the periodic refresh, merged into the loaded ones
+    Refreshed(Vec<EmoteSet>),
+    /// A newly added set, dropped if it was removed before it arrived
+    Fetched(EmoteSet, CancelToken),
+    /// Emotes to download and upload ahead of their first use
+    Preload(Vec<Emote>),
+    /// Called on the render thread with a copy of the loaded sets, hands its work back to the worker
+    Snapshot(Box<dyn FnOnce(Vec<EmoteSet>) + Send>),
+    /// The one-off cache migration finished, recorded in the settings
+    CacheMigrated,
+}
+
+fn channel_pair() -> &'static (Sender<Update>, Mutex<Receiver<Update>>) {
+    CHANNEL.get_or_init(|| {
+        let (sender, receiver) = channel();
+        (sender, Mutex::new(receiver))
+    })
+}
+
+/// Hands `update` to the render thread, never blocks
+pub fn send(update: Update) {
+    // the receiver lives in a static, sending can't fail
+    let _ = channel_pair().0.send(update);
+}
+
+/// Applies the updates sent since the last frame, runs on the render thread
+pub fn drain() {
+    let updates = channel_pair()
+        .1
+        .lock()
+        .unwrap()
+        .try_iter()
+        .collect::<Vec<_>>();
+    for update in updates {
+        apply(update);
+    }
+}
+
+/// Drops updates that didn't arrive before the unload
+pub fn clear() {
+    if let Some((_, receiver)) = CHANNEL.get() {
+        receiver.lock().unwrap().try_iter().for_each(drop);
+    }
+}
+
+/// Ids of the sets that should be loaded right now, downloads of others are outdated
+fn wanted_sets() -> Vec<String> {
+    let settings = Settings::get();
+    let mut ids = settings.emote_set_ids.clone();
+    if settings.use_global {
+        ids.push("global".to_string());
+    }
+    ids.extend(squad::temporary_sets());
+    ids
+}
+
+fn apply(update: Update) {
+    match update {
+        Update::Reloaded(mut emote_sets) => {
+            let wanted = wanted_sets();
+            // sets removed while the reload ran
+            emote_sets.retain(|set| wanted.contains(&set.id));
+            let mut current = EMOTE_SETS.lock().unwrap();
+            // sets added in the meantime were fetched on another worker thread, keep them
+            let added = current
+                .drain(..)
+                .filter(|set| {
+                    wanted.contains(&set.id) && !emote_sets.iter().any(|s| s.id == set.id)
+                })
+                .collect::<Vec<_>>();
+            emote_sets.extend(added);
+            *current = emote_sets;
+        }
+        Update::Refreshed(refreshed) => {
+            let wanted = wanted_sets();
+            let mut emote_sets = EMOTE_SETS.lock().unwrap();
+            for emote_set in refreshed.into_iter().filter(|set| wanted.contains(&set.id)) {
+                if let Some(existing) = emote_sets.iter_mut().find(|e| e.id == emote_set.id) {
+                    *existing = emote_set;
+                } else {
+                    emote_sets.push(emote_set);
+                }
+            }
+        }
+        Update::Fetched(emote_set, cancel) => {
+            let id = emote_set.id.clone();
+            // the set may have been removed after the download finished
+            if cancel.is_cancelled() {
+                log::info!("Dropping emote set {id}, it was removed during the download");
+                progress::SETS.remove(&id);
+            } else {
+                EMOTE_SETS.lock().unwrap().push(emote_set);
+            }
+            cancel::SET_DOWNLOADS.finish(&id, &cancel);
+        }
+        Update::Preload(emotes) => {
+            let mut loaded = LOADED_EMOTES.lock().unwrap();
+            for emote in &emotes {
+                load_emote_with_priority(&mut loaded, emote, Priority::Low);
+            }
+        }
+        Update::Snapshot(job) => {
+            let emote_sets = EMOTE_SETS.lock().unwrap().clone();
+            job(emote_sets);
+        }
+        Update::CacheMigrated => {
+            let mut settings = Settings::get();
+            settings.cache_migrated = true;
+            if let Err(e) = settings.save_quietly(&setting_path()) {
+                log::error!("Failed to save settings: {e}");
+            }
+        }
+    }
+}
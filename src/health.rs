@@ -0,0 +1,128 @@
+//! Checks run on load so support requests come with actionable info
+use crate::settings::{ChatMessageSource, Settings};
+use crate::util::e;
+use crate::{WORKER, net, setting_dir};
+use nexus::AddonApi;
+use nexus::imgui::{TreeNodeFlags, Ui};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CHECKS: Mutex<Vec<Check>> = const { Mutex::new(Vec::new()) };
+static UE_RECEIVED: AtomicBool = const { AtomicBool::new(false) };
+static CE_RECEIVED: AtomicBool = const { AtomicBool::new(false) };
+
+const GREEN: [f32; 4] = [0.4, 0.9, 0.4, 1.0];
+const YELLOW: [f32; 4] = [0.9, 0.8, 0.3, 1.0];
+const RED: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+struct Check {
+    name: &'static str,
+    /// `Err` holds the reason the check failed
+    result: Result<(), String>,
+    hint: &'static str,
+}
+
+/// Remembers that a chat source delivered a message, independent of the configured source
+pub fn message_received(source: ChatMessageSource) {
+    match source {
+        ChatMessageSource::UnofficialExtras => UE_RECEIVED.store(true, Ordering::Relaxed),
+        ChatMessageSource::ChatEvents => CE_RECEIVED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Runs all checks in the background
+pub fn run() {
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    worker.spawn(Box::new(|| {
+        let checks = vec![
+            Check {
+                name: "7tv reachable",
+                result: check_network(),
+                hint: "Check your internet connection, firewall or proxy settings",
+            },
+            Check {
+                name: "D3D11 device",
+                result: check_device(),
+                hint: "Nexus did not provide a device, emotes are shown as text. Restart the game or update Nexus",
+            },
+            Check {
+                name: "Addon directory writable",
+                result: check_addon_dir(),
+                hint: "Settings can not be saved. Check the permissions of the addon directory",
+            },
+        ];
+        *CHECKS.lock().unwrap() = checks;
+    }));
+}
+
+fn check_network() -> Result<(), String> {
+    match net::agent()
+        .get("https://7tv.io/v3/emote-sets/global")
+        .call()
+    {
+        // any http response means the host is reachable
+        Ok(_) | Err(ureq::Error::StatusCode(_)) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn check_device() -> Result<(), String> {
+    AddonApi::get()
+        .get_d3d11_device()
+        .map(|_| ())
+        .ok_or_else(|| "No device".to_string())
+}
+
+fn check_addon_dir() -> Result<(), String> {
+    let dir = setting_dir();
+    let probe = dir.join(".health");
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| e.to_string())
+}
+
+pub fn render(ui: &Ui, settings: &Settings) {
+    if !ui.collapsing_header(e("Health"), TreeNodeFlags::empty()) {
+        return;
+    }
+    let checks = CHECKS.lock().unwrap();
+    if checks.is_empty() {
+        ui.text_disabled(e("Running checks..."));
+    }
+    for check in checks.iter() {
+        match &check.result {
+            Ok(()) => ui.text_colored(GREEN, format!("{}: {}", e(check.name), e("OK"))),
+            Err(reason) => {
+                ui.text_colored(RED, format!("{}: {reason}", e(check.name)));
+                ui.text_wrapped(e(check.hint));
+            }
+        }
+    }
+    drop(checks);
+    let (configured, other) = match settings.chat_message_source {
+        ChatMessageSource::UnofficialExtras => (&UE_RECEIVED, &CE_RECEIVED),
+        ChatMessageSource::ChatEvents => (&CE_RECEIVED, &UE_RECEIVED),
+    };
+    if configured.load(Ordering::Relaxed) {
+        ui.text_colored(GREEN, format!("{}: {}", e("Chat source"), e("OK")));
+    } else if other.load(Ordering::Relaxed) {
+        ui.text_colored(RED, format!("{}: {}", e("Chat source"), e("No messages")));
+        ui.text_wrapped(e(
+            "Messages arrive from the other chat event source. Switch the source and save",
+        ));
+    } else {
+        ui.text_colored(
+            YELLOW,
+            format!("{}: {}", e("Chat source"), e("No messages yet")),
+        );
+        ui.text_wrapped(e(
+            "Make sure Unofficial Extras or the Chat Events addon is installed and send a message in squad chat",
+        ));
+    }
+    if ui.button(e("Run checks again")) {
+        CHECKS.lock().unwrap().clear();
+        run();
+    }
+}
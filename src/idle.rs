@@ -0,0 +1,27 @@
+//! Skips the per frame emote work while no chat is seen for a long time
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Unix time in seconds of the last chat message
+static LAST_ACTIVITY: AtomicU64 = const { AtomicU64::new(0) };
+/// Copy of the setting, so the render loop doesn't need the settings lock while idle
+static TIMEOUT_SECS: AtomicU32 = const { AtomicU32::new(0) };
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn activity() {
+    LAST_ACTIVITY.store(now(), Ordering::Relaxed);
+}
+
+pub fn set_timeout_mins(mins: u32) {
+    TIMEOUT_SECS.store(mins.saturating_mul(60), Ordering::Relaxed);
+}
+
+pub fn is_idle() -> bool {
+    let timeout = TIMEOUT_SECS.load(Ordering::Relaxed) as u64;
+    timeout > 0 && now().saturating_sub(LAST_ACTIVITY.load(Ordering::Relaxed)) >= timeout
+}
@@ -1,44 +1,106 @@
 #![feature(lock_value_accessors)]
 
-use background::{RunningWorker, Worker};
-use giftex::{Gif, GifState};
+use animation::{Backdrop, Style, Trail};
+use background::{Priority, RunningWorker, Worker};
+use brightness::Tint;
+use giftex::{Gif, GifFrame, GifState, LoadOptions};
+use motion::MotionPath;
 use nexus::arcdps::extras::message::{ChatMessageInfo, RawChatMessageInfo};
 use nexus::data_link::read_nexus_link;
+use nexus::event::arc::ACCOUNT_NAME;
+use nexus::event::extras::{SQUAD_UPDATE, SquadUpdate};
 use nexus::gui::{RenderType, register_render, render};
-use nexus::imgui::{Condition, Image, Ui, Window};
+use nexus::imgui::{Condition, DrawListMut, Ui, Window};
 use nexus::paths::get_addon_dir;
-use nexus::texture::{Texture, get_texture, get_texture_or_create_from_url};
 use nexus::{AddonApi, event_consume};
 use nexus::{AddonFlags, UpdateProvider, event::extras::CHAT_MESSAGE as UE_CHAT_MESSAGE};
-use settings::{Diff, Settings};
-use seventv::{EmoteSet, download_emote_sets, get_emotes};
+use provider::{Emote, EmoteSet, download_emote_sets};
+use settings::{Diff, Lifespan, MapChangeDespawn, Settings, SpawnOverflow};
+use seventv::{SevenTv, SevenTvFile};
+use spawn::SpawnQueue;
 use std::cell::Cell;
+use std::ffi::{CStr, c_char};
+use std::iter;
 use std::ops::RangeInclusive;
 use std::path::PathBuf;
-use std::sync::{Mutex, OnceLock};
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use windows::Win32::Graphics::Direct3D11::ID3D11Device;
 
 use crate::chat_events::{CHAT_MESSAGE as CE_CHAT_MESSAGE, Message, raw::Message as RawMessage};
-use crate::settings::ChatMessageSource;
+use crate::settings::{Channel, ChatMessageSource};
 
+mod animation;
+mod audit;
 mod background;
+mod badge;
+mod brightness;
+mod cache_cleanup;
+mod cancel;
 mod chat_events;
 mod chat_message;
+mod collisions;
+mod commands;
+mod credentials;
+mod diagnostics;
+mod file_cache;
+mod filter;
+mod frame_cache;
 mod giftex;
+mod gist;
+mod handoff;
+mod health;
+mod idle;
+mod motion;
+mod net;
+mod personal;
+mod preview;
+mod privacy;
+mod progress;
+mod provider;
+mod quickbar;
+mod rules;
+mod safe_mode;
+mod sampler;
+mod scheduler;
+#[cfg(feature = "scripting")]
+mod scripting;
 mod settings;
 mod seventv;
+mod slots;
+mod source_compare;
+mod spawn;
+mod squad;
+mod stats;
 mod util;
+mod whisper;
+
+fn setting_dir() -> PathBuf {
+    get_addon_dir(env!("CARGO_PKG_NAME")).expect("Addon dir to exist")
+}
+
+fn frame_cache_dir() -> PathBuf {
+    setting_dir().join("frame_cache")
+}
+
+fn file_cache_dir() -> PathBuf {
+    setting_dir().join("file_cache")
+}
 
 fn setting_path() -> PathBuf {
-    get_addon_dir(env!("CARGO_PKG_NAME"))
-        .expect("Addon dir to exist")
-        .join("settings.json")
+    setting_dir().join("settings.json")
 }
 
 #[derive(Debug, Clone)]
 struct EmoteLayer {
     identifier: String,
+    /// Shown instead of the texture when there is no D3D device
+    name: String,
+    /// Cdn scale of the file, `None` for images that are drawn at their native size
+    scale: Option<u32>,
+    /// Per emote size override from the settings
+    size_factor: f32,
     gif: Option<GifState>,
 }
 
@@ -48,35 +110,74 @@ struct ActiveEmote {
     position: Option<[f32; 2]>,
     start: Option<Instant>,
     start_offset: f32,
+    /// Style of the emote set, the global one is filled in when the emote appears
+    style: Option<Style>,
+    /// Path across the screen, the global one is filled in when the emote appears
+    motion: Option<&'static dyn MotionPath>,
+    /// Set when a lingering emote reached its height, it doesn't move from then on
+    stopped: Option<Instant>,
+    trail: Trail,
 }
 
 const SPEED: f32 = 0.5;
 impl ActiveEmote {
-    fn simulate(&mut self, elapsed: f32) {
+    /// Moves the emote along its path, it stops once its top crosses `stop_line`
+    fn simulate(&mut self, elapsed: f32, stop_line: Option<f32>) {
+        if self.stopped.is_some() {
+            return;
+        }
         let speed = SPEED
             + (self.start_offset + self.start.unwrap().elapsed().as_millis() as f32 / 1000.0).sin()
                 * 0.1;
-        if let Some(position) = self.position {
-            let [x, y] = position;
-            self.position = Some([x, y - speed * elapsed]);
+        if let Some((position, motion)) = self.position.zip(self.motion) {
+            let next = motion.advance(position, speed * elapsed);
+            if let Some(line) = stop_line
+                && position[1] != next[1]
+                && (position[1] - line) * (next[1] - line) <= 0.0
+            {
+                self.stopped = Some(Instant::now());
+            }
+            self.position = Some(next);
         }
     }
     fn get_position(&self, padding_width: f32) -> [f32; 2] {
         let position = self.position.unwrap_or([0.0, 0.0]);
-        [
-            position[0]
-                + (self.start_offset + self.start.unwrap().elapsed().as_millis() as f32 / 1000.0)
-                    .sin()
-                    * padding_width,
-            position[1],
-        ]
+        let t = self.start_offset + self.start.unwrap().elapsed().as_millis() as f32 / 1000.0;
+        let [dx, dy] = self
+            .motion
+            .map_or([0.0, 0.0], |motion| motion.wiggle(t, padding_width));
+        [position[0] + dx, position[1] + dy]
+    }
+    /// Seconds since the emote appeared
+    fn age(&self) -> f32 {
+        self.start
+            .map_or(0.0, |start| start.elapsed().as_secs_f32())
+    }
+    /// Seconds since the emote stopped, `None` while it moves
+    fn stopped_for(&self) -> Option<f32> {
+        self.stopped.map(|stopped| stopped.elapsed().as_secs_f32())
+    }
+    fn is_expired(&self, lifespan: &Lifespan) -> bool {
+        lifespan
+            .remaining(self.age(), self.stopped_for())
+            .is_some_and(|remaining| remaining <= 0.0)
     }
 }
 
 static ACTIVE_EMOTES: Mutex<Vec<ActiveEmote>> = const { Mutex::new(Vec::new()) };
 static EMOTE_SETS: Mutex<Vec<EmoteSet>> = const { Mutex::new(Vec::new()) };
 static WORKER: OnceLock<Mutex<Option<RunningWorker>>> = const { OnceLock::new() };
-static LOADED_EMOTES: Mutex<Vec<(String, Option<Gif>)>> = const { Mutex::new(Vec::new()) };
+static LOADED_EMOTES: Mutex<Vec<(String, Option<Arc<Gif>>)>> = const { Mutex::new(Vec::new()) };
+/// Time between two spawns while releasing the spawns collected during a loading screen
+const BACKLOG_RELEASE_INTERVAL: Duration = Duration::from_millis(150);
+/// How often the worker checks whether the emote sets are due for a refresh
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Downloads shrink the caches as they go, this catches what they miss
+const CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+static SPAWN_QUEUE: Mutex<SpawnQueue<ActiveEmote>> = const { Mutex::new(SpawnQueue::new()) };
+/// Emotes on screen or queued, lets the render loop skip its locks while there are none.
+/// Raised when spawns are queued and set to the exact count at the end of each frame.
+static EMOTE_COUNT: AtomicUsize = const { AtomicUsize::new(0) };
 
 fn load() {
     log::info!("Loading Meme Message");
@@ -84,18 +185,46 @@ fn load() {
     if let Err(e) = settings.load(&setting_path()) {
         log::error!("Failed to load settings: {e}");
     }
-    let lock = WORKER
-        .get_or_init(|| Mutex::new(Some(Worker::new().run())))
-        .lock()
-        .unwrap();
-    let worker = lock.as_ref().expect("Option to be set");
-    let settings = settings.clone();
-    worker.spawn(Box::new(move || {
-        let emote_sets = download_emote_sets(&settings.emote_set_ids, settings.use_global);
-        *EMOTE_SETS.lock().unwrap() = emote_sets;
-    }));
+    audit::baseline(&settings, &setting_dir());
+    safe_mode::begin_load();
+    // the idle timeout counts from the load, not from the last session
+    idle::activity();
+    idle::set_timeout_mins(settings.idle_timeout_mins);
+    privacy::set_hide_names(settings.hide_account_names);
+    provider::register(&SevenTv);
+    provider::register(&SevenTvFile);
+    motion::register_builtin();
+    scheduler::configure(&settings.download_limits, settings.bandwidth_saver);
+    net::set_fetch_threads(settings.fetch_threads);
+    net::configure(&settings);
+    cache_cleanup::set_max_size_mib(settings.cache_max_size_mib);
+    WORKER.get_or_init(|| {
+        let worker = Worker::new()
+            .threads(settings.worker_threads as usize)
+            .run();
+        worker.schedule("emote set refresh", REFRESH_CHECK_INTERVAL, refresh_if_due);
+        worker.schedule("cache pruning", CACHE_PRUNE_INTERVAL, cache_cleanup::shrink);
+        Mutex::new(Some(worker))
+    });
+    // nothing gets downloaded in safe mode, resuming loads the sets
+    if !safe_mode::is_active() {
+        if !settings.cache_migrated {
+            cache_cleanup::migrate();
+        }
+        reload_emote_sets(settings.clone(), true);
+    }
+    rules::load();
+    #[cfg(feature = "scripting")]
+    if settings.scripting {
+        scripting::load();
+    }
+    drop(settings);
+    if !safe_mode::is_active() {
+        health::run();
+    }
     register_render(RenderType::Render, render!(render_fn)).revert_on_unload();
     register_render(RenderType::OptionsRender, render!(render_options)).revert_on_unload();
+    slots::register();
     // TODO: this event is not triggered, if you are already in a squad when logging in
     UE_CHAT_MESSAGE
         .subscribe(event_consume!(|payload: Option<&RawChatMessageInfo>| {
@@ -111,6 +240,21 @@ fn load() {
     //     });
     // }
     // unsafe { (AddonApi::get().event.subscribe)(c"EV_CHAT:Message".as_ptr(), event_callback) }
+    SQUAD_UPDATE
+        .subscribe(event_consume!(|update: Option<&SquadUpdate>| {
+            if let Some(update) = update {
+                squad::update(update);
+            }
+        }))
+        .revert_on_unload();
+    ACCOUNT_NAME
+        .subscribe(event_consume!(|name: Option<&c_char>| {
+            if let Some(name) = name {
+                let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+                filter::set_self_account(&name);
+            }
+        }))
+        .revert_on_unload();
     CE_CHAT_MESSAGE
         .subscribe(event_consume!(|payload: Option<&RawMessage>| {
             if let Some(&payload) = payload {
@@ -120,66 +264,216 @@ fn load() {
         .revert_on_unload();
 }
 
+/// Replaces all loaded emote sets with freshly downloaded ones
+/// With `clean_caches` the cache cleanup follows the downloads, it needs all sets loaded
+fn reload_emote_sets(settings: Settings, clean_caches: bool) {
+    for id in &settings.emote_set_ids {
+        progress::SETS.update(id, progress::State::Queued);
+    }
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    worker.spawn(Box::new(move || {
+        let emote_sets = download_emote_sets(&settings.emote_set_ids, settings.use_global);
+        preload_emote_sets(&emote_sets, &settings);
+        if clean_caches {
+            cache_cleanup::run(settings, emote_sets.clone());
+        }
+        handoff::send(handoff::Update::Reloaded(emote_sets));
+    }));
+}
+
+/// Loads every emote of the sets marked for preloading, so their first use isn't delayed.
+/// Without uploads the files only go into the file cache, that needs the file cache enabled.
+fn preload_emote_sets(emote_sets: &[EmoteSet], settings: &Settings) {
+    if safe_mode::is_active() || settings.bandwidth_saver {
+        return;
+    }
+    let emotes = emote_sets
+        .iter()
+        .filter(|set| settings.preload_sets.contains(&set.id))
+        .flat_map(|set| &set.emotes)
+        .collect::<Vec<_>>();
+    if emotes.is_empty() {
+        return;
+    }
+    log::info!("Preloading {} emotes", emotes.len());
+    if settings.preload_upload {
+        // the render thread owns the loaded emotes, this may run on the worker
+        handoff::send(handoff::Update::Preload(
+            emotes.into_iter().cloned().collect(),
+        ));
+        return;
+    }
+    if !settings.cache_files {
+        log::warn!("Preloading without uploads needs the file cache, skipping");
+        return;
+    }
+    for emote in emotes {
+        // unloading or removing the set cancels the preloads like any other emote download
+        let Some(cancel) = cancel::EMOTE_DOWNLOADS.issue_if_idle(&emote.identifier) else {
+            continue;
+        };
+        let identifier = emote.identifier.clone();
+        let url = emote.url.clone();
+        scheduler::spawn_with_priority(
+            emote.provider,
+            Priority::Low,
+            Box::new(move || {
+                if !cancel.is_cancelled()
+                    && let Err(e) = giftex::prefetch(url.as_str(), &file_cache_dir(), &cancel)
+                {
+                    log::error!("Failed to preload {url}: {e:#}");
+                }
+                cancel::EMOTE_DOWNLOADS.finish(&identifier, &cancel);
+            }),
+        );
+    }
+}
+
 fn render_options(ui: &Ui) {
     let mut settings = Settings::get();
+    safe_mode::options_ui(ui, &settings);
+    seventv::status_ui(ui);
+    if let Some(imported) = gist::take_import() {
+        log::info!("Applying imported settings");
+        settings.apply_import(imported);
+        if let Err(e) = settings.save(&setting_path()) {
+            log::error!("Failed to save settings: {e}");
+        }
+        idle::set_timeout_mins(settings.idle_timeout_mins);
+        privacy::set_hide_names(settings.hide_account_names);
+        scheduler::configure(&settings.download_limits, settings.bandwidth_saver);
+        net::set_fetch_threads(settings.fetch_threads);
+        net::configure(&settings);
+        cache_cleanup::set_max_size_mib(settings.cache_max_size_mib);
+        reload_emote_sets(settings.clone(), false);
+    }
     let mut emote_sets = EMOTE_SETS.lock().unwrap();
+    let file_selection = settings.file_selection();
+    let preloaded = settings.preload_sets.clone();
+    let mut files_changed = false;
+    let mut cancelled = Vec::new();
+    let mut to_preload = Vec::new();
     // Check for source status here
     if let Some(diff) = settings.ui_and_save(emote_sets.as_slice(), ui) {
         settings.save(&setting_path()).unwrap();
+        if settings.file_selection() != file_selection {
+            // the files are picked when a set is resolved
+            reload_emote_sets(settings.clone(), false);
+            files_changed = true;
+        }
+        idle::set_timeout_mins(settings.idle_timeout_mins);
+        privacy::set_hide_names(settings.hide_account_names);
+        scheduler::configure(&settings.download_limits, settings.bandwidth_saver);
+        net::set_fetch_threads(settings.fetch_threads);
+        net::configure(&settings);
+        cache_cleanup::set_max_size_mib(settings.cache_max_size_mib);
+        // sets that were already loaded start preloading right away
+        to_preload.extend(
+            emote_sets
+                .iter()
+                .filter(|set| !preloaded.contains(&set.id))
+                .cloned(),
+        );
         for d in diff {
             match d {
-                Diff::Added(id) => {
-                    // Do we care about the case where we change settings during download?
-                    let lock = WORKER.wait().lock().unwrap();
-                    let worker = lock.as_ref().expect("Option to be set");
-                    worker.spawn(Box::new(move || {
-                        let Ok(emote_set) = get_emotes(&id) else {
-                            log::error!("Failed to download emote set: {id}");
-                            return;
-                        };
-                        let mut emote_sets = EMOTE_SETS.lock().unwrap();
-                        emote_sets.push(emote_set);
-                    }));
-                }
+                Diff::Added(id) => fetch_emote_set(id),
                 Diff::Removed(id) => {
-                    emote_sets.retain(|e| e.id != id);
+                    cancel::SET_DOWNLOADS.cancel(&id);
+                    progress::SETS.remove(&id);
+                    let (removed, kept): (Vec<_>, Vec<_>) =
+                        emote_sets.drain(..).partition(|e| e.id == id);
+                    *emote_sets = kept;
+                    cancelled.extend(
+                        removed
+                            .iter()
+                            .flat_map(|set| &set.emotes)
+                            .filter(|emote| {
+                                !emote_sets
+                                    .iter()
+                                    .flat_map(|set| &set.emotes)
+                                    .any(|e| e.identifier == emote.identifier)
+                            })
+                            .map(|emote| emote.identifier.clone()),
+                    );
                 }
             }
         }
     }
+    drop(emote_sets);
+    cancel_emote_loads(&cancelled);
+    if files_changed {
+        // free the textures of the old files, emotes load again on their next use
+        LOADED_EMOTES.lock().unwrap().clear();
+        progress::EMOTES.clear();
+    } else {
+        preload_emote_sets(&to_preload, &settings);
+    }
+    preview::render(ui, &settings);
+    rules::render(ui);
+    progress::render(ui);
+    health::render(ui, &settings);
+    diagnostics::render(ui, settings.dev_mode);
 }
 
-fn random_offset(range: RangeInclusive<f32>) -> f32 {
-    rand::random_range(range)
-}
-
-enum EmoteType {
-    Static(Texture),
-    Gif(GifState),
+/// Downloads a newly added emote set in the background
+fn fetch_emote_set(id: String) {
+    // removing the set before the download finished cancels it
+    let cancel = cancel::SET_DOWNLOADS.issue(&id);
+    progress::SETS.update(&id, progress::State::Queued);
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref().expect("Option to be set");
+    let (key, token) = (id.clone(), cancel.clone());
+    let job = worker.spawn_with_priority(
+        Priority::High,
+        Box::new(move || {
+            let emote_set = match provider::fetch_set(&id) {
+                Ok(emote_set) => emote_set,
+                Err(e) => {
+                    log::error!("Failed to download {e:#}");
+                    cancel::SET_DOWNLOADS.finish(&id, &cancel);
+                    return;
+                }
+            };
+            if !cancel.is_cancelled() {
+                let settings = Settings::get().clone();
+                preload_emote_sets(std::slice::from_ref(&emote_set), &settings);
+            }
+            handoff::send(handoff::Update::Fetched(emote_set, cancel));
+        }),
+    );
+    // a queued download of a set removed again doesn't start at all
+    cancel::SET_DOWNLOADS.attach(&key, &token, job);
 }
 
-impl EmoteType {
-    fn from_texture(texture: Texture) -> Self {
-        Self::Static(texture)
-    }
-
-    fn from_gif(gif: GifState) -> Self {
-        Self::Gif(gif)
+/// Cancels the downloads of emotes that only the removed sets contained.
+/// Must not be called while holding `EMOTE_SETS`, this locks the emote state before it
+fn cancel_emote_loads(identifiers: &[String]) {
+    if identifiers.is_empty() {
+        return;
     }
-
-    fn width(&self) -> f32 {
-        match self {
-            EmoteType::Static(t) => t.width as f32,
-            EmoteType::Gif(g) => g.frames.width,
-        }
+    for identifier in identifiers {
+        cancel::EMOTE_DOWNLOADS.cancel(identifier);
+        progress::EMOTES.remove(identifier);
     }
+    giftex::discard_uploads(identifiers);
+    let mut active_emotes = ACTIVE_EMOTES.lock().unwrap();
+    let mut loaded = LOADED_EMOTES.lock().unwrap();
+    // complete emotes stay, only unfinished ones are dropped
+    loaded.retain(|(identifier, gif)| {
+        !identifiers.contains(identifier) || gif.as_ref().is_some_and(|gif| gif.complete)
+    });
+    // spawns waiting for a texture that will never arrive
+    active_emotes.retain(|active| {
+        let layers = iter::once(&active.layers.0).chain(active.layers.1.as_ref());
+        !layers
+            .into_iter()
+            .any(|layer| layer.gif.is_none() && identifiers.contains(&layer.identifier))
+    });
+}
 
-    fn height(&self) -> f32 {
-        match self {
-            EmoteType::Static(t) => t.height as f32,
-            EmoteType::Gif(g) => g.frames.height,
-        }
-    }
+fn random_offset(range: RangeInclusive<f32>) -> f32 {
+    rand::random_range(range)
 }
 
 fn check_gif(active_emote: &mut EmoteLayer) {
@@ -190,89 +484,336 @@ fn check_gif(active_emote: &mut EmoteLayer) {
             None
         }
     }) {
-        active_emote.gif = Some(GifState::new(gif.clone()));
+        active_emote.gif = Some(GifState::new(Arc::clone(gif)));
     }
 }
 
+/// Render time per frame spent on uploading emote frames
+const UPLOAD_BUDGET: Duration = Duration::from_millis(2);
+
 fn update_gifs(device: &ID3D11Device) {
-    let mut loaded = LOADED_EMOTES.lock().unwrap();
-    let gifs = match giftex::process_queue(device) {
-        Ok(gifs) => gifs,
-        Err(e) => {
-            log::error!("Failed to process gif queue: {e}");
-            return;
-        }
+    let (stream_above, vram_budget_mib, frame_budget) = {
+        let settings = Settings::get();
+        (
+            settings.stream_frames_above,
+            settings.vram_budget_mib,
+            settings.frame_budget(),
+        )
     };
-    for (identifier, gif) in gifs {
+    let budget = frame_budget.map_or(UPLOAD_BUDGET, |frame| frame.min(UPLOAD_BUDGET));
+    let mut loaded = LOADED_EMOTES.lock().unwrap();
+    for (identifier, gif) in giftex::process_queue(device, stream_above, budget) {
         if let Some(e) = loaded.iter_mut().find(|(l, _)| l == &identifier) {
-            e.1 = Some(gif);
+            e.1 = Some(Arc::new(gif));
         }
     }
+    if vram_budget_mib > 0 {
+        evict_emotes(&mut loaded, vram_budget_mib as usize * 1024 * 1024);
+    }
+}
+
+/// Frees the least recently used emotes until the textures fit into `budget` bytes.
+/// `loaded` is ordered by last use, emotes on screen and emotes still loading are kept.
+/// Evicted emotes are loaded again on their next use.
+fn evict_emotes(loaded: &mut Vec<(String, Option<Arc<Gif>>)>, budget: usize) {
+    let mut total: usize = loaded
+        .iter()
+        .filter_map(|(_, gif)| gif.as_ref())
+        .map(|gif| gif.vram_bytes())
+        .sum();
+    loaded.retain(|(identifier, gif)| {
+        let Some(gif) = gif else {
+            return true;
+        };
+        if total <= budget || Arc::strong_count(gif) > 1 || !gif.complete {
+            return true;
+        }
+        log::info!("Evicting emote {identifier}");
+        total -= gif.vram_bytes();
+        false
+    });
 }
 
-fn get_textures(active_emote: &mut ActiveEmote) -> Option<(EmoteType, Option<EmoteType>)> {
-    let texture_base = get_texture(&active_emote.layers.0.identifier);
-    if active_emote.layers.0.gif.is_none() && texture_base.is_none() {
+fn get_textures(active_emote: &mut ActiveEmote) -> Option<(GifState, Option<GifState>)> {
+    // partially uploaded emotes show their first frame until the rest is there
+    for layer in iter::once(&mut active_emote.layers.0).chain(active_emote.layers.1.as_mut()) {
+        if layer.gif.as_ref().is_some_and(|gif| !gif.frames.complete) {
+            check_gif(layer);
+        }
+    }
+    if active_emote.layers.0.gif.is_none() {
         check_gif(&mut active_emote.layers.0);
         return None;
     }
-    let texture_base = texture_base
-        .map(EmoteType::from_texture)
-        .or_else(|| active_emote.layers.0.gif.take().map(EmoteType::from_gif))
-        .expect("Texture or gif should exist here");
+    if let Some(overlay) = active_emote.layers.1.as_mut()
+        && overlay.gif.is_none()
+    {
+        check_gif(overlay);
+        return None;
+    }
+    let base = active_emote.layers.0.gif.take()?;
+    let overlay = active_emote
+        .layers
+        .1
+        .as_mut()
+        .and_then(|overlay| overlay.gif.take());
+    Some((base, overlay))
+}
 
-    if let Some(overlay) = active_emote.layers.1.as_mut() {
-        let texture_overlay = get_texture(&overlay.identifier);
-        if overlay.gif.is_none() && texture_overlay.is_none() {
-            check_gif(overlay);
-            return None;
-        }
-        let texture_overlay = texture_overlay
-            .map(EmoteType::from_texture)
-            .or_else(|| overlay.gif.take().map(EmoteType::from_gif))
-            .expect("Texture or gif should exist here");
-        Some((texture_base, Some(texture_overlay)))
-    } else {
-        Some((texture_base, None))
+/// Downloads all configured emote sets again and merges them into the loaded ones
+/// Runs on the worker, the refresh is scheduled there
+fn refresh_emote_sets() {
+    if safe_mode::is_active() {
+        return;
+    }
+    // the render thread owns the loaded sets, the refresh continues with a copy of them
+    handoff::send(handoff::Update::Snapshot(Box::new(|loaded| {
+        let lock = WORKER.wait().lock().unwrap();
+        let Some(worker) = lock.as_ref() else {
+            return;
+        };
+        worker.spawn(Box::new(move || {
+            let settings = Settings::get().clone();
+            log::info!("Refreshing emote sets");
+            let mut ids = settings.emote_set_ids.clone();
+            if settings.use_global {
+                ids.push("global".to_string());
+            }
+            let refreshed = net::fetch_all(&ids, |id| {
+                match loaded.iter().find(|set| &set.id == id) {
+                    Some(set) => provider::refresh_set(set),
+                    None => provider::fetch_set(id),
+                }
+                .inspect_err(|e| log::error!("Failed to refresh {e:#}"))
+                .ok()
+            });
+            handoff::send(handoff::Update::Refreshed(
+                refreshed.into_iter().flatten().collect(),
+            ));
+        }));
+    })));
+}
+
+/// On screen size, files of a higher cdn scale are drawn smaller so only the quality changes
+fn display_size(layer: &EmoteLayer, gif: &GifState, emote_size: f32) -> [f32; 2] {
+    let factor = layer.scale.map_or(1.0, |scale| emote_size / scale as f32) * layer.size_factor;
+    let [width, height] = gif.frames.size();
+    [width * factor, height * factor]
+}
+
+/// Draws `frame` stretched onto `corners`, on top of its backdrop silhouettes
+fn draw_frame(
+    draw_list: &DrawListMut,
+    frame: &GifFrame,
+    corners: [[f32; 2]; 4],
+    style: &Style,
+    tint: Tint,
+) {
+    let [uv_a, uv_b, uv_c, uv_d] = frame.uv_corners();
+    for ([dx, dy], [r, g, b, alpha]) in style.backdrop.layers() {
+        let [p1, p2, p3, p4] = corners.map(|[x, y]| [x + dx, y + dy]);
+        draw_list
+            .add_image_quad(frame.get_id(), p1, p2, p3, p4)
+            .uv(uv_a, uv_b, uv_c, uv_d)
+            .col([*r, *g, *b, (alpha * tint.backdrop).min(1.0)])
+            .build();
+    }
+    let [p1, p2, p3, p4] = corners;
+    draw_list
+        .add_image_quad(frame.get_id(), p1, p2, p3, p4)
+        .uv(uv_a, uv_b, uv_c, uv_d)
+        .col({
+            let [r, g, b, a] = style.color;
+            [r, g, b, a * tint.opacity]
+        })
+        .build();
+}
+
+/// Emote names in brackets, used when textures can't be rendered
+fn text_label(active_emote: &ActiveEmote) -> String {
+    match &active_emote.layers.1 {
+        Some(overlay) => format!("[{} {}]", active_emote.layers.0.name, overlay.name),
+        None => format!("[{}]", active_emote.layers.0.name),
     }
 }
 
 fn render_fn(ui: &Ui) {
-    let device = AddonApi::get().get_d3d11_device().expect("Device to exist");
-    update_gifs(&device);
     thread_local! {
         static LAST_TS: Cell<Instant> = Cell::new(Instant::now());
+        static TEXT_MODE_LOGGED: Cell<bool> = const { Cell::new(false) };
+        /// Set when a loading screen ended, spawns collected during it trickle in from then on
+        static RELEASING_SINCE: Cell<Option<Instant>> = const { Cell::new(None) };
+        /// Whether the last frame was in gameplay, `None` while it wasn't checked
+        static WAS_GAMEPLAY: Cell<Option<bool>> = const { Cell::new(None) };
+        /// First emote of the next frame, the ones after the frame budget ran out get their turn first
+        static NEXT_EMOTE: Cell<usize> = const { Cell::new(0) };
+    }
+    safe_mode::render(ui);
+    safe_mode::frame_rendered();
+    handoff::drain();
+    // nothing to move or upload, the emote locks and the nexus link can wait
+    if EMOTE_COUNT.load(Ordering::Relaxed) == 0 && !giftex::has_uploads() {
+        LAST_TS.set(Instant::now());
+        WAS_GAMEPLAY.set(None);
+        render_windows(ui);
+        return;
     }
+    let ndata = read_nexus_link().expect("Nexuslink to exist");
+    if WAS_GAMEPLAY.replace(Some(ndata.is_gameplay)) == Some(true) && !ndata.is_gameplay {
+        despawn_on_map_change();
+    }
+    // emotes neither move nor spawn during loading screens, new ones wait in the queue
+    if idle::is_idle() || !ndata.is_gameplay {
+        if !ndata.is_gameplay && !SPAWN_QUEUE.lock().unwrap().is_empty() {
+            RELEASING_SINCE.set(Some(Instant::now()));
+        }
+        LAST_TS.set(Instant::now());
+        render_windows(ui);
+        return;
+    }
+    let frame_start = Instant::now();
+    // without a device no textures can be created, fall back to rendering the emote names
+    let device = AddonApi::get().get_d3d11_device();
+    match &device {
+        Some(device) if giftex::has_uploads() => update_gifs(device),
+        Some(_) => {}
+        None if !TEXT_MODE_LOGGED.replace(true) => {
+            log::warn!("No D3D11 device available, rendering emotes as text");
+        }
+        None => {}
+    }
+    let text_mode = device.is_none();
+    let (
+        emote_size,
+        global_style,
+        adaptive_tint,
+        filtering,
+        playback,
+        frame_budget,
+        motion,
+        spawn_area,
+        spawn_margin,
+        max_active,
+        lifespan,
+    ) = {
+        let settings = Settings::get();
+        (
+            settings.emote_size * settings.display_scale(),
+            settings.style(),
+            settings.adaptive_tint,
+            settings.texture_filtering,
+            settings.playback(),
+            settings.frame_budget(),
+            motion::by_name(&settings.motion),
+            settings.spawn_area(),
+            settings.spawn_margin.clamp(0.0, 0.45),
+            settings.active_limit(),
+            settings.lifespan(),
+        )
+    };
+    let tint = if adaptive_tint {
+        Tint::for_brightness(brightness::scene_brightness())
+    } else {
+        Tint::NONE
+    };
     let elapsed = LAST_TS.get().elapsed().as_millis() as f32;
-    const PADDING: f32 = 0.10;
+    /// Sideways wiggle of the emotes, as a fraction of the screen width
+    const WIGGLE: f32 = 0.05;
     let mut active_emotes = ACTIVE_EMOTES.lock().unwrap();
-    let ndata = read_nexus_link().expect("Nexuslink to exist");
+    {
+        let mut queue = SPAWN_QUEUE.lock().unwrap();
+        // after a loading screen the backlog is released one at a time instead of all at once
+        let mut budget = match RELEASING_SINCE.get() {
+            Some(since) if !queue.is_empty() => {
+                let due = since.elapsed().as_millis() / BACKLOG_RELEASE_INTERVAL.as_millis();
+                RELEASING_SINCE.set(Some(since + BACKLOG_RELEASE_INTERVAL * due as u32));
+                due as usize
+            }
+            Some(_) => {
+                RELEASING_SINCE.set(None);
+                usize::MAX
+            }
+            None => usize::MAX,
+        };
+        while budget > 0
+            && active_emotes.len() < max_active
+            && let Some(mut spawn) = queue.pop()
+        {
+            budget -= 1;
+            spawn.style.get_or_insert(global_style);
+            active_emotes.push(spawn);
+        }
+    }
     let mut to_remove = Vec::new();
-    for (i, active_emote) in active_emotes.iter_mut().enumerate() {
-        let Some((base, overlay)) = get_textures(active_emote) else {
-            continue;
+    let count = active_emotes.len();
+    let first = NEXT_EMOTE.replace(0) % count.max(1);
+    let mut out_of_budget = false;
+    for k in 0..count {
+        let i = (first + k) % count;
+        // at least one emote moves per frame, so all of them keep moving on slow machines.
+        // The others stay where they are until their turn but are still drawn
+        if !out_of_budget
+            && k > 0
+            && frame_budget.is_some_and(|budget| frame_start.elapsed() >= budget)
+        {
+            out_of_budget = true;
+            NEXT_EMOTE.set(i);
+        }
+        let active_emote = &mut active_emotes[i];
+        let textures = if text_mode {
+            None
+        } else {
+            let Some(textures) = get_textures(active_emote) else {
+                continue;
+            };
+            Some(textures)
         };
-        let width = overlay
-            .as_ref()
-            .map_or(base.width(), |o| o.width().max(base.width()));
-        let height = overlay
+        let base_size = textures
             .as_ref()
-            .map_or(base.height(), |o| o.height().max(base.height()));
+            .map(|(base, _)| display_size(&active_emote.layers.0, base, emote_size));
+        let overlay_size = textures.as_ref().and_then(|(_, overlay)| {
+            let layer = active_emote.layers.1.as_ref()?;
+            Some(display_size(layer, overlay.as_ref()?, emote_size))
+        });
+        let [width, height] = match (base_size, overlay_size) {
+            (Some([bw, bh]), Some([ow, oh])) => [bw.max(ow), bh.max(oh)],
+            (Some(base_size), None) => base_size,
+            _ => ui.calc_text_size(text_label(active_emote)),
+        };
+        let style = active_emote.style.unwrap_or(global_style);
+        let [width, height] = if textures.is_some() {
+            let [width, height] = style.animation.bounds([width, height]);
+            let padding = style.backdrop.padding() * 2.0;
+            [width + padding, height + padding]
+        } else {
+            [width, height]
+        };
+        let screen = [ndata.width as f32, ndata.height as f32];
+        let motion = *active_emote.motion.get_or_insert(motion);
         if active_emote.position.is_none() {
-            let factual_width = ndata.width as f32 - width / 2.0;
-            let left_offset = factual_width * PADDING;
-            let right_offset = factual_width * (1.0 - PADDING);
-            active_emote.position = Some([
-                random_offset(left_offset..=right_offset) - width / 2.0,
-                ndata.height as f32,
-            ]);
+            // paths start within the region, they may leave it afterwards
+            let [x0, y0, x1, y1] = spawn_area;
+            let region = [(x1 - x0) * screen[0], (y1 - y0) * screen[1]];
+            let random = [
+                random_offset(spawn_margin..=1.0 - spawn_margin),
+                random_offset(spawn_margin..=1.0 - spawn_margin),
+            ];
+            let [x, y] = motion.start(region, [width, height], random);
+            active_emote.position = Some([x + x0 * screen[0], y + y0 * screen[1]]);
         }
         if active_emote.start.is_none() {
             active_emote.start = Some(Instant::now());
         }
-        active_emote.simulate(elapsed);
-        let pos = active_emote.get_position(ndata.width as f32 * PADDING / 2.0);
-        if (pos[1] + height) < 0.0 {
+        // the center of the emote stops at the line
+        let stop_line = lifespan
+            .stop_line(screen[1])
+            .map(|line| line - height / 2.0);
+        if !out_of_budget {
+            active_emote.simulate(elapsed, stop_line);
+        }
+        let pos = active_emote.get_position(ndata.width as f32 * WIGGLE);
+        let age = active_emote.age();
+        if active_emote.is_expired(&lifespan) || motion.is_done(pos, [width, height], screen, age) {
             to_remove.push(i);
         } else if let Some(_w) = Window::new(format!("EMOTE#{i}"))
             .no_decoration()
@@ -284,71 +825,186 @@ fn render_fn(ui: &Ui) {
             .position(pos, Condition::Always)
             .begin(ui)
         {
-            ui.set_cursor_pos([(width - base.width()) / 2.0, (height - base.height()) / 2.0]);
-            match base {
-                EmoteType::Static(texture) => {
-                    Image::new(texture.id(), texture.size()).build(ui);
-                }
-                EmoteType::Gif(mut gif) => {
-                    gif.advance(ui);
-                    active_emote.layers.0.gif = Some(gif);
+            let Some((mut base, mut overlay)) = textures else {
+                ui.text(text_label(active_emote));
+                continue;
+            };
+            let base_size = base_size.expect("Size of the textures");
+            let [x, y] = ui.cursor_screen_pos();
+            ui.dummy([width, height]);
+            let center = [x + width / 2.0, y + height / 2.0];
+            let t = active_emote.start_offset + active_emote.start.unwrap().elapsed().as_secs_f32();
+            let draw_list = ui.get_window_draw_list();
+            // the emote window would clip the trail, so it goes behind all windows
+            let trail_list = style.trail.then(|| ui.get_background_draw_list());
+            let tint = Tint {
+                opacity: tint.opacity
+                    * motion.opacity(age)
+                    * lifespan.opacity(age, active_emote.stopped_for()),
+                ..tint
+            };
+            if let Some(device) = &device {
+                sampler::bind(device, sampler::DrawList::Window, filtering);
+                if trail_list.is_some() {
+                    sampler::bind(device, sampler::DrawList::Background, filtering);
                 }
             }
-            if let Some(overlay) = overlay {
-                ui.set_cursor_pos([
-                    (width - overlay.width()) / 2.0,
-                    (height - overlay.height()) / 2.0,
-                ]);
-                match overlay {
-                    EmoteType::Static(texture) => {
-                        Image::new(texture.id(), texture.size()).build(ui);
-                    }
-                    EmoteType::Gif(mut gif) => {
-                        gif.advance(ui);
-                        active_emote.layers.1.as_mut().unwrap().gif = Some(gif);
+            let layers =
+                iter::once((&mut base, base_size)).chain(overlay.as_mut().zip(overlay_size));
+            for (gif, size) in layers {
+                let frame = gif.frame(playback);
+                if let Some(trail_list) = &trail_list {
+                    let ghost_style = Style {
+                        backdrop: Backdrop::None,
+                        ..style
+                    };
+                    for (past, alpha) in active_emote.trail.ghosts() {
+                        let corners = style.animation.corners(past, size, t);
+                        let tint = Tint {
+                            opacity: tint.opacity * alpha,
+                            ..tint
+                        };
+                        draw_frame(trail_list, frame, corners, &ghost_style, tint);
                     }
                 }
+                let corners = style.animation.corners(center, size, t);
+                draw_frame(&draw_list, frame, corners, &style, tint);
+            }
+            sampler::restore(sampler::DrawList::Window);
+            if trail_list.is_some() {
+                sampler::restore(sampler::DrawList::Background);
+                active_emote.trail.record(center);
+            }
+            active_emote.layers.0.gif = Some(base);
+            if let Some(overlay) = overlay {
+                active_emote.layers.1.as_mut().unwrap().gif = Some(overlay);
             }
         }
     }
+    to_remove.sort_unstable();
     for i in to_remove.into_iter().rev() {
         log::info!("Removing emote #{i}");
         drop(active_emotes.swap_remove(i));
     }
+    let queued = SPAWN_QUEUE.lock().unwrap().len();
+    EMOTE_COUNT.store(active_emotes.len() + queued, Ordering::Relaxed);
+    drop(active_emotes);
+    render_windows(ui);
     LAST_TS.set(Instant::now());
 }
 
+/// Emotes of the old map look out of place on the new one, clears them if the settings ask for it
+fn despawn_on_map_change() {
+    let despawn = Settings::get().map_change_despawn;
+    if despawn == MapChangeDespawn::Keep {
+        return;
+    }
+    let mut active_emotes = ACTIVE_EMOTES.lock().unwrap();
+    log::info!("Map change, removing {} emotes", active_emotes.len());
+    active_emotes.clear();
+    let mut queue = SPAWN_QUEUE.lock().unwrap();
+    if despawn == MapChangeDespawn::All {
+        queue.clear();
+    }
+    EMOTE_COUNT.store(queue.len(), Ordering::Relaxed);
+}
+
+/// Windows that are independent of the emotes on screen, drawn on every path of the render loop
+fn render_windows(ui: &Ui) {
+    if Settings::get().show_emote_counter {
+        // without emotes the locks can be skipped, same as in the render loop
+        let (active, waiting) = if EMOTE_COUNT.load(Ordering::Relaxed) == 0 {
+            (0, 0)
+        } else {
+            let active = ACTIVE_EMOTES.lock().unwrap().len();
+            (active, SPAWN_QUEUE.lock().unwrap().len())
+        };
+        badge::render(ui, active, waiting);
+    }
+    quickbar::render(ui);
+    stats::render(ui);
+    commands::render(ui);
+    whisper::render(ui);
+}
+
+/// Refreshes the emote sets every `refresh_interval_mins`, the worker checks it once a minute
+fn refresh_if_due() {
+    static LAST_REFRESH: Mutex<Option<Instant>> = const { Mutex::new(None) };
+    let refresh_interval = Settings::get().refresh_interval_mins;
+    let mut last_refresh = LAST_REFRESH.lock().unwrap();
+    let last = *last_refresh.get_or_insert_with(Instant::now);
+    if refresh_interval > 0 && last.elapsed().as_secs() >= refresh_interval as u64 * 60 {
+        *last_refresh = Some(Instant::now());
+        drop(last_refresh);
+        refresh_emote_sets();
+    }
+}
+
 fn unload() {
+    // queued downloads are skipped instead of finished
+    cancel::SET_DOWNLOADS.clear();
+    cancel::EMOTE_DOWNLOADS.clear();
+    cache_cleanup::cancel();
     WORKER
         .wait()
         .replace(None)
         .unwrap()
         .expect("Option to be set")
         .join();
+    scheduler::shutdown();
     drop(ACTIVE_EMOTES.replace(Vec::new()));
+    SPAWN_QUEUE.lock().unwrap().clear();
+    EMOTE_COUNT.store(0, Ordering::Relaxed);
+    filter::clear();
+    commands::clear();
+    whisper::clear();
+    progress::clear();
+    rules::clear();
+    collisions::clear();
+    preview::clear();
+    #[cfg(feature = "scripting")]
+    scripting::unload();
+    squad::clear();
+    personal::clear();
+    provider::unregister_all();
+    motion::unregister_all();
+    stats::clear();
+    badge::clear();
+    source_compare::stop();
+    sampler::clear();
+    handoff::clear();
     drop(EMOTE_SETS.replace(Vec::new()));
-    // cannot unload textures loaded via nexus
-    // but we do need to release the gifs
-    // because we reload them regardless
-    // nexus textures will get reused on reload
+    // release the gpu resources of all decoded emotes
+    // they get downloaded and decoded again on reload
     drop(LOADED_EMOTES.replace(Vec::new()));
+    giftex::clear_pending_uploads();
+    safe_mode::finish_unload();
 }
 
 fn chat_message_ue(message: ChatMessageInfo<'_>) {
-    if !matches!(
+    health::message_received(ChatMessageSource::UnofficialExtras);
+    idle::activity();
+    let selected = matches!(
         Settings::get().chat_message_source,
         ChatMessageSource::UnofficialExtras
-    ) {
+    );
+    if !selected && !source_compare::is_running() {
         return;
     }
-    let message = message.to_owned();
-    process_message(message.into());
+    let message: Message = message.to_owned().into();
+    source_compare::observe(ChatMessageSource::UnofficialExtras, &message);
+    if selected {
+        process_message(message);
+    }
 }
 fn chat_message_ce(message: RawMessage) {
-    if !matches!(
+    health::message_received(ChatMessageSource::ChatEvents);
+    idle::activity();
+    let selected = matches!(
         Settings::get().chat_message_source,
         ChatMessageSource::ChatEvents
-    ) {
+    );
+    if !selected && !source_compare::is_running() {
         return;
     }
     let message: Message = match message.try_into() {
@@ -358,88 +1014,233 @@ fn chat_message_ce(message: RawMessage) {
             return;
         }
     };
-    process_message(message);
+    source_compare::observe(ChatMessageSource::ChatEvents, &message);
+    if selected {
+        process_message(message);
+    }
+}
+
+fn spawn_emote(
+    spawns: &mut Vec<ActiveEmote>,
+    emote: &Emote,
+    size_factor: f32,
+    style: Option<Style>,
+    last_was_emote: bool,
+) -> bool {
+    let layer = EmoteLayer {
+        identifier: emote.identifier.clone(),
+        name: emote.name.clone(),
+        scale: emote.scale,
+        size_factor,
+        gif: None,
+    };
+    if last_was_emote && emote.zero_width {
+        log::info!("Found zero width emote {}", emote.identifier);
+        let last = spawns.last_mut().expect("Last Active Emote to Exist");
+        last.layers.1 = Some(layer);
+        false
+    } else {
+        spawns.push(ActiveEmote {
+            layers: (layer, None),
+            position: None,
+            start: None,
+            start_offset: rand::random(),
+            style,
+            motion: None,
+            stopped: None,
+            trail: Trail::default(),
+        });
+        true
+    }
+}
+
+fn load_emote(loaded: &mut Vec<(String, Option<Arc<Gif>>)>, emote: &Emote) {
+    load_emote_with_priority(loaded, emote, Priority::Normal);
+}
+
+fn load_emote_with_priority(
+    loaded: &mut Vec<(String, Option<Arc<Gif>>)>,
+    emote: &Emote,
+    priority: Priority,
+) {
+    if safe_mode::is_active() {
+        return;
+    }
+    // text mode only draws the names, nothing would ever take the decoded frames off the queue
+    if AddonApi::get().get_d3d11_device().is_none() {
+        return;
+    }
+    // keep the most recently used emotes at the end, eviction starts at the front
+    if let Some(i) = loaded.iter().position(|(l, _)| l == &emote.identifier) {
+        let entry = loaded.remove(i);
+        loaded.push(entry);
+        return;
+    }
+    log::info!("Loading emote {}", emote.identifier);
+    // static images and gifs both go through our own decoder
+    // and get uploaded on the render thread
+    loaded.push((emote.identifier.clone(), None));
+    let identifier = emote.identifier.clone();
+    let url = emote.url.clone();
+    let cancel = cancel::EMOTE_DOWNLOADS.issue(&identifier);
+    progress::EMOTES.update(&identifier, progress::State::Queued);
+    scheduler::spawn_with_priority(
+        emote.provider,
+        priority,
+        Box::new(move || {
+            if !cancel.is_cancelled() {
+                progress::EMOTES.update(&identifier, progress::State::Downloading);
+            }
+            let options = {
+                let settings = Settings::get();
+                LoadOptions {
+                    max_dimension: settings.max_emote_dimension,
+                    compression: settings.texture_compression,
+                    cache_dir: settings.cache_frames.then(frame_cache_dir),
+                    file_cache_dir: settings.cache_files.then(file_cache_dir),
+                    always_loop: settings.always_loop_gifs,
+                    clamp_delays: settings.clamp_frame_delays,
+                }
+            };
+            let result = Gif::load(identifier.clone(), url.as_str(), &options, &cancel);
+            if let Err(e) = &result {
+                log::error!("Failed to load emote: {e}");
+            }
+            if !cancel.is_cancelled() {
+                progress::EMOTES.finish(&identifier, &result);
+            }
+            cancel::EMOTE_DOWNLOADS.finish(&identifier, &cancel);
+        }),
+    );
 }
 
-// TODO: filter based on source/settings
 fn process_message(chat: Message) {
     let Some(content) = chat.content() else {
         return;
     };
+    if safe_mode::is_active() {
+        return;
+    }
+    // clone so the settings lock is not held while locking the emote state
+    let settings = Settings::get().clone();
+    let channel = Channel::of(&chat.source);
+    // the message of the day arrives on login, before any of the filters make sense,
+    // so the rules run first and only their spawns wait for the filters
+    let mut triggered = Vec::new();
+    for action in rules::evaluate(&chat, content, channel, &settings) {
+        match action {
+            rules::Action::OfferGuildSets { ids, guild_index } => {
+                commands::offer_guild_sets(&ids, guild_index, &settings)
+            }
+            rules::Action::Spawn {
+                emote,
+                style,
+                count,
+            } => triggered.push((emote, style, count)),
+        }
+    }
+    // own commands count even while own messages spawn no emotes
+    if filter::is_own_message(&chat) && commands::handle(&chat, content, &settings) {
+        return;
+    }
+    if !filter::should_process(&chat, content, &settings) {
+        return;
+    }
+    if commands::handle(&chat, content, &settings) {
+        return;
+    }
+    // scripts run before the emote state is locked, they only return emote names
+    #[cfg(feature = "scripting")]
+    let scripted = if settings.scripting {
+        let author = chat.author().unwrap_or_default();
+        scripting::on_message(author, channel, content, &settings)
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "scripting"))]
+    let scripted = Vec::<String>::new();
+    let custom_emotes = EmoteSet::custom(&settings.custom_emotes);
+    let personal_emotes = chat
+        .account_name()
+        .filter(|_| settings.personal_emotes)
+        .and_then(personal::personal_set);
     let mut loaded = LOADED_EMOTES.lock().unwrap();
     let emote_sets = EMOTE_SETS.lock().unwrap();
+    let mut emote_sets =
+        provider::by_priority(&emote_sets, &custom_emotes, &settings.emote_set_ids);
+    // the sender's personal emotes win over the configured sets
+    if let Some(personal_emotes) = personal_emotes.as_deref() {
+        emote_sets.insert(1, personal_emotes);
+    }
+    emote_sets.retain(|set| settings.set_applies(&set.id, channel));
     let mut last_was_emote = false;
-    let mut active_emotes = ACTIVE_EMOTES.lock().unwrap();
-    for word in content.split_whitespace() {
+    let mut spawns = Vec::new();
+    let mut found = Vec::new();
+    let words = content
+        .split_whitespace()
+        .chain(scripted.iter().map(String::as_str));
+    for word in words {
+        let replaced = settings.is_replaced(word);
+        let word = settings.resolve_alias(word);
         let mut is_emote = false;
-        // TODO: if an emote is in multiple sets, only the last one can have a zero
-        // width emote
-        for emote in emote_sets.iter().flat_map(|e| e.emotes.iter()) {
-            if emote.name == word {
-                log::info!("Found emote {word} in chat message");
-                let identifier = format!("EMOTE_{word}");
-                if last_was_emote && emote.zero_width() {
-                    log::info!("Found zero width emote {word}");
-                    let last = active_emotes
-                        .last_mut()
-                        .expect("Last Active Emote to Exist");
-                    last.layers.1 = Some(EmoteLayer {
-                        identifier: identifier.clone(),
-                        gif: None,
-                    });
-                } else {
-                    is_emote = true;
-                    active_emotes.push(ActiveEmote {
-                        layers: (
-                            EmoteLayer {
-                                identifier: identifier.clone(),
-                                gif: None,
-                            },
-                            None,
-                        ),
-                        position: None,
-                        start: None,
-                        start_offset: rand::random(),
-                    });
-                }
-                if loaded.iter().any(|(l, _)| l == &identifier) {
-                    continue;
-                }
-                log::info!("Loading emote {word}");
-                if let Some(file) = emote.find_file() {
-                    let Ok(url) = url::Url::parse(&format!("https:{}/", emote.data.host.url))
-                    else {
-                        log::error!("Failed to parse url: {}", emote.data.host.url);
-                        continue;
-                    };
-                    let Ok(url) = url.join(&file.name) else {
-                        log::error!("Failed to join url: {}", file.name);
-                        continue;
-                    };
-                    // just trigger load
-                    // there should be a load_texture_from_url function
-                    // but apparently the bindings don't expose it yet
-                    loaded.push((identifier.clone(), None));
-                    if emote.data.animated {
-                        let lock = WORKER.wait().lock().unwrap();
-                        let worker = lock.as_ref().expect("Option to be set");
-                        worker.spawn(Box::new(move || {
-                            if let Err(e) = Gif::load(identifier.clone(), url.as_str()) {
-                                log::error!("Failed to load gif: {e}");
-                            };
-                        }));
-                    } else {
-                        let _ = get_texture_or_create_from_url(
-                            &identifier,
-                            url.origin().ascii_serialization(),
-                            url.path(),
-                        );
-                    }
-                }
+        if !replaced
+            && let Some((set, emote)) =
+                provider::find_emote_with_set(&emote_sets, word, &settings.disabled_emotes)
+        {
+            log::info!("Found emote {word} in chat message");
+            stats::record(&emote.name, channel, chat.author());
+            let size_factor = settings.emote_size_factor(&emote.name);
+            let style = settings.set_styles.get(&set.id).copied();
+            is_emote = spawn_emote(&mut spawns, emote, size_factor, style, last_was_emote);
+            load_emote(&mut loaded, emote);
+            if !found.contains(&emote.name) {
+                found.push(emote.name.clone());
             }
         }
         last_was_emote = is_emote;
     }
+    for (name, style, count) in triggered {
+        let Some((set, emote)) = provider::find_emote_with_set(&emote_sets, &name, &[]) else {
+            log::warn!("Emote {name} of a trigger rule not found");
+            continue;
+        };
+        stats::record(&emote.name, channel, chat.author());
+        let size_factor = settings.emote_size_factor(&emote.name);
+        let style = style.or_else(|| settings.set_styles.get(&set.id).copied());
+        for _ in 0..count {
+            spawn_emote(&mut spawns, emote, size_factor, style, false);
+        }
+        load_emote(&mut loaded, emote);
+    }
+    let author = chat.author().unwrap_or_default();
+    if settings.whisper_reply
+        && matches!(channel, Some(Channel::Whisper))
+        && !found.is_empty()
+        && !filter::is_own_message(&chat)
+    {
+        whisper::received(author, found);
+    }
+    let mut queue = SPAWN_QUEUE.lock().unwrap();
+    if settings.spawn_overflow == SpawnOverflow::Drop {
+        // queued spawns count too, they get the free slots first
+        let free = settings
+            .active_limit()
+            .saturating_sub(EMOTE_COUNT.load(Ordering::Relaxed));
+        if spawns.len() > free {
+            log::debug!(
+                "Dropping {} spawns, the screen is full",
+                spawns.len() - free
+            );
+            badge::suppressed(spawns.len() - free);
+            spawns.truncate(free);
+        }
+    }
+    EMOTE_COUNT.fetch_add(spawns.len(), Ordering::Relaxed);
+    for spawn in spawns {
+        if queue.push(author, spawn) {
+            badge::suppressed(1);
+        }
+    }
 }
 
 nexus::export! {
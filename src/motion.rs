@@ -0,0 +1,211 @@
+//! Paths of the emotes across the screen. Every path is a `MotionPath` in the registry,
+//! emotes look theirs up by name, so new ones don't touch the render loop.
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::sync::RwLock;
+
+pub trait MotionPath: Debug + Send + Sync {
+    /// Name in the settings and the options
+    fn name(&self) -> &'static str;
+    /// Top left corner of a new emote of `size`, `[rx, ry]` are random fractions of the screen
+    fn start(&self, screen: [f32; 2], size: [f32; 2], random: [f32; 2]) -> [f32; 2];
+    /// Moves an emote at `position` by `distance` pixels
+    fn advance(&self, position: [f32; 2], distance: f32) -> [f32; 2];
+    /// Offset on top of the path at `t` seconds, at most `amplitude` pixels
+    fn wiggle(&self, _t: f32, _amplitude: f32) -> [f32; 2] {
+        [0.0, 0.0]
+    }
+    /// Opacity `age` seconds after the emote appeared
+    fn opacity(&self, _age: f32) -> f32 {
+        1.0
+    }
+    /// Whether an emote at `position` of `size` left a screen of `screen` or faded out
+    fn is_done(&self, position: [f32; 2], size: [f32; 2], screen: [f32; 2], age: f32) -> bool;
+}
+
+static REGISTRY: RwLock<Vec<&'static dyn MotionPath>> = const { RwLock::new(Vec::new()) };
+
+/// Used for unknown names, e.g. of a path that was removed again
+pub const DEFAULT: &dyn MotionPath = &FloatUp;
+
+pub fn register(path: &'static dyn MotionPath) {
+    log::info!("Registering motion path {}", path.name());
+    REGISTRY.write().unwrap().push(path);
+}
+
+pub fn register_builtin() {
+    register(&FloatUp);
+    register(&FallDown);
+    register(&DriftSideways);
+    register(&FadeInPlace);
+    register(&Bounce);
+}
+
+pub fn unregister_all() {
+    REGISTRY.write().unwrap().clear();
+}
+
+pub fn by_name(name: &str) -> &'static dyn MotionPath {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .find(|path| path.name() == name)
+        .copied()
+        .unwrap_or(DEFAULT)
+}
+
+/// Names of all registered paths, in the order of registration
+pub fn names() -> Vec<&'static str> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .map(|path| path.name())
+        .collect()
+}
+
+/// Horizontal start of the paths that begin at a random spot along the width
+fn random_x([sw, _]: [f32; 2], [w, _]: [f32; 2], [rx, _]: [f32; 2]) -> f32 {
+    rx * (sw - w / 2.0) - w / 2.0
+}
+
+/// Rises from the bottom with a sideways wiggle
+#[derive(Debug)]
+pub struct FloatUp;
+
+impl MotionPath for FloatUp {
+    fn name(&self) -> &'static str {
+        "FloatUp"
+    }
+
+    fn start(&self, screen: [f32; 2], size: [f32; 2], random: [f32; 2]) -> [f32; 2] {
+        [random_x(screen, size, random), screen[1]]
+    }
+
+    fn advance(&self, [x, y]: [f32; 2], distance: f32) -> [f32; 2] {
+        [x, y - distance]
+    }
+
+    fn wiggle(&self, t: f32, amplitude: f32) -> [f32; 2] {
+        [t.sin() * amplitude, 0.0]
+    }
+
+    fn is_done(&self, [_, y]: [f32; 2], [_, h]: [f32; 2], _: [f32; 2], _: f32) -> bool {
+        y + h < 0.0
+    }
+}
+
+/// Falls from the top with a sideways wiggle
+#[derive(Debug)]
+pub struct FallDown;
+
+impl MotionPath for FallDown {
+    fn name(&self) -> &'static str {
+        "FallDown"
+    }
+
+    fn start(&self, screen: [f32; 2], size: [f32; 2], random: [f32; 2]) -> [f32; 2] {
+        [random_x(screen, size, random), -size[1]]
+    }
+
+    fn advance(&self, [x, y]: [f32; 2], distance: f32) -> [f32; 2] {
+        [x, y + distance]
+    }
+
+    fn wiggle(&self, t: f32, amplitude: f32) -> [f32; 2] {
+        [t.sin() * amplitude, 0.0]
+    }
+
+    fn is_done(&self, [_, y]: [f32; 2], _: [f32; 2], [_, sh]: [f32; 2], _: f32) -> bool {
+        y > sh
+    }
+}
+
+/// Crosses the screen from left to right with an up and down wiggle
+#[derive(Debug)]
+pub struct DriftSideways;
+
+impl MotionPath for DriftSideways {
+    fn name(&self) -> &'static str {
+        "DriftSideways"
+    }
+
+    fn start(&self, [_, sh]: [f32; 2], [w, h]: [f32; 2], [_, ry]: [f32; 2]) -> [f32; 2] {
+        [-w, ry * (sh - h)]
+    }
+
+    fn advance(&self, [x, y]: [f32; 2], distance: f32) -> [f32; 2] {
+        [x + distance, y]
+    }
+
+    fn wiggle(&self, t: f32, amplitude: f32) -> [f32; 2] {
+        [0.0, t.sin() * amplitude]
+    }
+
+    fn is_done(&self, [x, _]: [f32; 2], _: [f32; 2], [sw, _]: [f32; 2], _: f32) -> bool {
+        x > sw
+    }
+}
+
+/// Time an emote stays with `FadeInPlace`
+const FADE_LIFETIME: f32 = 3.0;
+const FADE_IN: f32 = 0.3;
+const FADE_OUT: f32 = 1.0;
+
+/// Appears somewhere on the screen and fades out again without moving
+#[derive(Debug)]
+pub struct FadeInPlace;
+
+impl MotionPath for FadeInPlace {
+    fn name(&self) -> &'static str {
+        "FadeInPlace"
+    }
+
+    fn start(&self, screen: [f32; 2], size: [f32; 2], random: [f32; 2]) -> [f32; 2] {
+        [
+            random_x(screen, size, random),
+            random[1] * (screen[1] - size[1]),
+        ]
+    }
+
+    fn advance(&self, position: [f32; 2], _: f32) -> [f32; 2] {
+        position
+    }
+
+    fn opacity(&self, age: f32) -> f32 {
+        (age / FADE_IN)
+            .min((FADE_LIFETIME - age) / FADE_OUT)
+            .clamp(0.0, 1.0)
+    }
+
+    fn is_done(&self, _: [f32; 2], _: [f32; 2], _: [f32; 2], age: f32) -> bool {
+        age >= FADE_LIFETIME
+    }
+}
+
+/// Hops along the bottom from left to right
+#[derive(Debug)]
+pub struct Bounce;
+
+impl MotionPath for Bounce {
+    fn name(&self) -> &'static str {
+        "Bounce"
+    }
+
+    fn start(&self, [_, sh]: [f32; 2], [w, h]: [f32; 2], _: [f32; 2]) -> [f32; 2] {
+        [-w, sh - h]
+    }
+
+    fn advance(&self, [x, y]: [f32; 2], distance: f32) -> [f32; 2] {
+        [x + distance, y]
+    }
+
+    fn wiggle(&self, t: f32, amplitude: f32) -> [f32; 2] {
+        [0.0, -(t * PI).sin().abs() * amplitude]
+    }
+
+    fn is_done(&self, [x, _]: [f32; 2], _: [f32; 2], [sw, _]: [f32; 2], _: f32) -> bool {
+        x > sw
+    }
+}
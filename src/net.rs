@@ -0,0 +1,380 @@
+//! Shared helpers for network requests. Every download goes through `Retry::run`, so a single
+//! dropped connection doesn't lose an emote set until the next refresh, and batches of requests
+//! run side by side through `fetch_all`.
+use crate::cancel::CancelToken;
+use crate::settings::Settings;
+use crate::util::e;
+use nexus::alert::send_alert;
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Agent of all requests, replaced when the network settings change.
+/// Connections to the same host are reused across requests
+static AGENT: Mutex<Option<ureq::Agent>> = const { Mutex::new(None) };
+/// Identifies the addon version, e.g. in the logs of the apis
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest stretch a retry sleeps without looking at its cancel token
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Copy of the setting, requests run on worker threads that don't hold the settings
+static FETCH_THREADS: AtomicUsize = const { AtomicUsize::new(4) };
+
+thread_local! {
+    /// Set on the threads of `fetch_all`, nested batches run on them one after another
+    static IN_FETCH: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_fetch_threads(threads: u32) {
+    FETCH_THREADS.store(threads.max(1) as usize, Ordering::Relaxed);
+}
+
+pub fn agent() -> ureq::Agent {
+    AGENT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            build_agent(
+                None,
+                Some(DEFAULT_CONNECT_TIMEOUT),
+                Some(DEFAULT_READ_TIMEOUT),
+            )
+        })
+        .clone()
+}
+
+fn build_agent(
+    proxy: Option<ureq::Proxy>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+) -> ureq::Agent {
+    let builder = ureq::Agent::config_builder()
+        .user_agent(USER_AGENT)
+        .timeout_connect(connect_timeout)
+        .timeout_recv_response(read_timeout)
+        .timeout_recv_body(read_timeout);
+    // without a proxy of our own, the one of the environment variables is used
+    match proxy {
+        Some(proxy) => builder.proxy(Some(proxy)).build().new_agent(),
+        None => builder.build().new_agent(),
+    }
+}
+
+/// Applies the proxy and the timeouts to all following requests
+pub fn configure(settings: &Settings) {
+    let proxy = match settings.proxy.url().map(|url| ureq::Proxy::new(&url)) {
+        Some(Ok(proxy)) => Some(proxy),
+        Some(Err(e)) => {
+            log::error!("Invalid proxy {}: {e}", settings.proxy.host);
+            None
+        }
+        None => None,
+    };
+    let timeout = |secs: u32| (secs > 0).then(|| Duration::from_secs(secs as u64));
+    let agent = build_agent(
+        proxy,
+        timeout(settings.connect_timeout_secs),
+        timeout(settings.read_timeout_secs),
+    );
+    *AGENT.lock().unwrap() = Some(agent);
+}
+
+/// Runs `fetch` for all `items` on up to `fetch_threads` threads, the results keep the order of `items`.
+/// Only meant for requests, decoded emotes still reach the render thread through its upload queue.
+/// Called from within another `fetch_all` it stays on the current thread, so the total number of
+/// threads never goes past `fetch_threads`.
+pub fn fetch_all<T: Sync, R: Send>(items: &[T], fetch: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = FETCH_THREADS.load(Ordering::Relaxed).min(items.len());
+    if threads <= 1 || IN_FETCH.get() {
+        return items.iter().map(fetch).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                IN_FETCH.set(true);
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let result = fetch(item);
+                    results.lock().unwrap().push((i, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Token bucket shared by the requests to one api, requests over the limit wait instead of failing
+pub struct RateLimiter {
+    name: &'static str,
+    bucket: Mutex<Bucket>,
+    capacity: f32,
+    per_second: f32,
+}
+
+struct Bucket {
+    /// Negative while requests wait for their token
+    tokens: f32,
+    refilled: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Allows bursts of `capacity` requests and `per_second` requests on average
+    pub const fn new(name: &'static str, capacity: u32, per_second: u32) -> Self {
+        Self {
+            name,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled: None,
+            }),
+            capacity: capacity as f32,
+            per_second: per_second as f32,
+        }
+    }
+
+    /// Takes a token, blocks until it is available. Waiting requests get their tokens in order
+    pub fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = match bucket.refilled {
+                Some(refilled) => now.duration_since(refilled).as_secs_f32() * self.per_second,
+                None => self.capacity,
+            };
+            bucket.tokens = (bucket.tokens + refill).min(self.capacity) - 1.0;
+            bucket.refilled = Some(now);
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f32(-bucket.tokens / self.per_second)
+        };
+        log::info!(
+            "Throttling {} requests, waiting {}ms",
+            self.name,
+            wait.as_millis()
+        );
+        thread::sleep(wait);
+    }
+}
+
+/// Stops requests to an api that keeps failing, instead of retrying every one of them.
+/// After `threshold` failed requests in a row the api is only tried again after a backoff,
+/// doubled whenever that try fails too. Any response of the api closes the breaker again.
+pub struct CircuitBreaker {
+    name: &'static str,
+    threshold: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    state: Mutex<Breaker>,
+}
+
+struct Breaker {
+    failures: u32,
+    /// Set while the breaker is open, requests fail right away until then
+    retry_at: Option<Instant>,
+    /// Set while the single request `check` let through after the backoff is running
+    trial: bool,
+    backoff: Duration,
+    last_error: Option<String>,
+}
+
+impl CircuitBreaker {
+    pub const fn new(
+        name: &'static str,
+        threshold: u32,
+        base_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        Self {
+            name,
+            threshold,
+            base_backoff,
+            max_backoff,
+            state: Mutex::new(Breaker {
+                failures: 0,
+                retry_at: None,
+                trial: false,
+                backoff: Duration::ZERO,
+                last_error: None,
+            }),
+        }
+    }
+
+    /// Fails while the breaker is open. Once the backoff passed, one request gets through
+    pub fn check(&self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match state.retry_at {
+            Some(retry_at) if Instant::now() < retry_at => anyhow::bail!(
+                "{} is unreachable, retrying in {}s",
+                self.name,
+                retry_at.saturating_duration_since(Instant::now()).as_secs()
+            ),
+            Some(_) => {
+                // requests running alongside the try wait for its outcome
+                state.retry_at = Some(Instant::now() + state.backoff);
+                state.trial = true;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the outcome of a request, only errors a later attempt could fix count as failures
+    pub fn record<T>(&self, result: &Result<T, ureq::Error>) {
+        let mut state = self.state.lock().unwrap();
+        let error = match result {
+            Err(error) if is_transient(error) => error,
+            _ => {
+                if state.retry_at.take().is_some() {
+                    log::info!("{} is reachable again", self.name);
+                }
+                state.trial = false;
+                state.failures = 0;
+                state.last_error = None;
+                return;
+            }
+        };
+        state.failures += 1;
+        state.last_error = Some(error.to_string());
+        if state.failures < self.threshold {
+            return;
+        }
+        let opened = state.retry_at.is_none();
+        state.backoff = if opened {
+            self.base_backoff
+        } else if std::mem::take(&mut state.trial) {
+            state.backoff.saturating_mul(2).min(self.max_backoff)
+        } else {
+            // requests started before the breaker opened, only the trial extends the backoff
+            return;
+        };
+        state.retry_at = Some(Instant::now() + state.backoff);
+        log::error!(
+            "{} failed {} times in a row ({error}), pausing requests for {}s",
+            self.name,
+            state.failures,
+            state.backoff.as_secs()
+        );
+        if opened {
+            send_alert(format!("{} {}: {error}", self.name, e("is unreachable")));
+        }
+    }
+
+    /// Last error and the time until the next try, `None` while the breaker is closed
+    pub fn status(&self) -> Option<(String, Duration)> {
+        let state = self.state.lock().unwrap();
+        let retry_at = state.retry_at?;
+        Some((
+            state.last_error.clone().unwrap_or_default(),
+            retry_at.saturating_duration_since(Instant::now()),
+        ))
+    }
+}
+
+/// How often and how patiently a request is retried
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    /// Tries in total, the first one included
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl Retry {
+    pub fn attempts(self, attempts: u32) -> Self {
+        Self { attempts, ..self }
+    }
+
+    /// Runs `request` until it succeeds, fails permanently or runs out of attempts
+    pub fn run<T>(
+        &self,
+        what: &str,
+        request: impl FnMut() -> Result<T, ureq::Error>,
+    ) -> Result<T, ureq::Error> {
+        self.run_cancellable(what, &CancelToken::default(), request)
+    }
+
+    /// Like `run`, but gives up waiting for the next attempt once `cancel` is set
+    pub fn run_cancellable<T>(
+        &self,
+        what: &str,
+        cancel: &CancelToken,
+        mut request: impl FnMut() -> Result<T, ureq::Error>,
+    ) -> Result<T, ureq::Error> {
+        let mut attempt = 1;
+        loop {
+            match request() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.attempts && is_transient(&e) && !cancel.is_cancelled() => {
+                    let delay = self.delay(attempt);
+                    log::warn!(
+                        "Request for {what} failed ({e}), retrying in {}ms",
+                        delay.as_millis()
+                    );
+                    if !sleep_unless_cancelled(delay, cancel) {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Exponential backoff with jitter, so clients that failed together don't retry together
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        backoff.mul_f32(0.5 + rand::random::<f32>() * 0.5)
+    }
+}
+
+/// Sleeps in short slices, `false` if `cancel` was set before `duration` passed
+fn sleep_unless_cancelled(duration: Duration, cancel: &CancelToken) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(CANCEL_CHECK_INTERVAL));
+    }
+}
+
+/// Errors a later attempt can fix, client errors like a 404 stay the same
+fn is_transient(e: &ureq::Error) -> bool {
+    match e {
+        ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled => true,
+        _ => false,
+    }
+}
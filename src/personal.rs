@@ -0,0 +1,108 @@
+//! Personal 7tv emote sets of message senders.
+//! The account of a sender is looked up with the emote providers once, players can also link
+//! their account to a 7tv user themselves with `!emoteset personal <7tv user id>`.
+use crate::provider::{self, EmoteSet};
+use crate::{WORKER, filter, privacy};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Failed lookups are tried again after this, the user may have signed up in the meantime
+const RETRY_AFTER: Duration = Duration::from_secs(30 * 60);
+
+/// Account name to 7tv user id, linked in chat
+static LINKS: Mutex<Vec<(String, String)>> = const { Mutex::new(Vec::new()) };
+/// Account name to the id of its personal emote set, found by the providers
+static ACCOUNTS: Mutex<Vec<(String, Lookup<String>)>> = const { Mutex::new(Vec::new()) };
+/// Emote set id to the set
+static SETS: Mutex<Vec<(String, Lookup<Arc<EmoteSet>>)>> = const { Mutex::new(Vec::new()) };
+
+enum Lookup<T> {
+    Pending,
+    Found(T),
+    /// Nothing found or the request failed, at the given time
+    Missing(Instant),
+}
+
+pub fn link(account_name: &str, user_id: &str) {
+    let account_name = filter::normalize_account(account_name);
+    log::info!(
+        "Linking {} to 7tv user {user_id}",
+        privacy::display_name(account_name)
+    );
+    let mut links = LINKS.lock().unwrap();
+    links.retain(|(account, _)| account != account_name);
+    links.push((account_name.to_string(), user_id.to_string()));
+}
+
+/// Personal emote set of an account, starts the lookup and the download on first use
+pub fn personal_set(account_name: &str) -> Option<Arc<EmoteSet>> {
+    let account_name = filter::normalize_account(account_name);
+    let linked = LINKS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(account, _)| account == account_name)
+        .map(|(_, user_id)| user_id.clone());
+    let set_id = match linked {
+        Some(user_id) => user_id,
+        None => cached(&ACCOUNTS, account_name, |account_name| {
+            provider::personal_set_id(account_name)
+                .inspect_err(|e| {
+                    log::error!(
+                        "Failed to look up the personal emotes of {}: {e:#}",
+                        privacy::display_name(account_name)
+                    )
+                })
+                .ok()
+                .flatten()
+        })?,
+    };
+    cached(&SETS, &set_id, |set_id| {
+        provider::fetch_set(set_id)
+            .inspect_err(|e| log::error!("Failed to download personal emote set {set_id}: {e:#}"))
+            .ok()
+            .map(Arc::new)
+    })
+}
+
+/// Value of `key`, a miss or an expired failure runs `fetch` on the worker and returns `None`
+fn cached<T: Clone + Send + 'static>(
+    entries: &'static Mutex<Vec<(String, Lookup<T>)>>,
+    key: &str,
+    fetch: impl FnOnce(&str) -> Option<T> + Send + 'static,
+) -> Option<T> {
+    let mut locked = entries.lock().unwrap();
+    match locked
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, lookup)| lookup)
+    {
+        Some(Lookup::Found(value)) => return Some(value.clone()),
+        Some(Lookup::Pending) => return None,
+        Some(Lookup::Missing(since)) if since.elapsed() < RETRY_AFTER => return None,
+        _ => {}
+    }
+    locked.retain(|(k, _)| k != key);
+    locked.push((key.to_string(), Lookup::Pending));
+    drop(locked);
+    let key = key.to_string();
+    let lock = WORKER.wait().lock().unwrap();
+    let worker = lock.as_ref()?;
+    worker.spawn(Box::new(move || {
+        let lookup = match fetch(&key) {
+            Some(value) => Lookup::Found(value),
+            None => Lookup::Missing(Instant::now()),
+        };
+        let mut entries = entries.lock().unwrap();
+        if let Some(entry) = entries.iter_mut().find(|(k, _)| *k == key) {
+            entry.1 = lookup;
+        }
+    }));
+    None
+}
+
+pub fn clear() {
+    LINKS.lock().unwrap().clear();
+    ACCOUNTS.lock().unwrap().clear();
+    SETS.lock().unwrap().clear();
+}
@@ -0,0 +1,201 @@
+//! Miniature of the emote overlay in the options, so the appearance can be tuned without
+//! writing emotes in chat. Uses the most recently used emote, its name while none is loaded.
+use crate::animation::{Backdrop, Style, Trail};
+use crate::brightness::Tint;
+use crate::giftex::GifState;
+use crate::motion;
+use crate::settings::Settings;
+use crate::util::e;
+use crate::{LOADED_EMOTES, SPEED, draw_frame, sampler};
+use nexus::AddonApi;
+use nexus::data_link::read_nexus_link;
+use nexus::imgui::{TreeNodeFlags, Ui};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Emotes currently in the preview, holding them keeps their texture alive
+static PREVIEW: Mutex<Option<Preview>> = const { Mutex::new(None) };
+
+const PANE_HEIGHT: f32 = 220.0;
+const SPAWN_INTERVAL: Duration = Duration::from_millis(1200);
+/// Screen height assumed without the nexus link
+const FALLBACK_SCREEN_HEIGHT: f32 = 1080.0;
+const SAMPLE_NAME: &str = "[Sample]";
+/// Room of an emote as a fraction of the pane height
+const ROOM: f32 = 0.25;
+
+struct Preview {
+    emotes: Vec<PreviewEmote>,
+    last_spawn: Instant,
+    last_frame: Instant,
+    spawned: u32,
+}
+
+struct PreviewEmote {
+    gif: Option<GifState>,
+    /// Top left corner of the room of the emote within the pane, `None` until it is placed
+    position: Option<[f32; 2]>,
+    /// Random fractions of the pane the emote starts at
+    random: [f32; 2],
+    start: Instant,
+    start_offset: f32,
+    /// When the emote reached the linger line
+    stopped: Option<Instant>,
+    trail: Trail,
+}
+
+impl PreviewEmote {
+    fn age(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    fn stopped_for(&self) -> Option<f32> {
+        self.stopped.map(|stopped| stopped.elapsed().as_secs_f32())
+    }
+}
+
+pub fn clear() {
+    PREVIEW.lock().unwrap().take();
+}
+
+pub fn render(ui: &Ui, settings: &Settings) {
+    if !ui.collapsing_header(e("Preview"), TreeNodeFlags::empty()) {
+        // don't keep the sample texture alive while nobody looks at it
+        clear();
+        return;
+    }
+    ui.child_window("emote preview")
+        .size([0.0, PANE_HEIGHT])
+        .border(true)
+        .build(|| simulate(ui, settings));
+}
+
+fn simulate(ui: &Ui, settings: &Settings) {
+    let [left, top] = ui.cursor_screen_pos();
+    let [width, height] = ui.content_region_avail();
+    ui.dummy([width, height]);
+    let screen_height = read_nexus_link().map_or(FALLBACK_SCREEN_HEIGHT, |link| link.height as f32);
+    // everything shrinks like the pane compared to the screen
+    let scale = height / screen_height.max(1.0);
+    let style = settings.style();
+    let playback = settings.playback();
+    let lifespan = settings.lifespan();
+    let margin = settings.spawn_margin.clamp(0.0, 0.45);
+    let mut preview = PREVIEW.lock().unwrap();
+    let preview = preview.get_or_insert_with(|| Preview {
+        emotes: Vec::new(),
+        last_spawn: Instant::now() - SPAWN_INTERVAL,
+        last_frame: Instant::now(),
+        spawned: 0,
+    });
+    let elapsed = preview.last_frame.elapsed().as_millis() as f32;
+    preview.last_frame = Instant::now();
+    if preview.last_spawn.elapsed() >= SPAWN_INTERVAL {
+        preview.last_spawn = Instant::now();
+        preview.spawned += 1;
+        // the most recently used emotes are at the end
+        let sample = LOADED_EMOTES
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|(_, gif)| gif.clone());
+        preview.emotes.push(PreviewEmote {
+            gif: sample.map(GifState::new),
+            position: None,
+            // spread over the region without randomness, so the preview stays calm
+            random: [0.618, 0.382].map(|step| {
+                margin + (1.0 - 2.0 * margin) * (preview.spawned as f32 * step).fract()
+            }),
+            start: Instant::now(),
+            start_offset: preview.spawned as f32,
+            stopped: None,
+            trail: Trail::default(),
+        });
+    }
+    let draw_list = ui.get_window_draw_list();
+    let device = AddonApi::get().get_d3d11_device();
+    if let Some(device) = &device {
+        sampler::bind(
+            device,
+            sampler::DrawList::Window,
+            settings.texture_filtering,
+        );
+    }
+    let motion = motion::by_name(&settings.motion);
+    // emotes are placed by a room of this size, larger ones overlap the pane edge a bit
+    let room = [height * ROOM, height * ROOM];
+    // the spawn region within the pane, like on the screen
+    let [x0, y0, x1, y1] = settings.spawn_area();
+    let region = [(x1 - x0) * width, (y1 - y0) * height];
+    // the center of the emote stops at the line
+    let stop_line = lifespan.stop_line(height).map(|line| line - room[1] / 2.0);
+    for emote in &mut preview.emotes {
+        let t = emote.start_offset + emote.start.elapsed().as_secs_f32();
+        let speed = SPEED + t.sin() * 0.1;
+        let position = emote.position.get_or_insert_with(|| {
+            let [x, y] = motion.start(region, room, emote.random);
+            [x + x0 * width, y + y0 * height]
+        });
+        if emote.stopped.is_none() {
+            let next = motion.advance(*position, speed * elapsed * scale);
+            if let Some(line) = stop_line
+                && position[1] != next[1]
+                && (position[1] - line) * (next[1] - line) <= 0.0
+            {
+                emote.stopped = Some(Instant::now());
+            }
+            *position = next;
+        }
+        let [dx, dy] = motion.wiggle(t, width * 0.05);
+        let center = [
+            left + position[0] + room[0] / 2.0 + dx,
+            top + position[1] + room[1] / 2.0 + dy,
+        ];
+        let tint = Tint {
+            opacity: motion.opacity(emote.age())
+                * lifespan.opacity(emote.age(), emote.stopped_for()),
+            ..Tint::NONE
+        };
+        let Some(gif) = emote.gif.as_mut().filter(|_| device.is_some()) else {
+            draw_list.add_text(center, [1.0, 1.0, 1.0, tint.opacity], SAMPLE_NAME);
+            continue;
+        };
+        let [w, h] = gif.frames.size();
+        // at the scale of the pane most emotes would be a few pixels, too small to judge them
+        let factor = settings.emote_size * settings.display_scale()
+            / settings.emote_scale.max(1) as f32
+            * scale.max(0.5);
+        let size = [w * factor, h * factor];
+        let frame = gif.frame(playback);
+        if style.trail {
+            let ghost_style = Style {
+                backdrop: Backdrop::None,
+                ..style
+            };
+            for (past, alpha) in emote.trail.ghosts() {
+                let corners = style.animation.corners(past, size, t);
+                let tint = Tint {
+                    opacity: tint.opacity * alpha,
+                    ..tint
+                };
+                draw_frame(&draw_list, frame, corners, &ghost_style, tint);
+            }
+            emote.trail.record(center);
+        }
+        let corners = style.animation.corners(center, size, t);
+        draw_frame(&draw_list, frame, corners, &style, tint);
+    }
+    if device.is_some() {
+        sampler::restore(sampler::DrawList::Window);
+    }
+    preview.emotes.retain(|emote| {
+        let expired = lifespan
+            .remaining(emote.age(), emote.stopped_for())
+            .is_some_and(|remaining| remaining <= 0.0);
+        !expired
+            && emote.position.is_none_or(|position| {
+                !motion.is_done(position, room, [width, height], emote.age())
+            })
+    });
+}
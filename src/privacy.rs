@@ -0,0 +1,24 @@
+//! Streamer mode, replaces account and character names wherever they are shown or logged
+use crate::filter::normalize_account;
+use crate::util::e;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Copy of the setting, names get formatted in places that don't hold the settings
+static HIDE_NAMES: AtomicBool = const { AtomicBool::new(false) };
+
+pub fn set_hide_names(hide: bool) {
+    HIDE_NAMES.store(hide, Ordering::Relaxed);
+}
+
+/// The name itself, or a short hash of it while names are hidden.
+/// The hash stays the same for a name, so people can still be told apart.
+pub fn display_name(name: &str) -> String {
+    if !HIDE_NAMES.load(Ordering::Relaxed) {
+        return name.to_string();
+    }
+    let mut hasher = DefaultHasher::new();
+    normalize_account(name).hash(&mut hasher);
+    format!("{}#{:04x}", e("Player"), hasher.finish() & 0xffff)
+}
@@ -0,0 +1,145 @@
+//! Download state of emote sets and emotes, shown in the options so users can tell
+//! why an emote isn't showing yet. Worker jobs update it, `render` reads it.
+use crate::EMOTE_SETS;
+use crate::util::{UiExt, e};
+use nexus::imgui::{TreeNodeFlags, Ui};
+use std::sync::Mutex;
+
+/// Emote sets, keyed by set id
+pub static SETS: Registry = Registry::new();
+/// Emotes, keyed by texture identifier
+pub static EMOTES: Registry = Registry::new();
+
+const GREEN: [f32; 4] = [0.4, 0.9, 0.4, 1.0];
+const YELLOW: [f32; 4] = [0.9, 0.8, 0.3, 1.0];
+const RED: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Queued,
+    Downloading,
+    Done,
+    Failed(String),
+}
+
+impl State {
+    fn label(&self) -> String {
+        match self {
+            State::Queued => e("Queued"),
+            State::Downloading => e("Downloading"),
+            State::Done => e("Done"),
+            State::Failed(_) => e("Failed"),
+        }
+    }
+
+    fn color(&self) -> [f32; 4] {
+        match self {
+            State::Queued | State::Downloading => YELLOW,
+            State::Done => GREEN,
+            State::Failed(_) => RED,
+        }
+    }
+}
+
+pub struct Registry(Mutex<Vec<(String, State)>>);
+
+impl Registry {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    pub fn update(&self, key: &str, state: State) {
+        let mut states = self.0.lock().unwrap();
+        match states.iter_mut().find(|(k, _)| k == key) {
+            Some((_, existing)) => *existing = state,
+            None => states.push((key.to_string(), state)),
+        }
+    }
+
+    /// Records the outcome of a download
+    pub fn finish<T, E: std::fmt::Display>(&self, key: &str, result: &Result<T, E>) {
+        let state = match result {
+            Ok(_) => State::Done,
+            Err(e) => State::Failed(format!("{e:#}")),
+        };
+        self.update(key, state);
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.0.lock().unwrap().retain(|(k, _)| k != key);
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+pub fn clear() {
+    SETS.clear();
+    EMOTES.clear();
+}
+
+/// Emotes load on their first use, so only the requested ones of a set show up here
+pub fn render(ui: &Ui) {
+    if !ui.collapsing_header(e("Downloads"), TreeNodeFlags::empty()) {
+        return;
+    }
+    let set_states = SETS.0.lock().unwrap().clone();
+    if set_states.is_empty() {
+        ui.text_disabled(e("Nothing downloaded yet"));
+        return;
+    }
+    let emote_sets = EMOTE_SETS.lock().unwrap();
+    let emote_states = EMOTES.0.lock().unwrap();
+    for (id, state) in &set_states {
+        let set = emote_sets.iter().find(|set| &set.id == id);
+        let name = set.map_or(id.as_str(), |set| set.name.as_str());
+        ui.text_colored(state.color(), format!("{name}: {}", state.label()));
+        if let State::Failed(reason) = state {
+            ui.help_marker(|| {
+                ui.tooltip_text(reason);
+            });
+        }
+        let Some(set) = set else {
+            continue;
+        };
+        let mut counts = [0; 4];
+        let mut failed = Vec::new();
+        for emote in &set.emotes {
+            let Some((_, state)) = emote_states.iter().find(|(k, _)| k == &emote.identifier) else {
+                continue;
+            };
+            let i = match state {
+                State::Queued => 0,
+                State::Downloading => 1,
+                State::Done => 2,
+                State::Failed(reason) => {
+                    failed.push((&emote.name, reason));
+                    3
+                }
+            };
+            counts[i] += 1;
+        }
+        if counts == [0; 4] {
+            continue;
+        }
+        ui.same_line();
+        ui.text_disabled(format!(
+            "{} {}, {} {}, {} {}, {} {}",
+            counts[0],
+            e("queued"),
+            counts[1],
+            e("downloading"),
+            counts[2],
+            e("done"),
+            counts[3],
+            e("failed"),
+        ));
+        for (name, reason) in failed {
+            ui.text_colored(RED, format!("  {name}"));
+            ui.help_marker(|| {
+                ui.tooltip_text(reason);
+            });
+        }
+    }
+}
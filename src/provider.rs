@@ -0,0 +1,327 @@
+use crate::net;
+use crate::progress::{self, State};
+use crate::settings::{CustomEmote, DisabledEmote, Settings};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::iter;
+use std::sync::RwLock;
+use strum::{VariantArray, VariantNames};
+use url::Url;
+
+/// An emote resolved to a single downloadable file, independent of the provider it came from
+#[derive(Debug, Clone)]
+pub struct Emote {
+    pub name: String,
+    /// Unique texture identifier, built by [`content_identifier`] from the file the emote points to
+    pub identifier: String,
+    pub url: Url,
+    pub zero_width: bool,
+    /// Name of the provider serving the file, used to apply its download limits
+    pub provider: &'static str,
+    /// Cdn scale of the file, `None` if the image has no defined scale
+    pub scale: Option<u32>,
+}
+
+/// Texture identifier keyed by the file instead of the emote name, so renamed emotes
+/// reuse their texture and equal names from different providers can't show the wrong image
+pub fn content_identifier(provider: &str, file: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    file.hash(&mut hasher);
+    format!("EMOTE_{provider}_{:016x}", hasher.finish())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DownloadLimits {
+    /// Parallel downloads, 0 picks a count from the cores
+    pub max_concurrent: u32,
+    /// Minimum time between the start of two downloads
+    pub interval_ms: u32,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            interval_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmoteSet {
+    pub id: String,
+    pub name: String,
+    pub emotes: Vec<Emote>,
+    /// Number of emotes the provider reports, can differ from the loaded ones
+    pub declared_count: usize,
+}
+
+impl EmoteSet {
+    /// Builds a pseudo set out of the custom url emotes from the settings
+    pub fn custom(custom_emotes: &[CustomEmote]) -> Self {
+        let emotes = custom_emotes
+            .iter()
+            .filter_map(|custom| match Url::parse(&custom.url) {
+                Ok(url) => Some(Emote {
+                    name: custom.name.clone(),
+                    identifier: content_identifier("custom", url.as_str()),
+                    url,
+                    zero_width: false,
+                    provider: "custom",
+                    scale: None,
+                }),
+                Err(e) => {
+                    log::error!("Failed to parse url {}: {e}", custom.url);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        Self {
+            id: "custom".to_string(),
+            name: "Custom".to_string(),
+            declared_count: emotes.len(),
+            emotes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, VariantArray, VariantNames)]
+pub enum ImageFormat {
+    Avif,
+    WebP,
+    Gif,
+    Png,
+}
+
+impl ImageFormat {
+    /// Whether our decoder can handle the format, see [`crate::giftex::decode`]
+    pub fn decodable(self, animated: bool) -> bool {
+        match self {
+            // the avif decoder only yields the first frame
+            Self::Avif => cfg!(feature = "avif") && !animated,
+            Self::WebP | Self::Gif | Self::Png => true,
+        }
+    }
+}
+
+/// Preferences for picking one of the files an emote is available as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSelection {
+    /// Formats in order of preference, missing ones are never used
+    pub formats: Vec<ImageFormat>,
+    /// 1 to 4, the size multiplier of the file
+    pub scale: u32,
+    /// Pick the smallest supported file instead of following the format order
+    pub low_bandwidth: bool,
+}
+
+/// A downloadable file of an emote
+pub struct FileCandidate {
+    pub format: ImageFormat,
+    pub scale: u32,
+    pub size: u32,
+}
+
+impl FileSelection {
+    /// Index of the best candidate. Files closer to the desired scale win over the format order,
+    /// with `low_bandwidth` the smallest file wins over both
+    pub fn pick(&self, candidates: &[FileCandidate], animated: bool) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.format.decodable(animated))
+            .filter_map(|(i, c)| {
+                let rank = self.formats.iter().position(|f| *f == c.format)?;
+                let scale_distance = c.scale.abs_diff(self.scale);
+                let key = if self.low_bandwidth {
+                    (c.size, scale_distance, rank)
+                } else {
+                    (0, scale_distance, rank)
+                };
+                Some((i, key))
+            })
+            .min_by_key(|(_, key)| *key)
+            .map(|(i, _)| i)
+    }
+}
+
+pub trait EmoteProvider: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &'static str;
+    /// Whether this provider is responsible for an emote set id
+    fn handles(&self, id: &str) -> bool;
+    /// Downloads an emote set and resolves every emote to a file url,
+    /// see [`file_selection`] for the file to pick
+    fn fetch_set(&self, id: &str) -> Result<EmoteSet>;
+    /// Downloads a loaded set again, providers can override this to skip unchanged sets
+    fn refresh(&self, set: &EmoteSet) -> Result<EmoteSet> {
+        self.fetch_set(&set.id)
+    }
+    /// Web page of an emote set, shown as link in the settings
+    fn set_url(&self, _id: &str) -> Option<String> {
+        None
+    }
+    /// File url of a single emote picked with `selection`, e.g. for an emote link that is
+    /// added as custom emote
+    fn emote_url(&self, _emote_id: &str, _selection: &FileSelection) -> Option<String> {
+        None
+    }
+    /// Defaults for emote file downloads, can be overridden in the settings
+    fn download_limits(&self) -> DownloadLimits {
+        DownloadLimits::default()
+    }
+    /// Id of the set with the personal emotes of a game account, `None` if the provider
+    /// doesn't know the account
+    fn personal_set_id(&self, _account_name: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+static REGISTRY: RwLock<Vec<&'static dyn EmoteProvider>> = const { RwLock::new(Vec::new()) };
+
+/// Later registrations take precedence, so specific providers can shadow generic ones
+pub fn register(provider: &'static dyn EmoteProvider) {
+    log::info!("Registering emote provider {}", provider.name());
+    REGISTRY.write().unwrap().push(provider);
+}
+
+pub fn unregister_all() {
+    REGISTRY.write().unwrap().clear();
+}
+
+/// Current file preferences from the settings
+pub fn file_selection() -> FileSelection {
+    Settings::get().file_selection()
+}
+
+pub fn provider_by_name(name: &str) -> Option<&'static dyn EmoteProvider> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .find(|provider| provider.name() == name)
+        .copied()
+}
+
+/// Names of all registered providers, in registration order
+pub fn provider_names() -> Vec<&'static str> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .map(|provider| provider.name())
+        .collect()
+}
+
+pub fn provider_for(id: &str) -> Option<&'static dyn EmoteProvider> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|provider| provider.handles(id))
+        .copied()
+}
+
+pub fn fetch_set(id: &str) -> Result<EmoteSet> {
+    progress::SETS.update(id, State::Downloading);
+    let result = provider_for(id)
+        .with_context(|| format!("No provider for emote set {id}"))
+        .and_then(|provider| {
+            provider
+                .fetch_set(id)
+                .with_context(|| format!("{} emote set {id}", provider.name()))
+        });
+    progress::SETS.finish(id, &result);
+    result
+}
+
+/// Asks the providers for the personal emote set of a game account, the latest registered first
+pub fn personal_set_id(account_name: &str) -> Result<Option<String>> {
+    let providers = REGISTRY.read().unwrap().clone();
+    for provider in providers.into_iter().rev() {
+        if let Some(id) = provider.personal_set_id(account_name)? {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+pub fn refresh_set(set: &EmoteSet) -> Result<EmoteSet> {
+    progress::SETS.update(&set.id, State::Downloading);
+    let result = provider_for(&set.id)
+        .with_context(|| format!("No provider for emote set {}", set.id))
+        .and_then(|provider| {
+            provider
+                .refresh(set)
+                .with_context(|| format!("{} emote set {}", provider.name(), set.id))
+        });
+    progress::SETS.finish(&set.id, &result);
+    result
+}
+
+/// Orders the emote sets by priority: custom emotes first,
+/// then the configured sets in the order of the settings and everything else last
+pub fn by_priority<'a>(
+    emote_sets: &'a [EmoteSet],
+    custom: &'a EmoteSet,
+    emote_set_ids: &[String],
+) -> Vec<&'a EmoteSet> {
+    let mut sorted = emote_sets.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|set| {
+        emote_set_ids
+            .iter()
+            .position(|id| id == &set.id)
+            .unwrap_or(usize::MAX)
+    });
+    sorted.insert(0, custom);
+    sorted
+}
+
+/// Finds the emote with the highest priority for a name, see [`by_priority`]
+pub fn find_emote<'a>(emote_sets: &[&'a EmoteSet], name: &str) -> Option<&'a Emote> {
+    find_emote_with_set(emote_sets, name, &[]).map(|(_, emote)| emote)
+}
+
+/// Like [`find_emote`], also returns the set the emote was found in.
+/// Sets in which the name is disabled are skipped, so a lower priority set can still provide it
+pub fn find_emote_with_set<'a>(
+    emote_sets: &[&'a EmoteSet],
+    name: &str,
+    disabled: &[DisabledEmote],
+) -> Option<(&'a EmoteSet, &'a Emote)> {
+    emote_sets
+        .iter()
+        .filter(|set| !disabled.iter().any(|d| d.matches(&set.id, name)))
+        .find_map(|set| {
+            set.emotes
+                .iter()
+                .find(|emote| emote.name == name)
+                .map(|emote| (*set, emote))
+        })
+}
+
+/// Local emote sets are referenced by the path of their json file
+pub fn is_local(id: &str) -> bool {
+    id.ends_with(".json")
+}
+
+pub fn download_emote_sets(emote_set_ids: &[String], use_global: bool) -> Vec<EmoteSet> {
+    let mut it: Box<dyn Iterator<Item = _>> = Box::new(emote_set_ids.iter().map(String::as_str));
+    if use_global {
+        it = Box::new(it.chain(iter::once("global")));
+    }
+    let ids = it.collect::<Vec<_>>();
+    let (ok, err): (Vec<_>, Vec<_>) = net::fetch_all(&ids, |id| fetch_set(id))
+        .into_iter()
+        .partition(Result::is_ok);
+    for e in err {
+        // noop
+        if let Err(e) = e {
+            log::error!("Failed to download {e:#}");
+        }
+    }
+    ok.into_iter().map(Result::unwrap).collect()
+}
@@ -0,0 +1,135 @@
+use crate::provider::{self, Emote, EmoteSet};
+use crate::settings::Settings;
+use crate::util::e;
+use crate::{
+    EMOTE_COUNT, EMOTE_SETS, LOADED_EMOTES, SPAWN_QUEUE, badge, idle, load_emote, setting_path,
+    spawn_emote,
+};
+use nexus::imgui::{DragDropFlags, DragDropTarget, MouseButton, Ui, Window};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::Ordering;
+
+pub const MAX_FAVORITES: usize = 10;
+/// Drag and drop payload of the emote browser, carries the [`payload_key`] of the emote
+pub const FAVORITE_PAYLOAD: &str = "EMOTE_FAVORITE";
+const ICON_SIZE: f32 = 32.0;
+
+/// Payloads have to be `Copy`, so the emote is identified by a hash of its set and name.
+/// Indices would point at another emote once the sets are reloaded during the drag
+pub fn payload_key(set: &EmoteSet, emote: &Emote) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (&set.id, &emote.name).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Small window with the favorite emotes.
+/// Click copies the name, right click spawns a local preview and shift click removes it.
+pub fn render(ui: &Ui) {
+    let settings = Settings::get().clone();
+    if !settings.show_quickbar {
+        return;
+    }
+    let mut remove = None;
+    let mut preview = None;
+    let mut dropped = None;
+    Window::new(e("Emote Quickbar"))
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(ui, || {
+            let mut loaded = LOADED_EMOTES.lock().unwrap();
+            let emote_sets = EMOTE_SETS.lock().unwrap();
+            let custom = EmoteSet::custom(&settings.custom_emotes);
+            let sorted = provider::by_priority(&emote_sets, &custom, &settings.emote_set_ids);
+            for (i, name) in settings.favorites.iter().enumerate() {
+                if i > 0 {
+                    ui.same_line();
+                }
+                let emote = provider::find_emote(&sorted, name);
+                if let Some(emote) = emote {
+                    load_emote(&mut loaded, emote);
+                }
+                let gif = emote.and_then(|emote| {
+                    loaded
+                        .iter()
+                        .find(|(l, _)| l == &emote.identifier)
+                        .and_then(|(_, gif)| gif.as_ref())
+                });
+                if let Some(gif) = gif {
+                    let scale = ICON_SIZE / gif.height;
+                    gif.frames[0]
+                        .image([gif.width * scale, ICON_SIZE])
+                        .build(ui);
+                } else {
+                    ui.button(format!("{name}##favorite{i}"));
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(format!(
+                        "{name}\n{}",
+                        e("Click to copy, right click to preview, shift click to remove")
+                    ));
+                }
+                if ui.is_item_clicked() {
+                    if ui.io().key_shift {
+                        remove = Some(i);
+                    } else {
+                        ui.set_clipboard_text(name);
+                    }
+                }
+                if ui.is_item_clicked_with_button(MouseButton::Right) {
+                    preview = emote.cloned();
+                }
+            }
+            if settings.favorites.len() < MAX_FAVORITES {
+                if !settings.favorites.is_empty() {
+                    ui.same_line();
+                }
+                ui.button("+##favoritedrop");
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(e("Drag emotes here from the emote browser"));
+                }
+                if let Some(target) = DragDropTarget::new(ui) {
+                    if let Some(Ok(payload)) =
+                        target.accept_payload::<u64, _>(FAVORITE_PAYLOAD, DragDropFlags::empty())
+                    {
+                        dropped = emote_sets.iter().find_map(|set| {
+                            set.emotes
+                                .iter()
+                                .find(|emote| payload_key(set, emote) == payload.data)
+                                .map(|emote| emote.name.clone())
+                        });
+                    }
+                    target.pop();
+                }
+            }
+        });
+
+    if let Some(emote) = preview {
+        let mut spawns = Vec::new();
+        let size_factor = settings.emote_size_factor(&emote.name);
+        spawn_emote(&mut spawns, &emote, size_factor, None, false);
+        idle::activity();
+        let mut queue = SPAWN_QUEUE.lock().unwrap();
+        EMOTE_COUNT.fetch_add(spawns.len(), Ordering::Relaxed);
+        for spawn in spawns {
+            if queue.push("", spawn) {
+                badge::suppressed(1);
+            }
+        }
+    }
+    if remove.is_none() && dropped.is_none() {
+        return;
+    }
+    let mut settings = Settings::get();
+    if let Some(i) = remove {
+        settings.favorites.remove(i);
+    }
+    if let Some(name) = dropped
+        && !settings.favorites.contains(&name)
+        && settings.favorites.len() < MAX_FAVORITES
+    {
+        settings.favorites.push(name);
+    }
+    if let Err(e) = settings.save(&setting_path()) {
+        log::error!("Failed to save settings: {e}");
+    }
+}
@@ -0,0 +1,442 @@
+//! Trigger rules for users that don't want to script, e.g. "spawn 3 Clap in squad chat when
+//! someone writes stack". Rules live in `rules.json` in the addon directory and are edited in
+//! the options. Patterns are case insensitive regular expressions.
+//! The guild message of the day runs through the same engine as a built-in rule, offering the
+//! emote sets it names instead of spawning emotes.
+use crate::animation::Style;
+use crate::chat_events::{Message, MessageSource};
+use crate::commands;
+use crate::filter::normalize_account;
+use crate::setting_dir;
+use crate::settings::{Channel, Settings, style_combos};
+use crate::util::{UiExt, e};
+use nexus::imgui::{TreeNodeFlags, Ui};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use strum::{VariantArray, VariantNames};
+use time::UtcDateTime;
+use windows::Win32::System::Time::{GetTimeZoneInformation, TIME_ZONE_INFORMATION};
+
+const RULES_FILE: &str = "rules.json";
+/// Spawns of a single rule, more would flood the screen with one message
+const MAX_COUNT: u32 = 20;
+/// `GetTimeZoneInformation` result while daylight saving time applies
+const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+
+static RULES: Mutex<Vec<Rule>> = const { Mutex::new(Vec::new()) };
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    pub enabled: bool,
+    /// Empty matches all channels
+    pub channels: Vec<Channel>,
+    /// Account names, empty matches everyone
+    pub senders: Vec<String>,
+    /// Regular expression searched in the message, ignoring case. Empty matches every message
+    pub pattern: String,
+    /// Compiled `pattern`, `None` while it is empty or invalid
+    #[serde(skip)]
+    regex: Option<Regex>,
+    #[serde(skip)]
+    pattern_error: Option<String>,
+    /// Local hours the rule is active in, the same hour twice means all day
+    pub from_hour: u8,
+    pub to_hour: u8,
+    pub emote: String,
+    /// Overrides the style of the emote set
+    pub style: Option<Style>,
+    pub count: u32,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            channels: Vec::new(),
+            senders: Vec::new(),
+            pattern: String::new(),
+            regex: None,
+            pattern_error: None,
+            from_hour: 0,
+            to_hour: 0,
+            emote: String::new(),
+            style: None,
+            count: 1,
+        }
+    }
+}
+
+/// What a matching rule does
+pub enum Action {
+    Spawn {
+        emote: String,
+        style: Option<Style>,
+        count: u32,
+    },
+    /// Emote sets named as `7tv:<id>` in the message of the day of a guild
+    OfferGuildSets { ids: Vec<String>, guild_index: u32 },
+}
+
+impl Rule {
+    fn compile(&mut self) {
+        self.regex = None;
+        self.pattern_error = None;
+        if self.pattern.is_empty() {
+            return;
+        }
+        match RegexBuilder::new(&self.pattern)
+            .case_insensitive(true)
+            .build()
+        {
+            Ok(regex) => self.regex = Some(regex),
+            Err(e) => self.pattern_error = Some(e.to_string()),
+        }
+    }
+
+    /// An invalid pattern matches nothing
+    fn pattern_matches(&self, content: &str) -> bool {
+        self.pattern.is_empty() || self.regex.as_ref().is_some_and(|r| r.is_match(content))
+    }
+
+    fn matches(&self, chat: &Message, content: &str, channel: Option<Channel>, hour: u8) -> bool {
+        if !self.enabled || self.emote.is_empty() {
+            return false;
+        }
+        if !self.channels.is_empty() && !channel.is_some_and(|c| self.channels.contains(&c)) {
+            return false;
+        }
+        if !self.senders.is_empty() {
+            let Some(account) = chat.account_name().map(normalize_account) else {
+                return false;
+            };
+            if !self
+                .senders
+                .iter()
+                .any(|s| normalize_account(s).eq_ignore_ascii_case(account))
+            {
+                return false;
+            }
+        }
+        if !self.pattern_matches(content) {
+            return false;
+        }
+        in_hours(self.from_hour, self.to_hour, hour)
+    }
+}
+
+fn rules_path() -> PathBuf {
+    setting_dir().join(RULES_FILE)
+}
+
+pub fn load() {
+    let mut rules: Vec<Rule> = match std::fs::read_to_string(rules_path()) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Failed to parse {RULES_FILE}: {e}");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    for rule in &mut rules {
+        rule.count = rule.count.clamp(1, MAX_COUNT);
+        rule.compile();
+        if let Some(error) = &rule.pattern_error {
+            log::error!("Invalid pattern of rule {}: {error}", rule.name);
+        }
+    }
+    *RULES.lock().unwrap() = rules;
+}
+
+fn save(rules: &[Rule]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(setting_dir())?;
+    std::fs::write(rules_path(), serde_json::to_string_pretty(rules)?)?;
+    Ok(())
+}
+
+pub fn clear() {
+    RULES.lock().unwrap().clear();
+}
+
+/// Actions of all rules that match the message, the built-in rule first
+pub fn evaluate(
+    chat: &Message,
+    content: &str,
+    channel: Option<Channel>,
+    settings: &Settings,
+) -> Vec<Action> {
+    let mut actions = guild_motd_rule(chat, settings)
+        .into_iter()
+        .collect::<Vec<_>>();
+    let rules = RULES.lock().unwrap();
+    if rules.is_empty() {
+        return actions;
+    }
+    let hour = local_hour();
+    actions.extend(
+        rules
+            .iter()
+            .filter(|rule| rule.matches(chat, content, channel, hour))
+            .map(|rule| {
+                log::info!("Rule {} matched", rule.name);
+                Action::Spawn {
+                    emote: rule.emote.clone(),
+                    style: rule.style,
+                    count: rule.count.clamp(1, MAX_COUNT),
+                }
+            }),
+    );
+    actions
+}
+
+/// Offers the emote sets named as `7tv:<id>` in a guild message of the day
+fn guild_motd_rule(chat: &Message, settings: &Settings) -> Option<Action> {
+    let MessageSource::GuildMotD {
+        content,
+        guild_index,
+    } = &chat.source
+    else {
+        return None;
+    };
+    if !settings.guild_motd_sets {
+        return None;
+    }
+    let ids = content
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix("7tv:"))
+        .filter(|id| !id.is_empty() && commands::is_valid_id(id))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    (!ids.is_empty()).then_some(Action::OfferGuildSets {
+        ids,
+        guild_index: *guild_index,
+    })
+}
+
+fn in_hours(from: u8, to: u8, hour: u8) -> bool {
+    match from.cmp(&to) {
+        std::cmp::Ordering::Equal => true,
+        std::cmp::Ordering::Less => (from..to).contains(&hour),
+        // the window wraps around midnight
+        std::cmp::Ordering::Greater => hour >= from || hour < to,
+    }
+}
+
+fn local_hour() -> u8 {
+    let now = UtcDateTime::now();
+    let utc_minutes = now.hour() as i32 * 60 + now.minute() as i32;
+    let mut info = TIME_ZONE_INFORMATION::default();
+    // SAFETY: `info` is a valid out pointer for the duration of the call
+    let id = unsafe { GetTimeZoneInformation(&mut info) };
+    let bias = info.Bias
+        + if id == TIME_ZONE_ID_DAYLIGHT {
+            info.DaylightBias
+        } else {
+            info.StandardBias
+        };
+    ((utc_minutes - bias).rem_euclid(24 * 60) / 60) as u8
+}
+
+pub fn render(ui: &Ui) {
+    if !ui.collapsing_header(e("Trigger rules"), TreeNodeFlags::empty()) {
+        return;
+    }
+    ui.text_wrapped(e(
+        "Spawn emotes when a chat message matches a rule, e.g. Clap when someone writes *stack* in squad chat",
+    ));
+    let mut rules = RULES.lock().unwrap();
+    let mut to_remove = None;
+    for (i, rule) in rules.iter_mut().enumerate() {
+        let title = if rule.name.is_empty() {
+            format!("{} {}", e("Rule"), i + 1)
+        } else {
+            rule.name.clone()
+        };
+        if !ui.collapsing_header(format!("{title}##rule{i}"), TreeNodeFlags::empty()) {
+            continue;
+        }
+        ui.checkbox(
+            e("Enabled") + &format!("##ruleenabled{i}"),
+            &mut rule.enabled,
+        );
+        ui.input_text(e("Name") + &format!("##rulename{i}"), &mut rule.name)
+            .build();
+        rule_conditions_ui(rule, i, ui);
+        rule_actions_ui(rule, i, ui);
+        if ui.button(e("Remove") + &format!("##ruleremove{i}")) {
+            to_remove = Some(i);
+        }
+    }
+    if let Some(i) = to_remove {
+        rules.remove(i);
+    }
+    if ui.button(e("Add rule")) {
+        rules.push(Rule::default());
+    }
+    ui.same_line();
+    if ui.button(e("Save rules")) {
+        match save(&rules) {
+            Ok(()) => log::info!("Saved {} rules", rules.len()),
+            Err(e) => log::error!("Failed to save {RULES_FILE}: {e}"),
+        }
+    }
+    ui.help_marker(|| {
+        ui.tooltip_text(e(
+            "Rules apply right away, saving keeps them for the next start",
+        ));
+    });
+}
+
+fn rule_conditions_ui(rule: &mut Rule, i: usize, ui: &Ui) {
+    if ui
+        .input_text(
+            e("Pattern") + &format!("##rulepattern{i}"),
+            &mut rule.pattern,
+        )
+        .build()
+    {
+        rule.compile();
+    }
+    ui.help_marker(|| {
+        ui.tooltip_text(e(
+            "Regular expression searched in the message, ignoring case, e.g. \\bstack\\b. Empty matches every message",
+        ));
+    });
+    if let Some(error) = &rule.pattern_error {
+        ui.text_colored([0.9, 0.3, 0.3, 1.0], error);
+    }
+    let mut senders = rule.senders.join(", ");
+    if ui
+        .input_text(e("Senders") + &format!("##rulesenders{i}"), &mut senders)
+        .build()
+    {
+        rule.senders = senders
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    ui.help_marker(|| {
+        ui.tooltip_text(e("Comma separated account names, empty matches everyone"));
+    });
+    ui.text(e("Channels"));
+    ui.help_marker(|| {
+        ui.tooltip_text(e("None selected matches all channels"));
+    });
+    for (channel, name) in <Channel as VariantArray>::VARIANTS
+        .iter()
+        .zip(<Channel as VariantNames>::VARIANTS)
+    {
+        ui.same_line();
+        let mut enabled = rule.channels.contains(channel);
+        if ui.checkbox(format!("{name}##rulechannel{i}"), &mut enabled) {
+            if enabled {
+                rule.channels.push(*channel);
+            } else {
+                rule.channels.retain(|c| c != channel);
+            }
+        }
+    }
+    let mut from = rule.from_hour as i32;
+    let mut to = rule.to_hour as i32;
+    if ui
+        .input_int(e("From hour") + &format!("##rulefrom{i}"), &mut from)
+        .build()
+    {
+        rule.from_hour = from.clamp(0, 23) as u8;
+    }
+    if ui
+        .input_int(e("To hour") + &format!("##ruleto{i}"), &mut to)
+        .build()
+    {
+        rule.to_hour = to.clamp(0, 23) as u8;
+    }
+    ui.help_marker(|| {
+        ui.tooltip_text(e(
+            "Local time the rule is active, the same hour twice means all day",
+        ));
+    });
+}
+
+fn rule_actions_ui(rule: &mut Rule, i: usize, ui: &Ui) {
+    ui.input_text(e("Emote") + &format!("##ruleemote{i}"), &mut rule.emote)
+        .build();
+    let mut count = rule.count as i32;
+    if ui
+        .input_int(e("Count") + &format!("##rulecount{i}"), &mut count)
+        .build()
+    {
+        rule.count = count.clamp(1, MAX_COUNT as i32) as u32;
+    }
+    let mut own_style = rule.style.is_some();
+    if ui.checkbox(e("Own style") + &format!("##rulestyle{i}"), &mut own_style) {
+        rule.style = own_style.then(Style::default);
+    }
+    if let Some(style) = &mut rule.style {
+        let id = format!("rulestyle{i}");
+        let _id = ui.push_id(id.as_str());
+        style_combos(&mut style.animation, &mut style.backdrop, ui);
+        ui.checkbox(e("Motion trail"), &mut style.trail);
+        ui.color_edit4(e("Color"), &mut style.color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, in_hours};
+
+    fn rule(pattern: &str) -> Rule {
+        let mut rule = Rule {
+            pattern: pattern.to_string(),
+            ..Rule::default()
+        };
+        rule.compile();
+        rule
+    }
+
+    #[test]
+    fn pattern_ignores_case() {
+        let rule = rule(r"\bstack\b");
+        assert!(rule.pattern_matches("STACK on tag"));
+        assert!(!rule.pattern_matches("stacking"));
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        assert!(rule("").pattern_matches("anything"));
+    }
+
+    #[test]
+    fn invalid_pattern_matches_nothing() {
+        let rule = rule("(unclosed");
+        assert!(rule.pattern_error.is_some());
+        assert!(!rule.pattern_matches("(unclosed"));
+    }
+
+    #[test]
+    fn hours_within_a_day() {
+        assert!(in_hours(8, 17, 8));
+        assert!(in_hours(8, 17, 16));
+        assert!(!in_hours(8, 17, 17));
+        assert!(!in_hours(8, 17, 3));
+    }
+
+    #[test]
+    fn hours_wrap_around_midnight() {
+        assert!(in_hours(22, 2, 23));
+        assert!(in_hours(22, 2, 1));
+        assert!(!in_hours(22, 2, 2));
+        assert!(!in_hours(22, 2, 12));
+    }
+
+    #[test]
+    fn same_hour_is_all_day() {
+        for hour in 0..24 {
+            assert!(in_hours(5, 5, hour));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Crash loop protection. A sentinel file exists from the start of the load until the first
+//! frame is rendered, finding it on load means the last load crashed the game. The addon then
+//! starts without downloads and floating emotes until the user resumes it.
+//! Crashes later in the session have nothing to do with the load and are ignored.
+use crate::settings::Settings;
+use crate::util::e;
+use crate::{health, reload_emote_sets, setting_dir};
+use nexus::imgui::{Ui, Window};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ACTIVE: AtomicBool = const { AtomicBool::new(false) };
+static DIALOG_OPEN: AtomicBool = const { AtomicBool::new(false) };
+/// Set once the first frame after the load was rendered
+static LOADED: AtomicBool = const { AtomicBool::new(false) };
+
+fn sentinel_path() -> PathBuf {
+    setting_dir().join("loaded.sentinel")
+}
+
+/// Enters safe mode if the previous load didn't finish, then marks this one as loading
+pub fn begin_load() {
+    let path = sentinel_path();
+    if path.exists() {
+        log::warn!("Previous load did not finish, starting in safe mode");
+        ACTIVE.store(true, Ordering::Relaxed);
+        DIALOG_OPEN.store(true, Ordering::Relaxed);
+    }
+    if let Err(e) = std::fs::create_dir_all(setting_dir()).and_then(|_| std::fs::write(&path, [])) {
+        log::error!("Failed to write {}: {e}", path.to_string_lossy());
+    }
+}
+
+/// Called every frame, the first one means the load went through
+pub fn frame_rendered() {
+    if !LOADED.swap(true, Ordering::Relaxed) {
+        remove_sentinel();
+    }
+}
+
+/// Called last on unload, an unload before the first frame didn't crash either
+pub fn finish_unload() {
+    if !LOADED.swap(false, Ordering::Relaxed) {
+        remove_sentinel();
+    }
+}
+
+fn remove_sentinel() {
+    let path = sentinel_path();
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::error!("Failed to remove {}: {e}", path.to_string_lossy());
+    }
+}
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+fn resume(settings: Settings) {
+    log::info!("Leaving safe mode");
+    ACTIVE.store(false, Ordering::Relaxed);
+    // the cleanup was skipped on load
+    reload_emote_sets(settings, true);
+    health::run();
+}
+
+pub fn render(ui: &Ui) {
+    if !DIALOG_OPEN.load(Ordering::Relaxed) {
+        return;
+    }
+    Window::new(e("Emotes Safe Mode"))
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(ui, || {
+            ui.text(e("The game crashed while emotes were loading last time."));
+            ui.text(e(
+                "Emotes are neither downloaded nor shown until you resume, in case one of them caused a crash.",
+            ));
+            if ui.button(e("Resume")) {
+                resume(Settings::get().clone());
+                DIALOG_OPEN.store(false, Ordering::Relaxed);
+            }
+            ui.same_line();
+            if ui.button(e("Stay in safe mode")) {
+                DIALOG_OPEN.store(false, Ordering::Relaxed);
+            }
+        });
+}
+
+/// Lets the user leave safe mode after closing the dialog
+pub fn options_ui(ui: &Ui, settings: &Settings) {
+    if !is_active() {
+        return;
+    }
+    ui.text_colored([1.0, 0.6, 0.0, 1.0], e("Safe mode, emotes are disabled"));
+    ui.same_line();
+    if ui.button(e("Resume##safemode")) {
+        resume(settings.clone());
+    }
+}
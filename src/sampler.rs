@@ -0,0 +1,153 @@
+//! Sampler states for the emote windows. The imgui backend binds a linear
+//! sampler that never reads past the first mip level, emote windows swap in
+//! their own through draw list callbacks and let the backend reset its render
+//! state afterwards.
+use nexus::imgui::sys;
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+use std::sync::Mutex;
+use strum::{VariantArray, VariantNames};
+use windows::Win32::Graphics::Direct3D11::*;
+
+/// `ImDrawCallback_ResetRenderState` of imgui.h, the backend sets up its own state again
+/// instead of calling it
+const RESET_RENDER_STATE: isize = -1;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Filtering {
+    /// Smooth, blends between mip levels
+    #[default]
+    Linear,
+    /// Sharp pixels, suits pixel art emotes
+    Point,
+}
+
+struct Samplers {
+    context: ID3D11DeviceContext,
+    linear: ID3D11SamplerState,
+    point: ID3D11SamplerState,
+}
+
+/// Draw list of the current window or the one behind all windows
+#[derive(Debug, Clone, Copy)]
+pub enum DrawList {
+    Window,
+    Background,
+}
+
+impl DrawList {
+    fn raw(self) -> *mut sys::ImDrawList {
+        // SAFETY: only called while a frame is built, the window one within a window
+        unsafe {
+            match self {
+                DrawList::Window => sys::igGetWindowDrawList(),
+                DrawList::Background => sys::igGetBackgroundDrawList(),
+            }
+        }
+    }
+}
+
+static SAMPLERS: Mutex<Option<Samplers>> = const { Mutex::new(None) };
+
+fn create(
+    device: &ID3D11Device,
+    filter: D3D11_FILTER,
+    address: D3D11_TEXTURE_ADDRESS_MODE,
+    max_lod: f32,
+) -> anyhow::Result<ID3D11SamplerState> {
+    let desc = D3D11_SAMPLER_DESC {
+        Filter: filter,
+        AddressU: address,
+        AddressV: address,
+        AddressW: address,
+        MipLODBias: 0.0,
+        MaxAnisotropy: 0,
+        ComparisonFunc: D3D11_COMPARISON_ALWAYS,
+        BorderColor: [0.0; 4],
+        MinLOD: 0.0,
+        MaxLOD: max_lod,
+    };
+    let mut sampler = None;
+    unsafe {
+        device.CreateSamplerState(&desc, Some(&mut sampler))?;
+    }
+    Ok(sampler.ok_or_else(windows::core::Error::from_win32)?)
+}
+
+fn init(device: &ID3D11Device) -> anyhow::Result<Samplers> {
+    Ok(Samplers {
+        context: unsafe { device.GetImmediateContext()? },
+        // frames at the edge of an atlas would pick up the opposite edge with wrapping
+        linear: create(
+            device,
+            D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+            D3D11_TEXTURE_ADDRESS_CLAMP,
+            D3D11_FLOAT32_MAX,
+        )?,
+        point: create(
+            device,
+            D3D11_FILTER_MIN_MAG_MIP_POINT,
+            D3D11_TEXTURE_ADDRESS_CLAMP,
+            D3D11_FLOAT32_MAX,
+        )?,
+    })
+}
+
+/// Runs on the render thread while the backend draws, the filtering is the callback data
+unsafe extern "C" fn bind_callback(_list: *const sys::ImDrawList, cmd: *const sys::ImDrawCmd) {
+    // SAFETY: the backend passes the command the callback was added with
+    let index = unsafe { (*cmd).UserCallbackData } as usize;
+    let Some(filtering) = <Filtering as VariantArray>::VARIANTS.get(index) else {
+        return;
+    };
+    if let Some(samplers) = SAMPLERS.lock().unwrap().as_ref() {
+        let sampler = match filtering {
+            Filtering::Linear => &samplers.linear,
+            Filtering::Point => &samplers.point,
+        };
+        // SAFETY: the context belongs to the device the backend is drawing with
+        unsafe {
+            samplers
+                .context
+                .PSSetSamplers(0, Some(&[Some(sampler.clone())]));
+        }
+    }
+}
+
+/// Draws the following images of `draw_list` with `filtering`, has to be paired with [`restore`]
+pub fn bind(device: &ID3D11Device, draw_list: DrawList, filtering: Filtering) {
+    let mut samplers = SAMPLERS.lock().unwrap();
+    if samplers.is_none() {
+        match init(device) {
+            Ok(created) => *samplers = Some(created),
+            Err(e) => {
+                log::error!("Failed to create sampler states: {e}");
+                return;
+            }
+        }
+    }
+    let index = <Filtering as VariantArray>::VARIANTS
+        .iter()
+        .position(|f| *f == filtering)
+        .unwrap_or_default();
+    // SAFETY: the draw list is valid for the current frame, the data is no pointer
+    unsafe {
+        sys::ImDrawList_AddCallback(draw_list.raw(), Some(bind_callback), index as *mut c_void);
+    }
+}
+
+/// Lets the imgui backend restore its render state for everything drawn after the emotes
+pub fn restore(draw_list: DrawList) {
+    // SAFETY: the backend compares the callback against this value and never calls it
+    unsafe {
+        let reset = std::mem::transmute::<isize, sys::ImDrawCallback>(RESET_RENDER_STATE);
+        sys::ImDrawList_AddCallback(draw_list.raw(), reset, std::ptr::null_mut());
+    }
+}
+
+/// Sampler states belong to the device, they have to go with it on unload
+pub fn clear() {
+    SAMPLERS.lock().unwrap().take();
+}
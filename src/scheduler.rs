@@ -0,0 +1,93 @@
+//! Runs emote downloads with the concurrency and politeness limits of their provider
+use crate::background::{Job, Priority, RunningWorker, Worker, default_thread_count};
+use crate::provider::{DownloadLimits, provider_by_name};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// One worker pool per provider, created on first use
+static POOLS: Mutex<Vec<(String, RunningWorker)>> = const { Mutex::new(Vec::new()) };
+/// Limits from the settings, they override the defaults of the providers
+static OVERRIDES: Mutex<Option<HashMap<String, DownloadLimits>>> = const { Mutex::new(None) };
+/// Bandwidth saver, every provider downloads one file at a time
+static SINGLE_DOWNLOAD: AtomicBool = const { AtomicBool::new(false) };
+/// Pools being torn down, joined on shutdown
+static THREADS: Mutex<Vec<JoinHandle<()>>> = const { Mutex::new(Vec::new()) };
+
+/// Runs `job` on its own thread that `shutdown` waits for
+fn spawn_thread(job: impl FnOnce() + Send + 'static) {
+    let mut threads = THREADS.lock().unwrap();
+    threads.retain(|thread| !thread.is_finished());
+    threads.push(thread::spawn(job));
+}
+
+pub fn limits_for(provider: &str) -> DownloadLimits {
+    let mut limits = OVERRIDES
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|overrides| overrides.get(provider).copied())
+        .unwrap_or_else(|| {
+            provider_by_name(provider).map_or_else(DownloadLimits::default, |p| p.download_limits())
+        });
+    if SINGLE_DOWNLOAD.load(Ordering::Relaxed) {
+        limits.max_concurrent = 1;
+    }
+    limits
+}
+
+/// Applies new limits, running downloads finish with the old ones.
+/// The pools are only rebuilt when the limits changed
+pub fn configure(overrides: &HashMap<String, DownloadLimits>, single_download: bool) {
+    let single_changed =
+        SINGLE_DOWNLOAD.swap(single_download, Ordering::Relaxed) != single_download;
+    let mut current = OVERRIDES.lock().unwrap();
+    if !single_changed && current.as_ref() == Some(overrides) {
+        return;
+    }
+    *current = Some(overrides.clone());
+    drop(current);
+    let pools = std::mem::take(&mut *POOLS.lock().unwrap());
+    // dropping joins the threads, don't block the caller with that
+    if !pools.is_empty() {
+        spawn_thread(move || drop(pools));
+    }
+}
+
+/// Emotes shown in chat go ahead of preloads
+pub fn spawn_with_priority(provider: &str, priority: Priority, job: Job) {
+    let limits = limits_for(provider);
+    let threads = match limits.max_concurrent {
+        0 => default_thread_count(),
+        n => n as usize,
+    };
+    let mut pools = POOLS.lock().unwrap();
+    let index = match pools.iter().position(|(name, _)| name == provider) {
+        Some(index) => index,
+        None => {
+            log::info!("Starting {threads} download threads for {provider}");
+            let worker = Worker::new()
+                .name(format!("{provider} downloads"))
+                .threads(threads)
+                .interval(Duration::from_millis(limits.interval_ms as u64))
+                .run();
+            pools.push((provider.to_string(), worker));
+            pools.len() - 1
+        }
+    };
+    pools[index].1.spawn_with_priority(priority, job);
+}
+
+/// Waits for all queued downloads
+pub fn shutdown() {
+    drop(std::mem::take(&mut *POOLS.lock().unwrap()));
+    let threads = std::mem::take(&mut *THREADS.lock().unwrap());
+    for thread in threads {
+        if thread.join().is_err() {
+            log::error!("A download thread panicked");
+        }
+    }
+    *OVERRIDES.lock().unwrap() = None;
+}
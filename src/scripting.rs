@@ -0,0 +1,203 @@
+//! User scripts for custom triggers, e.g. spawning Clap when the commander says "stack".
+//! Every `*.rhai` file in the `scripts` folder of the addon directory can define
+//!
+//! ```rhai
+//! fn on_message(author, channel, content) {
+//!     if channel == "Squad" && content.contains("stack") {
+//!         spawn("Clap");
+//!     }
+//! }
+//! ```
+//!
+//! Scripts can call `spawn(name)`, `setting(name)` and `log(text)`. Emotes spawned by a script
+//! go through the same sets, aliases and filters as emotes written in chat.
+use crate::setting_dir;
+use crate::settings::{Channel, Settings};
+use crate::util::e;
+use nexus::imgui::Ui;
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, ImmutableString, Scope};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+static SCRIPTS: Mutex<Option<Scripts>> = const { Mutex::new(None) };
+/// Emote names requested by the running script
+static SPAWNS: Mutex<Vec<String>> = const { Mutex::new(Vec::new()) };
+/// Readable settings and their revision, scripts run while the options may hold the settings lock
+static SETTINGS: Mutex<Option<(u64, Value)>> = const { Mutex::new(None) };
+/// Bounds runaway loops, a script runs on the chat thread
+const MAX_OPERATIONS: u64 = 100_000;
+/// Bound the memory and stack a script can take
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 32;
+/// Settings scripts can read with `setting(name)`, credentials and paths stay out of reach
+const READABLE_SETTINGS: &[&str] = &[
+    "emote_set_ids",
+    "use_global",
+    "aliases",
+    "disabled_emotes",
+    "favorites",
+    "muted_sets",
+    "emote_size",
+    "motion",
+    "animation",
+    "backdrop",
+    "trail",
+    "max_active_emotes",
+    "lifetime",
+    "lifetime_secs",
+    "squad_sync",
+    "min_group_size",
+    "only_when_commanding",
+    "dev_mode",
+];
+
+struct Scripts {
+    engine: Engine,
+    scripts: Vec<(String, AST)>,
+    /// Scripts that failed to compile, shown in the options
+    errors: Vec<(String, String)>,
+}
+
+fn scripts_dir() -> PathBuf {
+    setting_dir().join("scripts")
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS);
+    engine.register_fn("spawn", |name: ImmutableString| {
+        SPAWNS.lock().unwrap().push(name.to_string());
+    });
+    engine.register_fn("log", |text: ImmutableString| {
+        log::info!("Script: {text}");
+    });
+    engine.register_fn(
+        "setting",
+        |name: ImmutableString| -> Result<Dynamic, Box<EvalAltResult>> {
+            let settings = SETTINGS.lock().unwrap();
+            match settings
+                .as_ref()
+                .and_then(|(_, settings)| settings.get(name.as_str()))
+            {
+                Some(value) => rhai::serde::to_dynamic(value),
+                None => Ok(Dynamic::UNIT),
+            }
+        },
+    );
+    engine
+}
+
+/// Compiles all scripts of the scripts folder, replaces the previously loaded ones
+pub fn load() {
+    let engine = engine();
+    let mut scripts = Vec::new();
+    let mut errors = Vec::new();
+    let entries = fs::read_dir(scripts_dir()).into_iter().flatten().flatten();
+    for entry in entries {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "rhai") {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let result = engine.compile_file(path).and_then(|ast| {
+            // top level statements run once, `on_message` runs per message
+            engine.run_ast_with_scope(&mut Scope::new(), &ast)?;
+            Ok(ast)
+        });
+        match result {
+            Ok(ast) => {
+                log::info!("Loaded script {name}");
+                scripts.push((name, ast));
+            }
+            Err(e) => {
+                log::error!("Failed to load script {name}: {e}");
+                errors.push((name, e.to_string()));
+            }
+        }
+    }
+    *SCRIPTS.lock().unwrap() = Some(Scripts {
+        engine,
+        scripts,
+        errors,
+    });
+}
+
+pub fn unload() {
+    SCRIPTS.lock().unwrap().take();
+    SPAWNS.lock().unwrap().clear();
+    SETTINGS.lock().unwrap().take();
+}
+
+/// Runs `on_message` of all scripts, returns the emote names they want to spawn
+pub fn on_message(
+    author: &str,
+    channel: Option<Channel>,
+    content: &str,
+    settings: &Settings,
+) -> Vec<String> {
+    let scripts = SCRIPTS.lock().unwrap();
+    let Some(scripts) = scripts.as_ref() else {
+        return Vec::new();
+    };
+    let handlers = scripts
+        .scripts
+        .iter()
+        .filter(|(_, ast)| ast.iter_functions().any(|f| f.name == "on_message"))
+        .collect::<Vec<_>>();
+    if handlers.is_empty() {
+        return Vec::new();
+    }
+    {
+        let revision = Settings::revision();
+        let mut readable = SETTINGS.lock().unwrap();
+        if readable.as_ref().is_none_or(|(r, _)| *r != revision) {
+            *readable = Some((revision, readable_settings(settings)));
+        }
+    }
+    let channel = channel.map(|c| format!("{c:?}")).unwrap_or_default();
+    for (name, ast) in handlers {
+        let args = (author.to_string(), channel.clone(), content.to_string());
+        if let Err(e) = scripts.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false),
+            &mut Scope::new(),
+            ast,
+            "on_message",
+            args,
+        ) {
+            log::error!("Script {name} failed: {e}");
+        }
+    }
+    std::mem::take(&mut *SPAWNS.lock().unwrap())
+}
+
+fn readable_settings(settings: &Settings) -> Value {
+    let mut value = serde_json::to_value(settings).unwrap_or_default();
+    if let Value::Object(map) = &mut value {
+        map.retain(|key, _| READABLE_SETTINGS.contains(&key.as_str()));
+    }
+    value
+}
+
+pub fn status_ui(ui: &Ui) {
+    let scripts = SCRIPTS.lock().unwrap();
+    let Some(scripts) = scripts.as_ref() else {
+        return;
+    };
+    ui.text(format!(
+        "{}: {}",
+        e("Loaded scripts"),
+        scripts.scripts.len()
+    ));
+    for (name, error) in &scripts.errors {
+        ui.text_colored([0.9, 0.3, 0.3, 1.0], format!("{name}: {error}"));
+    }
+}
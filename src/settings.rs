@@ -1,14 +1,34 @@
-use crate::seventv::EmoteSet;
+use crate::animation::{Animation, Backdrop, Rotation, Skew, Style};
+use crate::chat_events::MessageSource;
+use crate::giftex::{Compression, PerformanceMode, Playback};
+use crate::motion;
+use crate::provider::{
+    self, DownloadLimits, EmoteSet, FileSelection, ImageFormat, is_local, provider_by_name,
+    provider_for, provider_names,
+};
+use crate::quickbar::{FAVORITE_PAYLOAD, payload_key};
+use crate::sampler::Filtering;
+use crate::slots::SET_SLOTS;
+use crate::spawn::{DEFAULT_MAX_ACTIVE_EMOTES, MAX_ACTIVE_EMOTES_LIMIT};
 use crate::util::{UiExt, e};
+use crate::{
+    audit, cache_cleanup, collisions, commands, credentials, file_cache_dir, frame_cache,
+    frame_cache_dir, gist, squad,
+};
 use anyhow::Result;
-use nexus::imgui::Ui;
+use nexus::data_link::read_nexus_link;
+use nexus::imgui::{DragDropSource, TreeNodeFlags, Ui};
 use serde::{Deserialize, Serialize};
 use std::cell::{Cell, RefCell};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use strum::{VariantArray, VariantNames};
+use url::Url;
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Diff<T: Debug + Clone + Hash + PartialEq + Eq> {
@@ -23,12 +43,634 @@ pub enum ChatMessageSource {
     ChatEvents,
 }
 
+/// What happens to the emotes when a loading screen starts
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum MapChangeDespawn {
+    /// Emotes continue on the new map
+    #[default]
+    Keep,
+    /// Emotes on screen disappear, queued ones still appear
+    Active,
+    /// Emotes on screen and queued ones disappear
+    All,
+}
+
+/// How emotes follow the resolution of the game, so they take up the same part of the screen
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum SizeScaling {
+    /// Same pixel size on every screen
+    None,
+    /// Relative to a 1080p screen
+    #[default]
+    Resolution,
+    /// Interface size and dpi scaling of the game
+    UiScale,
+}
+
+/// Screen height emote sizes are meant for
+const REFERENCE_HEIGHT: f32 = 1080.0;
+
+/// Part of the screen new emotes appear in
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum SpawnRegion {
+    #[default]
+    Anywhere,
+    LeftHalf,
+    RightHalf,
+    /// Above the chat box at its default size and position
+    AboveChat,
+    /// `spawn_rect` of the settings
+    Custom,
+}
+
+/// Chat box at its default size and position, as fractions of the screen
+const CHAT_BOX: [f32; 4] = [0.0, 0.0, 0.3, 0.72];
+
+impl SpawnRegion {
+    /// Left, top, right and bottom edge as fractions of the screen
+    pub fn rect(self, custom: [f32; 4]) -> [f32; 4] {
+        match self {
+            SpawnRegion::Anywhere => [0.0, 0.0, 1.0, 1.0],
+            SpawnRegion::LeftHalf => [0.0, 0.0, 0.5, 1.0],
+            SpawnRegion::RightHalf => [0.5, 0.0, 1.0, 1.0],
+            SpawnRegion::AboveChat => CHAT_BOX,
+            SpawnRegion::Custom => custom,
+        }
+    }
+}
+
+/// Smallest side of a custom spawn region, as a fraction of the screen
+const MIN_REGION: f32 = 0.05;
+const REGION_EDITOR_WIDTH: f32 = 320.0;
+const HANDLE_RADIUS: f32 = 4.0;
+
+/// Part of the custom region being dragged in the editor
+#[derive(Debug, Clone, Copy)]
+enum RegionDrag {
+    TopLeft,
+    BottomRight,
+    Move,
+}
+
+/// What happens to spawns while `max_active_emotes` are on screen
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum SpawnOverflow {
+    /// Spawns wait in the queue until emotes leave the screen
+    #[default]
+    Queue,
+    /// Spawns that don't fit are discarded
+    Drop,
+}
+
+/// When emotes disappear
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Lifetime {
+    /// Once their path left the screen
+    #[default]
+    UntilOffScreen,
+    /// After `lifetime_secs`, fading out at the end
+    Fixed,
+    /// Stop at `linger_height_percent` of the screen height and fade out after `lifetime_secs`
+    Linger,
+}
+
+/// Lifetime settings, copied out for the render loop
+#[derive(Debug, Clone, Copy)]
+pub struct Lifespan {
+    pub lifetime: Lifetime,
+    pub secs: f32,
+    /// Height emotes stop at with `Lifetime::Linger`, as a fraction of the screen from the bottom
+    pub linger_height: f32,
+}
+
+/// Emotes with a lifetime fade out during their last second
+const LIFETIME_FADE: f32 = 1.0;
+
+impl Lifespan {
+    /// Seconds left of an emote that appeared `age` seconds ago and stopped `stopped` seconds ago,
+    /// `None` while it lives until it leaves the screen
+    pub fn remaining(&self, age: f32, stopped: Option<f32>) -> Option<f32> {
+        match self.lifetime {
+            Lifetime::UntilOffScreen => None,
+            Lifetime::Fixed => Some(self.secs - age),
+            Lifetime::Linger => stopped.map(|stopped| self.secs - stopped),
+        }
+    }
+
+    pub fn opacity(&self, age: f32, stopped: Option<f32>) -> f32 {
+        self.remaining(age, stopped)
+            .map_or(1.0, |remaining| (remaining / LIFETIME_FADE).clamp(0.0, 1.0))
+    }
+
+    /// Screen line emotes stop at, `None` unless they linger
+    pub fn stop_line(&self, screen_height: f32) -> Option<f32> {
+        (self.lifetime == Lifetime::Linger).then_some(screen_height * (1.0 - self.linger_height))
+    }
+}
+
+/// Language of the chat, picks the word list emote names are checked against
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    VariantArray,
+    VariantNames,
+)]
+pub enum ChatLanguage {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+}
+
+/// Chat channels an emote set can be restricted to
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, VariantArray, VariantNames,
+)]
+pub enum Channel {
+    Guild,
+    Local,
+    Map,
+    Party,
+    Squad,
+    PvP,
+    WvW,
+    Whisper,
+}
+
+impl Channel {
+    /// Channel of a chat message, `None` for messages that are not player chat
+    pub fn of(source: &MessageSource) -> Option<Self> {
+        match source {
+            MessageSource::Guild { .. } | MessageSource::GuildMotD { .. } => Some(Self::Guild),
+            MessageSource::Local(_) => Some(Self::Local),
+            MessageSource::Map(_) => Some(Self::Map),
+            MessageSource::Party(_) => Some(Self::Party),
+            MessageSource::Squad(_) | MessageSource::SquadMessage(_) => Some(Self::Squad),
+            MessageSource::TeamPvP(_) => Some(Self::PvP),
+            MessageSource::TeamWvW { .. } => Some(Self::WvW),
+            MessageSource::Whisper(_) => Some(Self::Whisper),
+            MessageSource::Emote { .. } | MessageSource::EmoteCustom { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomEmote {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmoteAlias {
+    pub alias: String,
+    pub emote: String,
+    /// The emote name itself no longer triggers the emote, only the alias does
+    #[serde(default)]
+    pub replace: bool,
+}
+
+/// Emote turned off in a single set, the same name in other sets still spawns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "DisabledEntry")]
+pub struct DisabledEmote {
+    /// Empty for entries of older versions, which disabled the name in every set
+    pub set_id: String,
+    pub name: String,
+}
+
+impl DisabledEmote {
+    pub fn matches(&self, set_id: &str, name: &str) -> bool {
+        self.name == name && (self.set_id.is_empty() || self.set_id == set_id)
+    }
+}
+
+/// Older versions stored only the emote name
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DisabledEntry {
+    Name(String),
+    Keyed { set_id: String, name: String },
+}
+
+impl From<DisabledEntry> for DisabledEmote {
+    fn from(entry: DisabledEntry) -> Self {
+        match entry {
+            DisabledEntry::Name(name) => Self {
+                set_id: String::new(),
+                name,
+            },
+            DisabledEntry::Keyed { set_id, name } => Self { set_id, name },
+        }
+    }
+}
+
+/// Proxy for all requests, stays on this machine like the sync folder
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Host name, optionally with a scheme like `socks5://`, empty disables the proxy
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Kept in the credential manager, only read from the file to move passwords of older
+    /// versions there
+    #[serde(skip_serializing)]
+    pub password: String,
+}
+
+impl ProxyConfig {
+    /// Proxy url with the credentials, `None` without a host
+    pub fn url(&self) -> Option<String> {
+        let host = self.host.trim();
+        if host.is_empty() {
+            return None;
+        }
+        let (scheme, host) = host.split_once("://").unwrap_or(("http", host));
+        let mut url = Url::parse(&format!("{scheme}://{host}")).ok()?;
+        if self.port > 0 {
+            url.set_port(Some(self.port)).ok()?;
+        }
+        if !self.username.is_empty() {
+            // the setters percent-encode special characters of the credentials
+            url.set_username(&self.username).ok()?;
+            url.set_password(Some(&self.password)).ok()?;
+        }
+        Some(url.to_string())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub emote_set_ids: Vec<String>,
     pub use_global: bool,
     #[serde(default)]
     pub chat_message_source: ChatMessageSource,
+    #[serde(default)]
+    pub custom_emotes: Vec<CustomEmote>,
+    #[serde(default)]
+    pub aliases: Vec<EmoteAlias>,
+    #[serde(default)]
+    pub dev_mode: bool,
+    /// Runs the user scripts of the addon directory, only with the `scripting` feature
+    #[serde(default)]
+    pub scripting: bool,
+    /// Messages older than this are ignored, 0 disables the cutoff
+    #[serde(default = "default_max_message_age")]
+    pub max_message_age_secs: u32,
+    /// Identical messages of the same author within this window only spawn once, 0 disables it
+    #[serde(default = "default_duplicate_window")]
+    pub duplicate_window_secs: u32,
+    /// Emote sets get downloaded again after this many minutes, 0 disables it
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval_mins: u32,
+    #[serde(default)]
+    pub disabled_emotes: Vec<DisabledEmote>,
+    /// Size multipliers of single emotes by name
+    #[serde(default)]
+    pub emote_size_overrides: HashMap<String, f32>,
+    #[serde(default)]
+    pub ignore_own_messages: bool,
+    #[serde(default)]
+    pub favorites: Vec<String>,
+    #[serde(default)]
+    pub show_quickbar: bool,
+    #[serde(default)]
+    pub show_stats: bool,
+    #[serde(default)]
+    pub show_emote_counter: bool,
+    /// Streamer mode, account and character names are replaced by a short hash
+    #[serde(default)]
+    pub hide_account_names: bool,
+    #[serde(default)]
+    pub watch_clipboard: bool,
+    /// Offer emotes to copy when a whisper with emotes arrives
+    #[serde(default)]
+    pub whisper_reply: bool,
+    /// Emote set id to the channels it applies to. Sets without an entry apply everywhere
+    #[serde(default)]
+    pub emote_set_channels: HashMap<String, Vec<Channel>>,
+    /// Emote set id to the style its emotes spawn with. Sets without an entry use the global style
+    #[serde(default)]
+    pub set_styles: HashMap<String, Style>,
+    /// Emote sets switched off, they stay downloaded and apply again once switched on
+    #[serde(default)]
+    pub muted_sets: Vec<String>,
+    /// Emote set ids toggled by the keybind slots, empty for unassigned slots
+    #[serde(default)]
+    pub set_slots: Vec<String>,
+    /// Prompt to add emote sets other players share with `!emoteset add <id>`
+    #[serde(default)]
+    pub accept_shared_sets: bool,
+    /// Prompt to add emote sets named as `7tv:<id>` in a guild message of the day
+    #[serde(default = "default_guild_motd_sets")]
+    pub guild_motd_sets: bool,
+    /// Guild emote sets the prompt was ignored for, so it doesn't return on every login
+    #[serde(default)]
+    pub ignored_guild_sets: Vec<String>,
+    /// Temporarily adopt the emote sets the commander syncs with `!emoteset sync <id>...`
+    #[serde(default)]
+    pub squad_sync: bool,
+    /// Only spawn emotes in a squad or party of at least this size, 0 disables it
+    #[serde(default)]
+    pub min_group_size: u32,
+    #[serde(default)]
+    pub only_when_commanding: bool,
+    /// Use the personal 7tv emotes of the players in chat
+    #[serde(default)]
+    pub personal_emotes: bool,
+    /// Emote rendering pauses after this many minutes without chat, 0 disables it
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_mins: u32,
+    #[serde(default)]
+    pub map_change_despawn: MapChangeDespawn,
+    /// Emotes on screen at the same time, including queued ones with `SpawnOverflow::Drop`
+    #[serde(default = "default_max_active_emotes")]
+    pub max_active_emotes: u32,
+    #[serde(default)]
+    pub spawn_overflow: SpawnOverflow,
+    #[serde(default)]
+    pub lifetime: Lifetime,
+    #[serde(default = "default_lifetime_secs")]
+    pub lifetime_secs: f32,
+    #[serde(default = "default_linger_height")]
+    pub linger_height_percent: u32,
+    #[serde(default)]
+    pub spawn_region: SpawnRegion,
+    /// Left, top, right and bottom edge of the custom spawn region, as fractions of the screen
+    #[serde(default = "default_spawn_rect")]
+    pub spawn_rect: [f32; 4],
+    /// Distance of new emotes to the edges of the spawn region, as a fraction of its size
+    #[serde(default = "default_spawn_margin")]
+    pub spawn_margin: f32,
+    #[serde(default)]
+    pub chat_language: ChatLanguage,
+    /// Emote names the common word warning was dismissed for
+    #[serde(default)]
+    pub ignored_collisions: Vec<String>,
+    /// Per provider overrides of the emote download limits
+    #[serde(default)]
+    pub download_limits: HashMap<String, DownloadLimits>,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Emote sets and emote data requested at the same time
+    #[serde(default = "default_fetch_threads")]
+    pub fetch_threads: u32,
+    /// 0 waits as long as the system allows
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_secs: u32,
+    /// Longest wait for the response and for each part of its body, 0 disables it
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout_secs: u32,
+    /// Github token with gist scope to export the settings. Kept in the credential manager,
+    /// only read from the file to move tokens of older versions there
+    #[serde(default, skip_serializing)]
+    pub gist_token: String,
+    /// Gist of the last export, further exports update it
+    #[serde(default)]
+    pub gist_id: String,
+    /// Emote file formats in order of preference
+    #[serde(default = "default_image_formats")]
+    pub image_formats: Vec<ImageFormat>,
+    /// Cdn scale of the emote files, 1 to 4
+    #[serde(default = "default_emote_scale")]
+    pub emote_scale: u32,
+    #[serde(default)]
+    pub animation: Animation,
+    /// Name of the motion path of all emotes, unlike the style it can't be set per emote set
+    #[serde(default = "default_motion")]
+    pub motion: String,
+    #[serde(default)]
+    pub backdrop: Backdrop,
+    /// Fading copies behind moving emotes
+    #[serde(default)]
+    pub trail: bool,
+    #[serde(default)]
+    pub texture_filtering: Filtering,
+    /// Adjust opacity and backdrop strength to the time of day and map
+    #[serde(default)]
+    pub adaptive_tint: bool,
+    /// Longest side of emote textures in pixels, larger emotes get downscaled, 0 disables
+    #[serde(default = "default_max_emote_dimension")]
+    pub max_emote_dimension: u32,
+    #[serde(default)]
+    pub texture_compression: Compression,
+    /// Keep decoded animations on disk so they only get decoded once
+    #[serde(default = "default_cache_frames")]
+    pub cache_frames: bool,
+    /// Keep downloaded emote files on disk, they load without the cdn on the next session
+    #[serde(default = "default_cache_files")]
+    pub cache_files: bool,
+    /// Cached files of emotes no set contains get removed at startup after this many days, 0 keeps them
+    #[serde(default = "default_cache_max_age")]
+    pub cache_max_age_days: u32,
+    /// Both caches together, the least recently used files go first. 0 disables the limit
+    #[serde(default = "default_cache_max_size")]
+    pub cache_max_size_mib: u32,
+    /// Caches of earlier versions had no limit and got cleaned up once
+    #[serde(default)]
+    pub cache_migrated: bool,
+    /// Emote sets whose emotes are all loaded when the set loads, not on their first use
+    #[serde(default)]
+    pub preload_sets: Vec<String>,
+    /// Preloaded emotes are also decoded and uploaded, otherwise they only go into the file cache
+    #[serde(default)]
+    pub preload_upload: bool,
+    /// Loop every gif, even the ones authored to play only once
+    #[serde(default)]
+    pub always_loop_gifs: bool,
+    /// Tiny frame delays are raised to what browsers show
+    #[serde(default = "default_clamp_frame_delays")]
+    pub clamp_frame_delays: bool,
+    #[serde(default)]
+    pub performance_mode: PerformanceMode,
+    /// Frame changes per second of animated emotes in `PerformanceMode::CapFps`
+    #[serde(default = "default_animation_fps")]
+    pub animation_fps: u32,
+    /// All instances of the same gif play in sync
+    #[serde(default = "default_sync_playback")]
+    pub sync_playback: bool,
+    /// Gifs with more frames keep them in memory and stream them into one texture, 0 disables
+    #[serde(default)]
+    pub stream_frames_above: u32,
+    /// Least recently used emotes get freed above this much video memory, 0 disables
+    #[serde(default = "default_vram_budget")]
+    pub vram_budget_mib: u32,
+    /// Render time per frame for uploads and emotes, the rest waits for the next frame. 0 disables
+    #[serde(default)]
+    pub frame_budget_us: u32,
+    /// Threads of the background worker, 0 picks them based on the processor
+    #[serde(default)]
+    pub worker_threads: u32,
+    /// On screen size in multiples of the 1x files, independent of the downloaded scale
+    #[serde(default = "default_emote_size")]
+    pub emote_size: f32,
+    #[serde(default)]
+    pub size_scaling: SizeScaling,
+    #[serde(default)]
+    pub low_bandwidth: bool,
+    /// Smallest files, no preloading and one download at a time, for metered connections
+    #[serde(default)]
+    pub bandwidth_saver: bool,
+    /// Folder, e.g. in OneDrive, the settings get mirrored to. Local to this machine
+    #[serde(default)]
+    pub sync_folder: String,
+    /// Unix time of the last save, the newer settings win when syncing
+    #[serde(default)]
+    pub modified: u64,
+    /// `modified` at the last sync, local changes after it conflict with newer synced ones
+    #[serde(default)]
+    pub synced: u64,
+}
+
+const SYNC_FILE: &str = "nexus-emotes-settings.json";
+/// Name of the github token in the credential manager
+const GIST_TOKEN: &str = "gist_token";
+const PROXY_PASSWORD: &str = "proxy_password";
+
+/// Fills `secret` from the credential manager, true if it was still in the settings file and
+/// got moved there
+fn load_secret(name: &str, label: &str, secret: &mut String) -> bool {
+    if secret.is_empty() {
+        match credentials::read(name) {
+            Ok(stored) => *secret = stored.unwrap_or_default(),
+            Err(e) => log::error!("Failed to read the {label}: {e:#}"),
+        }
+        return false;
+    }
+    log::info!("Moving the {label} from the settings file to the credential manager");
+    if let Err(e) = credentials::write(name, secret) {
+        log::error!("Failed to store the {label}: {e:#}");
+        return false;
+    }
+    true
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn default_max_message_age() -> u32 {
+    30
+}
+
+fn default_duplicate_window() -> u32 {
+    10
+}
+
+fn default_refresh_interval() -> u32 {
+    30
+}
+
+fn default_connect_timeout() -> u32 {
+    10
+}
+
+fn default_read_timeout() -> u32 {
+    30
+}
+
+fn default_fetch_threads() -> u32 {
+    4
+}
+
+fn default_image_formats() -> Vec<ImageFormat> {
+    <ImageFormat as VariantArray>::VARIANTS.to_vec()
+}
+
+fn default_emote_scale() -> u32 {
+    3
+}
+
+fn default_max_active_emotes() -> u32 {
+    DEFAULT_MAX_ACTIVE_EMOTES
+}
+
+fn default_lifetime_secs() -> f32 {
+    5.0
+}
+
+fn default_linger_height() -> u32 {
+    50
+}
+
+fn default_spawn_rect() -> [f32; 4] {
+    [0.25, 0.25, 0.75, 0.75]
+}
+
+fn default_spawn_margin() -> f32 {
+    0.1
+}
+
+fn default_motion() -> String {
+    motion::DEFAULT.name().to_string()
+}
+
+fn default_max_emote_dimension() -> u32 {
+    512
+}
+
+fn default_vram_budget() -> u32 {
+    512
+}
+
+fn default_emote_size() -> f32 {
+    3.0
+}
+
+fn default_idle_timeout() -> u32 {
+    10
+}
+
+fn default_cache_frames() -> bool {
+    true
+}
+
+fn default_cache_files() -> bool {
+    true
+}
+
+fn default_cache_max_age() -> u32 {
+    30
+}
+
+fn default_cache_max_size() -> u32 {
+    2048
+}
+
+fn default_clamp_frame_delays() -> bool {
+    true
+}
+
+fn default_animation_fps() -> u32 {
+    15
+}
+
+fn default_sync_playback() -> bool {
+    true
+}
+
+fn default_guild_motd_sets() -> bool {
+    true
 }
 
 impl Default for Settings {
@@ -37,11 +679,93 @@ impl Default for Settings {
             emote_set_ids: Vec::new(),
             use_global: true,
             chat_message_source: ChatMessageSource::UnofficialExtras,
+            custom_emotes: Vec::new(),
+            aliases: Vec::new(),
+            dev_mode: false,
+            scripting: false,
+            max_message_age_secs: default_max_message_age(),
+            duplicate_window_secs: default_duplicate_window(),
+            refresh_interval_mins: default_refresh_interval(),
+            disabled_emotes: Vec::new(),
+            emote_size_overrides: HashMap::new(),
+            ignore_own_messages: false,
+            favorites: Vec::new(),
+            show_quickbar: false,
+            whisper_reply: false,
+            show_stats: false,
+            show_emote_counter: false,
+            hide_account_names: false,
+            watch_clipboard: false,
+            emote_set_channels: HashMap::new(),
+            set_styles: HashMap::new(),
+            muted_sets: Vec::new(),
+            set_slots: Vec::new(),
+            accept_shared_sets: false,
+            guild_motd_sets: default_guild_motd_sets(),
+            ignored_guild_sets: Vec::new(),
+            squad_sync: false,
+            min_group_size: 0,
+            only_when_commanding: false,
+            personal_emotes: false,
+            idle_timeout_mins: default_idle_timeout(),
+            map_change_despawn: MapChangeDespawn::default(),
+            max_active_emotes: default_max_active_emotes(),
+            spawn_overflow: SpawnOverflow::default(),
+            lifetime: Lifetime::default(),
+            lifetime_secs: default_lifetime_secs(),
+            linger_height_percent: default_linger_height(),
+            spawn_region: SpawnRegion::default(),
+            spawn_rect: default_spawn_rect(),
+            spawn_margin: default_spawn_margin(),
+            chat_language: ChatLanguage::default(),
+            ignored_collisions: Vec::new(),
+            download_limits: HashMap::new(),
+            fetch_threads: default_fetch_threads(),
+            connect_timeout_secs: default_connect_timeout(),
+            read_timeout_secs: default_read_timeout(),
+            proxy: ProxyConfig::default(),
+            gist_token: String::new(),
+            gist_id: String::new(),
+            image_formats: default_image_formats(),
+            emote_scale: default_emote_scale(),
+            emote_size: default_emote_size(),
+            size_scaling: SizeScaling::default(),
+            animation: Animation::default(),
+            motion: default_motion(),
+            backdrop: Backdrop::default(),
+            trail: false,
+            adaptive_tint: false,
+            texture_filtering: Filtering::default(),
+            max_emote_dimension: default_max_emote_dimension(),
+            texture_compression: Compression::default(),
+            cache_frames: default_cache_frames(),
+            cache_files: default_cache_files(),
+            cache_max_age_days: default_cache_max_age(),
+            cache_max_size_mib: default_cache_max_size(),
+            cache_migrated: false,
+            preload_sets: Vec::new(),
+            preload_upload: false,
+            always_loop_gifs: false,
+            clamp_frame_delays: default_clamp_frame_delays(),
+            performance_mode: PerformanceMode::default(),
+            animation_fps: default_animation_fps(),
+            sync_playback: default_sync_playback(),
+            stream_frames_above: 0,
+            vram_budget_mib: default_vram_budget(),
+            frame_budget_us: 0,
+            worker_threads: 0,
+            low_bandwidth: false,
+            bandwidth_saver: false,
+            sync_folder: String::new(),
+            modified: 0,
+            synced: 0,
         }
     }
 }
 
 static SETTINGS: OnceLock<Mutex<Settings>> = OnceLock::new();
+/// Raised whenever the settings are loaded or saved
+static REVISION: AtomicU64 = const { AtomicU64::new(0) };
 
 impl Settings {
     pub fn get() -> MutexGuard<'static, Self> {
@@ -52,22 +776,123 @@ impl Settings {
     pub fn load(&mut self, path: &impl AsRef<std::path::Path>) -> Result<()> {
         let path = path.as_ref();
         if !path.exists() {
+            self.load_secrets(path);
             return Ok(());
         }
         let settings = std::fs::read_to_string(path)?;
         *self = serde_json::from_str(&settings)?;
-        Ok(())
+        REVISION.fetch_add(1, Ordering::Relaxed);
+        self.load_secrets(path);
+        self.load_sync_copy(path)
+    }
+
+    /// Reads the secrets from the credential manager, ones still in the file are moved there
+    fn load_secrets(&mut self, path: &Path) {
+        let moved_token = load_secret(GIST_TOKEN, "github token", &mut self.gist_token);
+        let moved_password =
+            load_secret(PROXY_PASSWORD, "proxy password", &mut self.proxy.password);
+        if !moved_token && !moved_password {
+            return;
+        }
+        // the secrets are never serialized, writing the file drops them
+        if let Err(e) = serde_json::to_string_pretty(self)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(std::fs::write(path, json)?))
+        {
+            log::error!("Failed to remove the secrets from the settings: {e:#}");
+        }
+    }
+
+    /// Changes whenever the settings are loaded or saved, so copies of them know they are outdated
+    #[cfg(feature = "scripting")]
+    pub fn revision() -> u64 {
+        REVISION.load(Ordering::Relaxed)
     }
 
-    pub fn save(&self, path: &impl AsRef<std::path::Path>) -> Result<()> {
+    /// Saves a change made by the user and appends it to the audit log
+    pub fn save(&mut self, path: &impl AsRef<std::path::Path>) -> Result<()> {
         let path = path.as_ref();
+        self.write(path)?;
+        audit::record(self, path.parent().unwrap());
+        Ok(())
+    }
+
+    /// Saves a change the addon made on its own, like bookkeeping, without an audit entry
+    pub fn save_quietly(&mut self, path: &impl AsRef<std::path::Path>) -> Result<()> {
+        self.write(path.as_ref())?;
+        audit::rebase(self);
+        Ok(())
+    }
+
+    fn write(&mut self, path: &Path) -> Result<()> {
+        REVISION.fetch_add(1, Ordering::Relaxed);
         if !path.exists() {
             std::fs::create_dir_all(path.parent().unwrap())?;
         }
+        self.modified = unix_now();
+        if let Some(sync_path) = self.sync_path() {
+            let mut synced = self.clone();
+            synced.sync_folder.clear();
+            synced.proxy = ProxyConfig::default();
+            match std::fs::write(&sync_path, serde_json::to_string_pretty(&synced)?) {
+                Ok(()) => self.synced = self.modified,
+                Err(e) => log::error!("Failed to write {}: {e}", sync_path.display()),
+            }
+        }
         std::fs::write(path, serde_json::to_string_pretty(self)?)?;
         Ok(())
     }
 
+    fn sync_path(&self) -> Option<PathBuf> {
+        (!self.sync_folder.is_empty()).then(|| Path::new(&self.sync_folder).join(SYNC_FILE))
+    }
+
+    /// Takes the synced settings if they are newer than the local ones
+    fn load_sync_copy(&mut self, local_path: &Path) -> Result<()> {
+        let Some(sync_path) = self.sync_path() else {
+            return Ok(());
+        };
+        if !sync_path.exists() {
+            return Ok(());
+        }
+        let synced: Settings = serde_json::from_str(&std::fs::read_to_string(&sync_path)?)?;
+        if synced.modified <= self.modified {
+            return Ok(());
+        }
+        if self.modified > self.synced {
+            // both sides changed, newest wins but keep ours around
+            let backup = local_path.with_file_name("settings.conflict.json");
+            log::warn!(
+                "Settings changed here and in {}, keeping a copy of the local ones in {}",
+                sync_path.display(),
+                backup.display()
+            );
+            std::fs::write(backup, serde_json::to_string_pretty(self)?)?;
+        }
+        log::info!("Using newer settings from {}", sync_path.display());
+        let modified = synced.modified;
+        self.replace_keeping_local(synced);
+        self.synced = modified;
+        std::fs::write(local_path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Replaces everything but the fields local to this machine
+    fn replace_keeping_local(&mut self, other: Settings) {
+        let gist_token = std::mem::take(&mut self.gist_token);
+        let sync_folder = std::mem::take(&mut self.sync_folder);
+        let proxy = std::mem::take(&mut self.proxy);
+        // the caches of this machine are already migrated
+        let cache_migrated = self.cache_migrated;
+        *self = Settings {
+            gist_token,
+            sync_folder,
+            proxy,
+            cache_migrated,
+            ..other
+        };
+    }
+
     pub fn ui_and_save(
         &mut self,
         emote_sets: &[EmoteSet],
@@ -93,6 +918,41 @@ impl Settings {
                 <ChatMessageSource as VariantNames>::VARIANTS,
             )
         });
+        ui.checkbox(e("Ignore my own messages"), &mut self.ignore_own_messages);
+        let mut max_age = self.max_message_age_secs as i32;
+        if ui.input_int(e("Max message age (s)"), &mut max_age).build() {
+            self.max_message_age_secs = max_age.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Ignore messages that arrive late, e.g. after a reconnect. 0 disables the cutoff",
+            ));
+        });
+        let mut duplicate_window = self.duplicate_window_secs as i32;
+        if ui
+            .input_int(e("Duplicate window (s)"), &mut duplicate_window)
+            .build()
+        {
+            self.duplicate_window_secs = duplicate_window.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Identical messages of the same player within this window only spawn emotes once. 0 disables it",
+            ));
+        });
+        let mut min_group_size = self.min_group_size as i32;
+        if ui
+            .input_int(e("Minimum group size"), &mut min_group_size)
+            .build()
+        {
+            self.min_group_size = min_group_size.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Only show emotes in a squad or party with at least this many players. 0 disables it. Requires Unofficial Extras",
+            ));
+        });
+        ui.checkbox(e("Only when commanding"), &mut self.only_when_commanding);
         ui.checkbox(e("Use global 7tv Emote Set"), &mut self.use_global);
         if ui.help_marker(|| {
             ui.tooltip_text(e("Enable 7tv global emote set. Click to open in browser"));
@@ -101,6 +961,121 @@ impl Settings {
         {
             log::error!("Failed to open browser: {e}");
         }
+        ui.checkbox(e("Accept shared emote sets"), &mut self.accept_shared_sets);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Asks to add emote sets other players share with \"!emoteset add <id>\" in squad, party or guild chat",
+            ));
+        });
+        ui.checkbox(e("Guild emote sets"), &mut self.guild_motd_sets);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Offers the emote sets your guilds name as 7tv:<id> in their message of the day",
+            ));
+        });
+        ui.checkbox(e("Reply to whispers with emotes"), &mut self.whisper_reply);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Whispers with emotes open a popup with the emotes and your favorites to copy",
+            ));
+        });
+        self.squad_sync_ui(ui);
+        ui.checkbox(e("Personal emotes"), &mut self.personal_emotes);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Looks up the 7tv user of every player by account name, players can also link theirs with \"!emoteset personal <user id>\" in chat. Downloads an emote set per player that has one",
+            ));
+        });
+        let mut refresh_interval = self.refresh_interval_mins as i32;
+        if ui
+            .input_int(e("Refresh interval (min)"), &mut refresh_interval)
+            .build()
+        {
+            self.refresh_interval_mins = refresh_interval.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Download the emote sets again periodically to pick up new emotes. 0 disables it",
+            ));
+        });
+        let mut idle_timeout = self.idle_timeout_mins as i32;
+        if ui
+            .input_int(e("Idle timeout (min)"), &mut idle_timeout)
+            .build()
+        {
+            self.idle_timeout_mins = idle_timeout.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Pause emote rendering when no chat message was seen for this long. 0 disables it",
+            ));
+        });
+        let mut despawn = <MapChangeDespawn as VariantArray>::VARIANTS
+            .iter()
+            .position(|d| *d == self.map_change_despawn)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Despawn on map change"),
+            &mut despawn,
+            <MapChangeDespawn as VariantNames>::VARIANTS,
+        ) {
+            self.map_change_despawn = <MapChangeDespawn as VariantArray>::VARIANTS[despawn];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Active removes the emotes on screen when a loading screen starts, All also drops the queued ones",
+            ));
+        });
+        let mut max_active = self.max_active_emotes as i32;
+        if ui
+            .input_int(e("Max emotes on screen"), &mut max_active)
+            .build()
+        {
+            self.max_active_emotes = (max_active.max(1) as u32).min(MAX_ACTIVE_EMOTES_LIMIT);
+        }
+        let mut overflow = <SpawnOverflow as VariantArray>::VARIANTS
+            .iter()
+            .position(|o| *o == self.spawn_overflow)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("When full"),
+            &mut overflow,
+            <SpawnOverflow as VariantNames>::VARIANTS,
+        ) {
+            self.spawn_overflow = <SpawnOverflow as VariantArray>::VARIANTS[overflow];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Queue shows the extra emotes once others left the screen, Drop discards them",
+            ));
+        });
+        let mut lifetime = <Lifetime as VariantArray>::VARIANTS
+            .iter()
+            .position(|l| *l == self.lifetime)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Lifetime"),
+            &mut lifetime,
+            <Lifetime as VariantNames>::VARIANTS,
+        ) {
+            self.lifetime = <Lifetime as VariantArray>::VARIANTS[lifetime];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Fixed removes emotes after the duration, Linger stops them at the height and removes them after the duration. Linger only stops paths that move up or down",
+            ));
+        });
+        if self.lifetime != Lifetime::UntilOffScreen {
+            ui.slider(e("Duration (s)"), 1.0, 30.0, &mut self.lifetime_secs);
+        }
+        if self.lifetime == Lifetime::Linger {
+            ui.slider(
+                e("Linger height (%)"),
+                5,
+                95,
+                &mut self.linger_height_percent,
+            );
+        }
         if old_use_global != self.use_global {
             DIFF.with_borrow_mut(|d| {
                 if self.use_global {
@@ -112,15 +1087,47 @@ impl Settings {
                 }
             });
         }
+        if let Some(id) = self.clipboard_ui(ui)
+            && !self.emote_set_ids.contains(&id)
+        {
+            self.emote_set_ids.push(id.clone());
+            DIFF.with_borrow_mut(|d| {
+                d.remove(&Diff::Removed(id.clone()));
+                d.insert(Diff::Added(id));
+            });
+        }
         let t = ui.begin_table("emote sets", 2);
         let mut to_remove = Vec::new();
+        // sets higher up in the list win if multiple sets contain the same emote name
+        let mut move_up = None;
+        let global_style = self.style();
         for (i, id) in self.emote_set_ids.iter().enumerate() {
             ui.table_next_row();
             ui.table_next_column();
-            if let Some(es) = emote_sets.iter().find(|es| &es.id == id) {
-                ui.link(&es.name, format!("https://7tv.app/emote-sets/{id}"));
-            } else {
-                ui.link(id, format!("https://7tv.app/emote-sets/{id}"));
+            let emote_set = emote_sets.iter().find(|es| &es.id == id);
+            let name = emote_set.map_or(id, |es| &es.name);
+            match provider_for(id).and_then(|provider| provider.set_url(id)) {
+                Some(url) => ui.link(name, url),
+                None => {
+                    ui.text(name);
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(id);
+                    }
+                }
+            }
+            if let Some(emote_set) = emote_set
+                && emote_set.emotes.len() < emote_set.declared_count
+            {
+                ui.same_line();
+                ui.text_colored(
+                    [0.9, 0.8, 0.3, 1.0],
+                    format!("{}/{}", emote_set.emotes.len(), emote_set.declared_count),
+                );
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(e(
+                        "Not all emotes of this set could be loaded. Check the log for details",
+                    ));
+                }
             }
             ui.table_next_column();
             if ui.button(e("Remove") + &format!("##emotesetremove{i}")) {
@@ -130,9 +1137,50 @@ impl Settings {
                     d.insert(Diff::Removed(id.clone()))
                 });
             }
+            ui.same_line();
+            if ui.button(e("Up") + &format!("##emotesetup{i}")) && i > 0 {
+                move_up = Some(i);
+            }
+            ui.same_line();
+            channels_ui(&mut self.emote_set_channels, id, i, ui);
+            ui.same_line();
+            set_style_ui(&mut self.set_styles, id, i, global_style, ui);
+            ui.same_line();
+            let mut enabled = !self.muted_sets.contains(id);
+            if ui.checkbox(e("On") + &format!("##emotesetenabled{i}"), &mut enabled) {
+                if enabled {
+                    self.muted_sets.retain(|m| m != id);
+                } else {
+                    self.muted_sets.push(id.clone());
+                }
+            }
+            ui.same_line();
+            let mut preload = self.preload_sets.contains(id);
+            if ui.checkbox(
+                e("Preload") + &format!("##emotesetpreload{i}"),
+                &mut preload,
+            ) {
+                if preload {
+                    self.preload_sets.push(id.clone());
+                } else {
+                    self.preload_sets.retain(|p| p != id);
+                }
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text(e(
+                    "Download all emotes of the set when it loads, not on their first use",
+                ));
+            }
         }
         for tr in to_remove {
-            self.emote_set_ids.remove(tr);
+            let id = self.emote_set_ids.remove(tr);
+            self.emote_set_channels.remove(&id);
+            self.set_styles.remove(&id);
+            self.muted_sets.retain(|m| m != &id);
+            self.preload_sets.retain(|p| p != &id);
+        }
+        if let Some(i) = move_up {
+            self.emote_set_ids.swap(i - 1, i);
         }
         ui.table_next_row();
         ui.table_next_column();
@@ -156,7 +1204,56 @@ impl Settings {
                 id.clear();
             }
         });
+        ui.table_next_row();
+        ui.table_next_column();
+        thread_local! {
+            static PATH: RefCell<String> = const { RefCell::new(String::new()) };
+        }
+        PATH.with_borrow_mut(|path| {
+            ui.input_text(e("File") + "##emotesetfileinput", path)
+                .build();
+            ui.help_marker(|| {
+                ui.tooltip_text(e(
+                    "Path to a .json file with the same content as the 7tv emote set api response",
+                ));
+            });
+            ui.table_next_column();
+            if ui.button(e("Add") + "##emotesetfileadd") {
+                if is_local(path) {
+                    self.emote_set_ids.push(path.clone());
+                    DIFF.with_borrow_mut(|d| {
+                        d.remove(&Diff::Removed(path.clone()));
+                        d.insert(Diff::Added(path.clone()));
+                    });
+                    path.clear();
+                } else {
+                    log::error!("Emote set file has to be a .json file: {path}");
+                }
+            }
+        });
         drop(t);
+        self.set_slots_ui(emote_sets, ui);
+        self.emote_browser_ui(emote_sets, ui);
+        self.custom_emotes_ui(ui);
+        self.aliases_ui(ui);
+        self.emote_sizes_ui(ui);
+        self.collisions_ui(emote_sets, ui);
+        self.file_selection_ui(ui);
+        self.download_limits_ui(ui);
+        self.proxy_ui(ui);
+        ui.checkbox(e("Show statistics"), &mut self.show_stats);
+        ui.checkbox(e("Show emote counter"), &mut self.show_emote_counter);
+        ui.checkbox(e("Hide account names"), &mut self.hide_account_names);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Streamer mode, names in statistics, prompts and logs are replaced by a short hash",
+            ));
+        });
+        self.sync_ui(ui);
+        ui.checkbox(e("Developer mode"), &mut self.dev_mode);
+        self.advanced_ui(ui);
+        #[cfg(feature = "scripting")]
+        self.scripting_ui(ui);
         if ui.button(e("Save")) {
             // TODO: replace message callback source in lib.rs
             CURRENT_ITEM.with_borrow_mut(|i| {
@@ -167,4 +1264,1249 @@ impl Settings {
             None
         }
     }
+
+    /// Global appearance of the emotes
+    pub fn style(&self) -> Style {
+        Style {
+            animation: self.animation,
+            backdrop: self.backdrop,
+            trail: self.trail,
+            ..Style::default()
+        }
+    }
+
+    pub fn playback(&self) -> Playback {
+        Playback {
+            mode: self.performance_mode,
+            animation_fps: self.animation_fps,
+            synced: self.sync_playback,
+        }
+    }
+
+    /// Whether an emote set applies to messages of a channel
+    pub fn set_applies(&self, id: &str, channel: Option<Channel>) -> bool {
+        if self.muted_sets.iter().any(|m| m == id) {
+            return false;
+        }
+        match (self.emote_set_channels.get(id), channel) {
+            (None, _) => true,
+            (Some(channels), Some(channel)) => channels.contains(&channel),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Assigns emote sets to the keybinds that switch them on and off
+    fn set_slots_ui(&mut self, emote_sets: &[EmoteSet], ui: &Ui) {
+        if !ui.collapsing_header(e("Emote set keybinds"), TreeNodeFlags::empty()) {
+            return;
+        }
+        ui.text_wrapped(e(
+            "Bind the keys in the Nexus keybinds, each key switches the assigned emote set on and off",
+        ));
+        self.set_slots.resize(SET_SLOTS, String::new());
+        let mut names = vec![e("None")];
+        names.extend(self.emote_set_ids.iter().map(|id| {
+            emote_sets
+                .iter()
+                .find(|es| &es.id == id)
+                .map_or(id.clone(), |es| es.name.clone())
+        }));
+        for (i, slot) in self.set_slots.iter_mut().enumerate() {
+            let mut selected = self
+                .emote_set_ids
+                .iter()
+                .position(|id| id == slot)
+                .map_or(0, |p| p + 1);
+            let label = format!("{} {}##emotesetslot{i}", e("Slot"), i + 1);
+            if ui.combo_simple_string(label, &mut selected, &names) {
+                *slot = match selected {
+                    0 => String::new(),
+                    n => self.emote_set_ids[n - 1].clone(),
+                };
+            }
+        }
+    }
+
+    fn squad_sync_ui(&mut self, ui: &Ui) {
+        ui.checkbox(e("Squad sync"), &mut self.squad_sync);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Use the emote sets of the commander while in their squad. Requires Unofficial Extras",
+            ));
+        });
+        ui.same_line();
+        if ui.button(e("Copy sync command")) {
+            ui.set_clipboard_text(commands::sync_command(&self.emote_set_ids));
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text(e(
+                "Paste it into squad chat as commander to share your emote sets with the squad",
+            ));
+        }
+        let temporary = squad::temporary_sets();
+        if !temporary.is_empty() {
+            ui.text_disabled(format!(
+                "{}: {}",
+                e("Squad emote sets"),
+                temporary.join(", ")
+            ));
+        }
+    }
+
+    pub fn file_selection(&self) -> FileSelection {
+        FileSelection {
+            formats: self.image_formats.clone(),
+            scale: if self.bandwidth_saver {
+                1
+            } else {
+                self.emote_scale
+            },
+            low_bandwidth: self.low_bandwidth || self.bandwidth_saver,
+        }
+    }
+
+    fn file_selection_ui(&mut self, ui: &Ui) {
+        ui.text(e("Emote files"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Formats are tried in this order, formats without a decoder are skipped. Applies after saving",
+            ));
+        });
+        let mut move_up = None;
+        let mut to_remove = None;
+        for (i, format) in self.image_formats.iter().enumerate() {
+            ui.text(format!("{}. {format:?}", i + 1));
+            ui.same_line();
+            if ui.button(e("Up") + &format!("##formatup{i}")) && i > 0 {
+                move_up = Some(i);
+            }
+            ui.same_line();
+            if ui.button(e("Remove") + &format!("##formatremove{i}")) {
+                to_remove = Some(i);
+            }
+        }
+        if let Some(i) = move_up {
+            self.image_formats.swap(i - 1, i);
+        }
+        if let Some(i) = to_remove {
+            self.image_formats.remove(i);
+        }
+        for format in <ImageFormat as VariantArray>::VARIANTS {
+            if !self.image_formats.contains(format) {
+                if ui.button(format!("{} {format:?}", e("Add"))) {
+                    self.image_formats.push(*format);
+                }
+                ui.same_line();
+            }
+        }
+        ui.new_line();
+        ui.slider(e("Download scale"), 1, 4, &mut self.emote_scale);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Resolution of the downloaded files. 1x saves VRAM, 4x stays sharp on big screens",
+            ));
+        });
+        ui.slider(e("Emote size"), 0.5, 4.0, &mut self.emote_size);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "On screen size in multiples of the 1x files. Sizes of single emotes scale on top of it",
+            ));
+        });
+        let mut scaling = <SizeScaling as VariantArray>::VARIANTS
+            .iter()
+            .position(|s| *s == self.size_scaling)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Size scaling"),
+            &mut scaling,
+            <SizeScaling as VariantNames>::VARIANTS,
+        ) {
+            self.size_scaling = <SizeScaling as VariantArray>::VARIANTS[scaling];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Resolution keeps emotes the same part of the screen as at 1080p, UiScale follows the interface size of the game",
+            ));
+        });
+        self.animation_ui(ui);
+        self.spawn_region_ui(ui);
+        let mut max_dimension = self.max_emote_dimension as i32;
+        if ui
+            .input_int(e("Max emote dimension (px)"), &mut max_dimension)
+            .build()
+        {
+            self.max_emote_dimension = max_dimension.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Larger emotes are downscaled before they are uploaded. 0 keeps the original size",
+            ));
+        });
+        let mut compression = <Compression as VariantArray>::VARIANTS
+            .iter()
+            .position(|c| *c == self.texture_compression)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Texture compression"),
+            &mut compression,
+            <Compression as VariantNames>::VARIANTS,
+        ) {
+            self.texture_compression = <Compression as VariantArray>::VARIANTS[compression];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Compresses emotes to BC7 before the upload, uses about a quarter of the video memory",
+            ));
+        });
+        ui.checkbox(e("Cache downloaded files"), &mut self.cache_files);
+        ui.checkbox(e("Cache decoded animations"), &mut self.cache_frames);
+        ui.same_line();
+        if ui.button(e("Clear cache")) {
+            frame_cache::clear(&frame_cache_dir());
+            frame_cache::clear(&file_cache_dir());
+        }
+        if ui.is_item_hovered() {
+            let size = frame_cache::size(&frame_cache_dir()) + frame_cache::size(&file_cache_dir());
+            ui.tooltip_text(format!(
+                "{}: {:.1}MiB",
+                e("Cache size"),
+                size as f32 / (1024.0 * 1024.0)
+            ));
+        }
+        ui.same_line();
+        if ui.button(e("Verify cache")) {
+            cache_cleanup::verify();
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text(e(
+                "Checks all cached files and removes damaged ones, their emotes load again on the next use",
+            ));
+        }
+        let mut max_age = self.cache_max_age_days as i32;
+        if ui
+            .input_int(e("Remove unused cache files after (days)"), &mut max_age)
+            .build()
+        {
+            self.cache_max_age_days = max_age.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "At startup, cached emotes that none of the emote sets contains anymore are deleted once they are this old. 0 keeps them",
+            ));
+        });
+        let mut max_size = self.cache_max_size_mib as i32;
+        if ui
+            .input_int(e("Cache size limit (MiB)"), &mut max_size)
+            .build()
+        {
+            self.cache_max_size_mib = max_size.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "When the caches grow past this, the emotes used the longest time ago are deleted. 0 disables the limit",
+            ));
+        });
+        cache_cleanup::status_ui(ui);
+        ui.checkbox(e("Upload preloaded emotes"), &mut self.preload_upload);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Preloaded emotes are also decoded and kept in video memory. Otherwise they only go into the file cache, which needs to be enabled",
+            ));
+        });
+        ui.checkbox(e("Always loop gifs"), &mut self.always_loop_gifs);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Some gifs are made to play once and stop on their last frame, this loops them anyway",
+            ));
+        });
+        ui.checkbox(e("Browser frame timing"), &mut self.clamp_frame_delays);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Frames with a delay of 10ms or less are shown for 100ms and no frame shorter than 20ms, like browsers do",
+            ));
+        });
+        ui.checkbox(e("Synchronized playback"), &mut self.sync_playback);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Spammed emotes animate in sync, turn it off so every emote starts on its own",
+            ));
+        });
+        self.performance_ui(ui);
+        let mut stream_above = self.stream_frames_above as i32;
+        if ui
+            .input_int(e("Stream gifs with more frames than"), &mut stream_above)
+            .build()
+        {
+            self.stream_frames_above = stream_above.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Keeps frames of long gifs in memory instead of video memory. 0 uploads all frames",
+            ));
+        });
+        let mut vram_budget = self.vram_budget_mib as i32;
+        if ui
+            .input_int(e("Video memory budget (MiB)"), &mut vram_budget)
+            .build()
+        {
+            self.vram_budget_mib = vram_budget.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Frees the least recently used emotes above this budget, they load again on their next use. 0 keeps all emotes",
+            ));
+        });
+        ui.checkbox(e("Low bandwidth"), &mut self.low_bandwidth);
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Use the smallest file instead of the format order"));
+        });
+        ui.checkbox(e("Bandwidth saver"), &mut self.bandwidth_saver);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "For metered or slow connections. Downloads the smallest files one at a time and skips preloading",
+            ));
+        });
+    }
+
+    fn performance_ui(&mut self, ui: &Ui) {
+        let mut mode = <PerformanceMode as VariantArray>::VARIANTS
+            .iter()
+            .position(|m| *m == self.performance_mode)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Performance mode"),
+            &mut mode,
+            <PerformanceMode as VariantNames>::VARIANTS,
+        ) {
+            self.performance_mode = <PerformanceMode as VariantArray>::VARIANTS[mode];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "CapFps limits how often animated emotes change their frame, FirstFrame shows animated emotes as still images",
+            ));
+        });
+        if self.performance_mode == PerformanceMode::CapFps {
+            let mut fps = self.animation_fps as i32;
+            if ui.input_int(e("Animation fps"), &mut fps).build() {
+                self.animation_fps = fps.max(1) as u32;
+            }
+        }
+        let mut budget = self.frame_budget_us as i32;
+        if ui.input_int(e("Frame budget (us)"), &mut budget).build() {
+            self.frame_budget_us = budget.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Time per frame spent on emotes, busy frames continue with the remaining emotes on the next one. 0 disables the limit",
+            ));
+        });
+        let mut threads = self.worker_threads as i32;
+        if ui.input_int(e("Background threads"), &mut threads).build() {
+            self.worker_threads = threads.clamp(0, 16) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Threads loading emote sets and cleaning up the caches. 0 picks them based on the processor. Applies after a restart",
+            ));
+        });
+    }
+
+    /// `None` when the frame budget is disabled
+    pub fn frame_budget(&self) -> Option<Duration> {
+        (self.frame_budget_us > 0).then(|| Duration::from_micros(self.frame_budget_us as u64))
+    }
+
+    /// Factor of the emote size for the current screen, 1 without the nexus link
+    pub fn display_scale(&self) -> f32 {
+        let Some(link) = read_nexus_link() else {
+            return 1.0;
+        };
+        let scale = match self.size_scaling {
+            SizeScaling::None => 1.0,
+            SizeScaling::Resolution => link.height as f32 / REFERENCE_HEIGHT,
+            SizeScaling::UiScale => link.scaling,
+        };
+        // the link is zeroed before the game has a window
+        if scale > 0.0 { scale } else { 1.0 }
+    }
+
+    pub fn lifespan(&self) -> Lifespan {
+        Lifespan {
+            lifetime: self.lifetime,
+            secs: self.lifetime_secs,
+            linger_height: self.linger_height_percent.min(100) as f32 / 100.0,
+        }
+    }
+
+    /// Emotes on screen at the same time, hand edited files may go past the limit of the slider
+    pub fn active_limit(&self) -> usize {
+        self.max_active_emotes.clamp(1, MAX_ACTIVE_EMOTES_LIMIT) as usize
+    }
+
+    /// Spawn region as left, top, right and bottom edge in fractions of the screen
+    pub fn spawn_area(&self) -> [f32; 4] {
+        self.spawn_region.rect(self.spawn_rect)
+    }
+
+    fn spawn_region_ui(&mut self, ui: &Ui) {
+        let mut region = <SpawnRegion as VariantArray>::VARIANTS
+            .iter()
+            .position(|r| *r == self.spawn_region)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Spawn region"),
+            &mut region,
+            <SpawnRegion as VariantNames>::VARIANTS,
+        ) {
+            self.spawn_region = <SpawnRegion as VariantArray>::VARIANTS[region];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Part of the screen new emotes appear in. AboveChat assumes the chat box at its default size",
+            ));
+        });
+        ui.slider(e("Spawn margin"), 0.0, 0.45, &mut self.spawn_margin);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Keeps new emotes away from the edges of the region, as a fraction of its size",
+            ));
+        });
+        if self.spawn_region == SpawnRegion::Custom {
+            region_editor(&mut self.spawn_rect, ui);
+        }
+    }
+
+    fn animation_ui(&mut self, ui: &Ui) {
+        let paths = motion::names();
+        let mut selected = paths
+            .iter()
+            .position(|name| *name == self.motion)
+            .unwrap_or_default();
+        if ui.combo_simple_string(e("Motion"), &mut selected, &paths) {
+            self.motion = paths[selected].to_string();
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Path of the emotes across the screen"));
+        });
+        style_combos(&mut self.animation, &mut self.backdrop, ui);
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Keeps light emotes visible over bright skies"));
+        });
+        ui.checkbox(e("Motion trail"), &mut self.trail);
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Draws fading copies along the recent path of emotes"));
+        });
+        let mut filtering = <Filtering as VariantArray>::VARIANTS
+            .iter()
+            .position(|f| *f == self.texture_filtering)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Texture filtering"),
+            &mut filtering,
+            <Filtering as VariantNames>::VARIANTS,
+        ) {
+            self.texture_filtering = <Filtering as VariantArray>::VARIANTS[filtering];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Point keeps pixel art emotes sharp, linear is smoother"));
+        });
+        ui.checkbox(e("Adapt to scene brightness"), &mut self.adaptive_tint);
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Dims emotes at night and in instances and strengthens shadows and outlines in bright maps",
+            ));
+        });
+    }
+
+    fn download_limits_ui(&mut self, ui: &Ui) {
+        ui.text(e("Download limits"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Parallel emote downloads (0 is automatic) and the minimum delay between them per provider",
+            ));
+        });
+        let t = ui.begin_table("download limits", 3);
+        for name in provider_names() {
+            let mut limits = self
+                .download_limits
+                .get(name)
+                .copied()
+                .or_else(|| provider_by_name(name).map(|p| p.download_limits()))
+                .unwrap_or_default();
+            let mut max_concurrent = limits.max_concurrent as i32;
+            let mut interval_ms = limits.interval_ms as i32;
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(name);
+            ui.table_next_column();
+            let mut changed = ui
+                .input_int(
+                    e("Parallel") + &format!("##downloadparallel{name}"),
+                    &mut max_concurrent,
+                )
+                .build();
+            ui.table_next_column();
+            changed |= ui
+                .input_int(
+                    e("Delay (ms)") + &format!("##downloaddelay{name}"),
+                    &mut interval_ms,
+                )
+                .build();
+            if changed {
+                limits.max_concurrent = max_concurrent.max(0) as u32;
+                limits.interval_ms = interval_ms.max(0) as u32;
+                self.download_limits.insert(name.to_string(), limits);
+            }
+        }
+        drop(t);
+        let mut fetch_threads = self.fetch_threads as i32;
+        if ui
+            .input_int(e("Parallel set requests"), &mut fetch_threads)
+            .build()
+        {
+            self.fetch_threads = fetch_threads.max(1) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Emote sets and the data of large sets are requested this many at a time",
+            ));
+        });
+        let mut connect_timeout = self.connect_timeout_secs as i32;
+        if ui
+            .input_int(e("Connect timeout (s)"), &mut connect_timeout)
+            .build()
+        {
+            self.connect_timeout_secs = connect_timeout.max(0) as u32;
+        }
+        let mut read_timeout = self.read_timeout_secs as i32;
+        if ui
+            .input_int(e("Read timeout (s)"), &mut read_timeout)
+            .build()
+        {
+            self.read_timeout_secs = read_timeout.max(0) as u32;
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Requests that take longer fail and are retried. Raise them on slow connections, 0 disables them",
+            ));
+        });
+    }
+
+    fn proxy_ui(&mut self, ui: &Ui) {
+        if !ui.collapsing_header(e("Proxy"), TreeNodeFlags::empty()) {
+            return;
+        }
+        ui.input_text(e("Host") + "##proxyhost", &mut self.proxy.host)
+            .build();
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Proxy for all downloads, e.g. proxy.example.com or socks5://localhost. Empty uses the system environment",
+            ));
+        });
+        let mut port = self.proxy.port as i32;
+        if ui.input_int(e("Port") + "##proxyport", &mut port).build() {
+            self.proxy.port = port.clamp(0, u16::MAX as i32) as u16;
+        }
+        ui.input_text(e("Username") + "##proxyuser", &mut self.proxy.username)
+            .build();
+        ui.input_text(e("Password") + "##proxypassword", &mut self.proxy.password)
+            .password(true)
+            .build();
+        if ui.is_item_deactivated_after_edit()
+            && let Err(e) = credentials::write(PROXY_PASSWORD, &self.proxy.password)
+        {
+            log::error!("Failed to store the proxy password: {e:#}");
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "The proxy settings are stored locally and never exported, the password in the Windows credential manager. They apply after saving",
+            ));
+        });
+    }
+
+    /// Replaces everything but the local sync credentials and the proxy
+    pub fn apply_import(&mut self, imported: Settings) {
+        let gist_id = std::mem::take(&mut self.gist_id);
+        self.replace_keeping_local(imported);
+        self.gist_id = gist_id;
+    }
+
+    fn sync_ui(&mut self, ui: &Ui) {
+        if !ui.collapsing_header(e("Import / Export"), TreeNodeFlags::empty()) {
+            return;
+        }
+        ui.input_text(e("Sync folder") + "##syncfolder", &mut self.sync_folder)
+            .build();
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Settings are mirrored to this folder, e.g. in OneDrive. On load the newer settings win, a conflicting local copy is kept as settings.conflict.json",
+            ));
+        });
+        ui.input_text(e("Github token") + "##gisttoken", &mut self.gist_token)
+            .password(true)
+            .build();
+        if ui.is_item_deactivated_after_edit()
+            && let Err(e) = credentials::write(GIST_TOKEN, &self.gist_token)
+        {
+            log::error!("Failed to store the github token: {e:#}");
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Personal access token with the gist scope. It is kept in the Windows credential manager and never exported",
+            ));
+        });
+        if ui.button(e("Export to gist")) {
+            gist::export(self);
+        }
+        if !self.gist_id.is_empty() {
+            ui.same_line();
+            ui.text_disabled(&self.gist_id);
+        }
+        thread_local! {
+            static URL: RefCell<String> = const { RefCell::new(String::new()) };
+        }
+        URL.with_borrow_mut(|url| {
+            ui.input_text(e("URL") + "##gistimporturl", url).build();
+            ui.help_marker(|| {
+                ui.tooltip_text(e("Gist url or any url serving the raw settings json"));
+            });
+            if ui.button(e("Import")) && !url.is_empty() {
+                gist::import(url.clone());
+            }
+        });
+        match gist::status() {
+            Some(Ok(message)) => ui.text(message),
+            Some(Err(error)) => ui.text_colored([0.9, 0.3, 0.3, 1.0], error),
+            None => {}
+        }
+    }
+
+    fn advanced_ui(&self, ui: &Ui) {
+        if !ui.collapsing_header(e("Advanced"), TreeNodeFlags::empty()) {
+            return;
+        }
+        ui.text(e("Settings history"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Every change you make is appended to audit.log in the addon folder",
+            ));
+        });
+        audit::history_ui(ui);
+    }
+
+    #[cfg(feature = "scripting")]
+    fn scripting_ui(&mut self, ui: &Ui) {
+        if !ui.collapsing_header(e("Scripts"), TreeNodeFlags::empty()) {
+            return;
+        }
+        if ui.checkbox(e("Run scripts"), &mut self.scripting) {
+            if self.scripting {
+                crate::scripting::load();
+            } else {
+                crate::scripting::unload();
+            }
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Runs the .rhai files of the scripts folder in the addon directory on every chat message",
+            ));
+        });
+        if !self.scripting {
+            return;
+        }
+        if ui.button(e("Reload scripts")) {
+            crate::scripting::load();
+        }
+        crate::scripting::status_ui(ui);
+    }
+
+    /// Offers to add 7tv links from the clipboard. Returns the emote set id to add
+    fn clipboard_ui(&mut self, ui: &Ui) -> Option<String> {
+        ui.checkbox(
+            e("Watch clipboard for 7tv links"),
+            &mut self.watch_clipboard,
+        );
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "While this window is open, copied 7tv emote set, user or emote links can be added with one click",
+            ));
+        });
+        if !self.watch_clipboard {
+            return None;
+        }
+        thread_local! {
+            // clipboard content that was already handled or dismissed
+            static LAST_CLIPBOARD: RefCell<String> = const { RefCell::new(String::new()) };
+            static EMOTE_NAME: RefCell<String> = const { RefCell::new(String::new()) };
+            // reading the clipboard asks the os every time, it's only read once per interval
+            static CLIPBOARD: RefCell<String> = const { RefCell::new(String::new()) };
+            static LAST_POLL: Cell<Option<Instant>> = const { Cell::new(None) };
+        }
+        let now = Instant::now();
+        if LAST_POLL
+            .get()
+            .is_none_or(|last| now - last >= CLIPBOARD_POLL_INTERVAL)
+        {
+            LAST_POLL.set(Some(now));
+            CLIPBOARD.set(ui.clipboard_text().unwrap_or_default());
+        }
+        let clipboard = CLIPBOARD.with_borrow(Clone::clone);
+        if LAST_CLIPBOARD.with_borrow(|last| last == &clipboard) {
+            return None;
+        }
+        let link = parse_7tv_link(&clipboard)?;
+        let mut add_set = None;
+        let mut handled = false;
+        match link {
+            SevenTvLink::EmoteSet(id) => {
+                ui.text(format!(
+                    "{} {id}",
+                    e("Clipboard contains the 7tv emote set")
+                ));
+                ui.same_line();
+                if ui.button(e("Add this set") + "##clipboardadd") {
+                    add_set = Some(id);
+                    handled = true;
+                }
+            }
+            SevenTvLink::Emote(id) => EMOTE_NAME.with_borrow_mut(|name| {
+                ui.text(format!("{} {id}", e("Clipboard contains the 7tv emote")));
+                ui.input_text(e("Name") + "##clipboardemotename", name)
+                    .build();
+                ui.same_line();
+                if ui.button(e("Add as custom emote") + "##clipboardadd")
+                    && !name.is_empty()
+                    && let Some(url) = provider_by_name("7tv")
+                        .and_then(|provider| provider.emote_url(&id, &self.file_selection()))
+                {
+                    self.custom_emotes.retain(|c| &c.name != name);
+                    self.custom_emotes.push(CustomEmote {
+                        name: std::mem::take(name),
+                        url,
+                    });
+                    handled = true;
+                }
+            }),
+        }
+        ui.same_line();
+        if ui.button(e("Dismiss") + "##clipboarddismiss") {
+            handled = true;
+        }
+        if handled {
+            LAST_CLIPBOARD.set(clipboard);
+        }
+        add_set
+    }
+
+    fn emote_browser_ui(&mut self, emote_sets: &[EmoteSet], ui: &Ui) {
+        if !ui.collapsing_header(e("Emote Browser"), TreeNodeFlags::empty()) {
+            return;
+        }
+        thread_local! {
+            static SEARCH: RefCell<String> = const { RefCell::new(String::new()) };
+        }
+        ui.checkbox(e("Show emote quickbar"), &mut self.show_quickbar);
+        ui.help_marker(|| {
+            ui.tooltip_text(e("Drag emotes from the list below into the quickbar"));
+        });
+        SEARCH.with_borrow_mut(|search| {
+            ui.input_text(e("Search") + "##emotebrowsersearch", search)
+                .build();
+            let search = search.to_lowercase();
+            for emote_set in emote_sets {
+                if !ui.collapsing_header(
+                    format!("{}##emotebrowser{}", emote_set.name, emote_set.id),
+                    TreeNodeFlags::empty(),
+                ) {
+                    continue;
+                }
+                ui.indent();
+                for emote in emote_set
+                    .emotes
+                    .iter()
+                    .filter(|emote| emote.name.to_lowercase().contains(&search))
+                {
+                    let mut enabled = !self.is_disabled(&emote_set.id, &emote.name);
+                    if ui.checkbox(
+                        format!("{}##emotebrowser{}{}", emote.name, emote_set.id, emote.name),
+                        &mut enabled,
+                    ) {
+                        if enabled {
+                            self.disabled_emotes
+                                .retain(|d| !d.matches(&emote_set.id, &emote.name));
+                        } else {
+                            self.disabled_emotes.push(DisabledEmote {
+                                set_id: emote_set.id.clone(),
+                                name: emote.name.clone(),
+                            });
+                        }
+                    }
+                    if let Some(_tooltip) = DragDropSource::new(FAVORITE_PAYLOAD)
+                        .begin_payload(ui, payload_key(emote_set, emote))
+                    {
+                        ui.text(&emote.name);
+                    }
+                }
+                ui.unindent();
+            }
+        });
+    }
+
+    fn custom_emotes_ui(&mut self, ui: &Ui) {
+        ui.text(e("Custom Emotes"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Map an emote name to an image URL. Gifs are animated like 7tv emotes",
+            ));
+        });
+        let t = ui.begin_table("custom emotes", 3);
+        let mut to_remove = Vec::new();
+        for (i, custom) in self.custom_emotes.iter().enumerate() {
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(&custom.name);
+            ui.table_next_column();
+            ui.link(&custom.url, &custom.url);
+            ui.table_next_column();
+            if ui.button(e("Remove") + &format!("##customemoteremove{i}")) {
+                to_remove.push(i);
+            }
+        }
+        for tr in to_remove {
+            self.custom_emotes.remove(tr);
+        }
+        ui.table_next_row();
+        thread_local! {
+            static CUSTOM: RefCell<CustomEmote> = RefCell::new(CustomEmote::default());
+        }
+        CUSTOM.with_borrow_mut(|custom| {
+            ui.table_next_column();
+            ui.input_text(e("Name") + "##customemotename", &mut custom.name)
+                .build();
+            ui.table_next_column();
+            ui.input_text(e("URL") + "##customemoteurl", &mut custom.url)
+                .build();
+            ui.table_next_column();
+            if ui.button(e("Add") + "##customemoteadd")
+                && !custom.name.is_empty()
+                && !custom.url.is_empty()
+            {
+                self.custom_emotes.retain(|c| c.name != custom.name);
+                self.custom_emotes.push(std::mem::take(custom));
+            }
+        });
+        drop(t);
+    }
+
+    pub fn emote_size_factor(&self, name: &str) -> f32 {
+        self.emote_size_overrides.get(name).copied().unwrap_or(1.0)
+    }
+
+    /// Resolves an alias to the emote name it points to
+    pub fn resolve_alias<'a>(&'a self, word: &'a str) -> &'a str {
+        self.aliases
+            .iter()
+            .find(|a| a.alias == word)
+            .map_or(word, |a| a.emote.as_str())
+    }
+
+    /// Whether the emote was turned off in the emote browser for this set
+    pub fn is_disabled(&self, set_id: &str, name: &str) -> bool {
+        self.disabled_emotes.iter().any(|d| d.matches(set_id, name))
+    }
+
+    /// Whether an alias replaces the emote name, so the name alone is a normal word again
+    pub fn is_replaced(&self, word: &str) -> bool {
+        self.aliases
+            .iter()
+            .any(|a| a.replace && a.emote == word && a.alias != word)
+    }
+
+    fn aliases_ui(&mut self, ui: &Ui) {
+        ui.text(e("Aliases"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Typing the alias in chat triggers the emote as well, the emote name keeps working. Check Replace to rename the emote, then only the alias triggers it",
+            ));
+        });
+        let t = ui.begin_table("emote aliases", 4);
+        let mut to_remove = Vec::new();
+        for (i, alias) in self.aliases.iter_mut().enumerate() {
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(&alias.alias);
+            ui.table_next_column();
+            ui.text(&alias.emote);
+            ui.table_next_column();
+            ui.checkbox(
+                e("Replace") + &format!("##aliasreplace{i}"),
+                &mut alias.replace,
+            );
+            if ui.is_item_hovered() {
+                ui.tooltip_text(if alias.replace {
+                    e("Only the alias triggers the emote")
+                } else {
+                    e("The alias and the emote name both trigger the emote")
+                });
+            }
+            ui.table_next_column();
+            if ui.button(e("Remove") + &format!("##aliasremove{i}")) {
+                to_remove.push(i);
+            }
+        }
+        for tr in to_remove {
+            self.aliases.remove(tr);
+        }
+        ui.table_next_row();
+        thread_local! {
+            static ALIAS: RefCell<EmoteAlias> = RefCell::new(EmoteAlias::default());
+        }
+        ALIAS.with_borrow_mut(|alias| {
+            ui.table_next_column();
+            ui.input_text(e("Alias") + "##aliasinput", &mut alias.alias)
+                .build();
+            ui.table_next_column();
+            ui.input_text(e("Emote") + "##aliasemoteinput", &mut alias.emote)
+                .build();
+            ui.table_next_column();
+            ui.checkbox(e("Replace") + "##aliasreplaceinput", &mut alias.replace);
+            ui.help_marker(|| {
+                ui.tooltip_text(e("The emote name alone no longer triggers the emote"));
+            });
+            ui.table_next_column();
+            if ui.button(e("Add") + "##aliasadd")
+                && !alias.alias.is_empty()
+                && !alias.emote.is_empty()
+            {
+                self.aliases.retain(|a| a.alias != alias.alias);
+                self.aliases.push(std::mem::take(alias));
+            }
+        });
+        drop(t);
+    }
+
+    /// Size overrides of single emotes, the statistics window sets them too
+    fn emote_sizes_ui(&mut self, ui: &Ui) {
+        ui.text(e("Emote sizes"));
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Scales single emotes on top of the emote size, from 0.5 (50%) to 3 (300%)",
+            ));
+        });
+        let t = ui.begin_table("emote sizes", 3);
+        let mut names = self
+            .emote_size_overrides
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        let mut to_remove = None;
+        for (i, name) in names.iter().enumerate() {
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(name);
+            ui.table_next_column();
+            if let Some(size) = self.emote_size_overrides.get_mut(name) {
+                ui.slider(format!("##emotesize{i}"), 0.5, 3.0, size);
+            }
+            ui.table_next_column();
+            if ui.button(e("Remove") + &format!("##emotesizeremove{i}")) {
+                to_remove = Some(name.clone());
+            }
+        }
+        if let Some(name) = to_remove {
+            self.emote_size_overrides.remove(&name);
+        }
+        ui.table_next_row();
+        thread_local! {
+            static NAME: RefCell<String> = const { RefCell::new(String::new()) };
+            static SIZE: Cell<f32> = const { Cell::new(1.5) };
+        }
+        NAME.with_borrow_mut(|name| {
+            ui.table_next_column();
+            ui.input_text(e("Emote") + "##emotesizeinput", name).build();
+            ui.table_next_column();
+            let mut size = SIZE.get();
+            if ui.slider("##emotesizenew", 0.5, 3.0, &mut size) {
+                SIZE.set(size);
+            }
+            ui.table_next_column();
+            // the default size doesn't need an override
+            if ui.button(e("Add") + "##emotesizeadd") && !name.is_empty() && size != 1.0 {
+                self.emote_size_overrides.insert(std::mem::take(name), size);
+            }
+        });
+        drop(t);
+    }
+
+    /// Warns about emote names that are common words of the chat language
+    fn collisions_ui(&mut self, emote_sets: &[EmoteSet], ui: &Ui) {
+        let mut language = <ChatLanguage as VariantArray>::VARIANTS
+            .iter()
+            .position(|l| *l == self.chat_language)
+            .unwrap_or_default();
+        if ui.combo_simple_string(
+            e("Chat language"),
+            &mut language,
+            <ChatLanguage as VariantNames>::VARIANTS,
+        ) {
+            self.chat_language = <ChatLanguage as VariantArray>::VARIANTS[language];
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Emote names are checked against common words of this language",
+            ));
+        });
+        let custom = EmoteSet::custom(&self.custom_emotes);
+        let sets = emote_sets
+            .iter()
+            .chain(std::iter::once(&custom))
+            .collect::<Vec<_>>();
+        let collisions = collisions::find(&sets, self.chat_language)
+            .into_iter()
+            .filter(|c| {
+                !self.ignored_collisions.contains(&c.name)
+                    && provider::find_emote_with_set(&sets, &c.name, &self.disabled_emotes)
+                        .is_some()
+                    && !self.is_replaced(&c.name)
+            })
+            .collect::<Vec<_>>();
+        if collisions.is_empty() {
+            return;
+        }
+        ui.text_colored(
+            [0.9, 0.8, 0.3, 1.0],
+            format!("{} {}", collisions.len(), e("emote names are common words")),
+        );
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Normal sentences containing these words spawn the emote. An alias that replaces the name avoids that",
+            ));
+        });
+        for (i, collision) in collisions.iter().enumerate() {
+            let alias = collisions::suggested_alias(&collision.name);
+            ui.text(format!("{} ({})", collision.name, collision.set));
+            ui.same_line();
+            if ui.button(format!("{} {alias}##collisionalias{i}", e("Use"))) {
+                self.aliases.retain(|a| a.alias != alias);
+                self.aliases.push(EmoteAlias {
+                    alias,
+                    emote: collision.name.clone(),
+                    replace: true,
+                });
+            }
+            ui.same_line();
+            if ui.button(e("Ignore") + &format!("##collisionignore{i}")) {
+                self.ignored_collisions.push(collision.name.clone());
+            }
+        }
+    }
+}
+
+/// How often the clipboard is checked for 7tv links while the settings are open
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+enum SevenTvLink {
+    /// Emote set or user id, both are accepted as emote set id
+    EmoteSet(String),
+    Emote(String),
+}
+
+fn parse_7tv_link(text: &str) -> Option<SevenTvLink> {
+    let url = url::Url::parse(text.trim()).ok()?;
+    if !url.host_str()?.ends_with("7tv.app") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    let kind = segments.next()?;
+    let id = segments.next().filter(|id| !id.is_empty())?.to_string();
+    match kind {
+        "emote-sets" | "users" => Some(SevenTvLink::EmoteSet(id)),
+        "emotes" => Some(SevenTvLink::Emote(id)),
+        _ => None,
+    }
+}
+
+/// The screen in miniature with the custom spawn region.
+/// Dragging the region moves it, dragging one of its corners resizes it
+fn region_editor(rect: &mut [f32; 4], ui: &Ui) {
+    thread_local! {
+        static DRAGGING: Cell<Option<RegionDrag>> = const { Cell::new(None) };
+    }
+    let [screen_width, screen_height] =
+        read_nexus_link().map_or([16.0, 9.0], |link| [link.width as f32, link.height as f32]);
+    let width = ui.content_region_avail()[0].min(REGION_EDITOR_WIDTH);
+    let size = [width, width * screen_height / screen_width.max(1.0)];
+    let origin = ui.cursor_screen_pos();
+    ui.invisible_button("spawnregion", size);
+    let to_screen = |x: f32, y: f32| [origin[0] + x * size[0], origin[1] + y * size[1]];
+    if ui.is_item_active() {
+        let [x0, y0, x1, y1] = *rect;
+        let mouse = ui.io().mouse_pos;
+        let near = |[x, y]: [f32; 2]| (mouse[0] - x).hypot(mouse[1] - y) <= HANDLE_RADIUS * 2.0;
+        let [left, top] = to_screen(x0, y0);
+        let [right, bottom] = to_screen(x1, y1);
+        let drag = DRAGGING.get().or_else(|| {
+            if near([left, top]) {
+                Some(RegionDrag::TopLeft)
+            } else if near([right, bottom]) {
+                Some(RegionDrag::BottomRight)
+            } else {
+                let inside =
+                    (left..=right).contains(&mouse[0]) && (top..=bottom).contains(&mouse[1]);
+                inside.then_some(RegionDrag::Move)
+            }
+        });
+        DRAGGING.set(drag);
+        let [dx, dy] = ui.io().mouse_delta;
+        let (dx, dy) = (dx / size[0].max(1.0), dy / size[1].max(1.0));
+        match drag {
+            Some(RegionDrag::TopLeft) => {
+                rect[0] = (x0 + dx).clamp(0.0, x1 - MIN_REGION);
+                rect[1] = (y0 + dy).clamp(0.0, y1 - MIN_REGION);
+            }
+            Some(RegionDrag::BottomRight) => {
+                rect[2] = (x1 + dx).clamp(x0 + MIN_REGION, 1.0);
+                rect[3] = (y1 + dy).clamp(y0 + MIN_REGION, 1.0);
+            }
+            Some(RegionDrag::Move) => {
+                let dx = dx.clamp(-x0, 1.0 - x1);
+                let dy = dy.clamp(-y0, 1.0 - y1);
+                *rect = [x0 + dx, y0 + dy, x1 + dx, y1 + dy];
+            }
+            None => {}
+        }
+    } else {
+        DRAGGING.set(None);
+    }
+    let [x0, y0, x1, y1] = *rect;
+    let (top_left, bottom_right) = (to_screen(x0, y0), to_screen(x1, y1));
+    let draw_list = ui.get_window_draw_list();
+    draw_list
+        .add_rect(
+            origin,
+            [origin[0] + size[0], origin[1] + size[1]],
+            [0.5, 0.5, 0.5, 1.0],
+        )
+        .build();
+    draw_list
+        .add_rect(top_left, bottom_right, [0.4, 0.7, 1.0, 0.3])
+        .filled(true)
+        .build();
+    draw_list
+        .add_rect(top_left, bottom_right, [0.4, 0.7, 1.0, 1.0])
+        .build();
+    for corner in [top_left, bottom_right] {
+        draw_list
+            .add_circle(corner, HANDLE_RADIUS, [1.0, 1.0, 1.0, 1.0])
+            .filled(true)
+            .build();
+    }
+}
+
+/// Rotation, skew and backdrop combos, shared by the global and the per set style
+pub fn style_combos(animation: &mut Animation, backdrop: &mut Backdrop, ui: &Ui) {
+    let mut rotation = <Rotation as VariantArray>::VARIANTS
+        .iter()
+        .position(|r| *r == animation.rotation)
+        .unwrap_or_default();
+    if ui.combo_simple_string(
+        e("Rotation"),
+        &mut rotation,
+        <Rotation as VariantNames>::VARIANTS,
+    ) {
+        animation.rotation = <Rotation as VariantArray>::VARIANTS[rotation];
+    }
+    let mut skew = <Skew as VariantArray>::VARIANTS
+        .iter()
+        .position(|s| *s == animation.skew)
+        .unwrap_or_default();
+    if ui.combo_simple_string(e("Skew"), &mut skew, <Skew as VariantNames>::VARIANTS) {
+        animation.skew = <Skew as VariantArray>::VARIANTS[skew];
+    }
+    let mut selected = <Backdrop as VariantArray>::VARIANTS
+        .iter()
+        .position(|b| b == backdrop)
+        .unwrap_or_default();
+    if ui.combo_simple_string(
+        e("Backdrop"),
+        &mut selected,
+        <Backdrop as VariantNames>::VARIANTS,
+    ) {
+        *backdrop = <Backdrop as VariantArray>::VARIANTS[selected];
+    }
+}
+
+/// Popup to give an emote set its own style, which starts out as a copy of `global`
+fn set_style_ui(
+    set_styles: &mut HashMap<String, Style>,
+    id: &str,
+    i: usize,
+    global: Style,
+    ui: &Ui,
+) {
+    let popup = format!("emotesetstyle{i}");
+    let label = if set_styles.contains_key(id) {
+        e("Own style")
+    } else {
+        e("Global style")
+    };
+    if ui.button(label + &format!("##{popup}")) {
+        ui.open_popup(&popup);
+    }
+    ui.popup(&popup, || {
+        let mut own = set_styles.contains_key(id);
+        if ui.checkbox(e("Own style"), &mut own) {
+            if own {
+                set_styles.insert(id.to_string(), global);
+            } else {
+                set_styles.remove(id);
+            }
+        }
+        let Some(style) = set_styles.get_mut(id) else {
+            return;
+        };
+        style_combos(&mut style.animation, &mut style.backdrop, ui);
+        ui.checkbox(e("Motion trail"), &mut style.trail);
+        ui.color_edit4(e("Color"), &mut style.color);
+    });
+}
+
+/// Popup to restrict an emote set to some chat channels
+fn channels_ui(set_channels: &mut HashMap<String, Vec<Channel>>, id: &str, i: usize, ui: &Ui) {
+    let popup = format!("emotesetchannels{i}");
+    let label = if set_channels.contains_key(id) {
+        e("Channels")
+    } else {
+        e("All channels")
+    };
+    if ui.button(label + &format!("##{popup}")) {
+        ui.open_popup(&popup);
+    }
+    ui.popup(&popup, || {
+        let mut all = !set_channels.contains_key(id);
+        if ui.checkbox(e("All channels"), &mut all) {
+            if all {
+                set_channels.remove(id);
+            } else {
+                set_channels.insert(id.to_string(), Vec::new());
+            }
+        }
+        let Some(channels) = set_channels.get_mut(id) else {
+            return;
+        };
+        for (channel, name) in <Channel as VariantArray>::VARIANTS
+            .iter()
+            .zip(<Channel as VariantNames>::VARIANTS)
+        {
+            let mut enabled = channels.contains(channel);
+            if ui.checkbox(name, &mut enabled) {
+                if enabled {
+                    channels.push(*channel);
+                } else {
+                    channels.retain(|c| c != channel);
+                }
+            }
+        }
+    });
 }
@@ -0,0 +1,66 @@
+//! Emote set responses on disk with their etag and last modified date, or a hash of the set
+//! for the v4 api that has neither. Refreshes send conditional requests and the cached set is
+//! used on a 304, an unchanged hash or a network failure.
+use super::EmoteSet;
+use crate::frame_cache::stable_hash;
+use crate::setting_dir;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+/// Validators of a cached response, sent back as `If-None-Match` and `If-Modified-Since`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Hash of a set from the v4 api, compared after the download instead
+    pub content_hash: Option<u64>,
+}
+
+impl Validators {
+    /// Whether a conditional request is possible
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+pub fn content_hash(set: &EmoteSet) -> u64 {
+    // serializing a set we just parsed can't fail
+    stable_hash(&serde_json::to_vec(set).unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CachedSet {
+    pub validators: Validators,
+    pub set: EmoteSet,
+}
+
+fn path(id: &str) -> PathBuf {
+    setting_dir()
+        .join("set_cache")
+        .join(format!("{:016x}.json", stable_hash(id.as_bytes())))
+}
+
+pub fn load(id: &str) -> Option<CachedSet> {
+    let json = fs::read_to_string(path(id)).ok()?;
+    match serde_json::from_str(&json) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            log::warn!("Discarding cached emote set {id}: {e}");
+            None
+        }
+    }
+}
+
+pub fn store(id: &str, validators: &Validators, set: &EmoteSet) {
+    let path = path(id);
+    let json = json!({ "validators": validators, "set": set });
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, json.to_string()));
+    if let Err(e) = result {
+        log::error!("Failed to cache emote set {id}: {e}");
+    }
+}
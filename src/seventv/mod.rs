@@ -0,0 +1,470 @@
+use crate::net::{self, CircuitBreaker, RateLimiter, Retry};
+use crate::provider::{self, EmoteProvider, FileCandidate, FileSelection, ImageFormat};
+use crate::util::{UiExt, e};
+use anyhow::Result;
+use cache::Validators;
+use nexus::imgui::Ui;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use url::Url;
+
+mod cache;
+mod v4;
+
+/// 7tv answers bursts with 429s, every api request takes a token first. The cdn is not limited
+static API_LIMIT: RateLimiter = RateLimiter::new("7tv api", 10, 5);
+/// While 7tv is down, set downloads and refreshes fail right away instead of retrying each
+static API_BREAKER: CircuitBreaker = CircuitBreaker::new(
+    "7tv",
+    5,
+    Duration::from_secs(60),
+    Duration::from_secs(30 * 60),
+);
+
+/// Banner in the options while 7tv is unreachable, the log is easy to miss
+pub fn status_ui(ui: &Ui) {
+    let Some((error, retry_in)) = API_BREAKER.status() else {
+        return;
+    };
+    ui.text_colored(
+        [0.9, 0.3, 0.3, 1.0],
+        format!(
+            "{} {}s",
+            e("7tv is unreachable, retrying in"),
+            retry_in.as_secs()
+        ),
+    );
+    ui.help_marker(|| {
+        ui.tooltip_text(error);
+    });
+}
+
+// Represents an owner with dynamic style.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Owner {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub avatar_url: String,
+    pub style: Value,
+    // pub role_: Vec<String>,
+}
+
+// Enum for File.format with variants for "AVIF" and "WEBP".
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum FileFormat {
+    Avif,
+    Webp,
+    Gif,
+    Png,
+    #[serde(other)]
+    Unknown,
+}
+
+// Represents a file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct File {
+    pub name: String,
+    pub static_name: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: u32,
+    pub size: u32,
+    pub format: FileFormat,
+}
+
+impl File {
+    fn candidate(&self) -> Option<FileCandidate> {
+        let format = match self.format {
+            FileFormat::Avif => ImageFormat::Avif,
+            FileFormat::Webp => ImageFormat::WebP,
+            FileFormat::Gif => ImageFormat::Gif,
+            FileFormat::Png => ImageFormat::Png,
+            FileFormat::Unknown => return None,
+        };
+        // names look like `3x.webp`, static variants like `3x_static.webp` are skipped
+        let (scale, rest) = self.name.split_once('x')?;
+        if rest.contains("static") {
+            return None;
+        }
+        Some(FileCandidate {
+            format,
+            scale: scale.parse().ok()?,
+            size: self.size,
+        })
+    }
+}
+
+// Represents the host containing URL and a list of files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Host {
+    pub url: String,
+    pub files: Vec<File>,
+}
+
+// Extra data for an emote.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmoteData {
+    pub id: String,
+    pub name: String,
+    pub state: Vec<String>,
+    pub listed: bool,
+    pub animated: bool,
+    // pub owner: Owner,
+    pub host: Host,
+}
+
+// Represents an emote.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Emote {
+    pub id: String,
+    pub name: String,
+    pub flags: u32,
+    pub timestamp: u64,
+    pub actor_id: Value, // using Value for unknown actor_id
+    /// Missing for some emotes of very large sets, see [`fill_missing_data`]
+    #[serde(default)]
+    pub data: Option<EmoteData>,
+}
+
+impl Emote {
+    pub fn zero_width(&self) -> bool {
+        // TODO figure out if there are other flags
+        self.flags == 1
+    }
+    pub fn find_file(&self, selection: &FileSelection) -> Option<&File> {
+        let data = self.data.as_ref()?;
+        let (files, candidates): (Vec<_>, Vec<_>) = data
+            .host
+            .files
+            .iter()
+            .filter_map(|file| Some((file, file.candidate()?)))
+            .unzip();
+        let index = selection.pick(&candidates, data.animated)?;
+        Some(files[index])
+    }
+
+    pub fn resolve(&self, selection: &FileSelection) -> Result<provider::Emote> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No data for emote {}", self.name))?;
+        let file = self
+            .find_file(selection)
+            .ok_or_else(|| anyhow::anyhow!("No supported file for emote {}", self.name))?;
+        let url = Url::parse(&format!("https:{}/", data.host.url))?.join(&file.name)?;
+        Ok(provider::Emote {
+            name: self.name.clone(),
+            // 7tv files never change, the emote id and file name address the image
+            identifier: provider::content_identifier(
+                SevenTv.name(),
+                (&data.id, &file.name, file.size),
+            ),
+            url,
+            zero_width: self.zero_width(),
+            // files of local sets are hosted on 7tv too
+            provider: SevenTv.name(),
+            scale: file.candidate().map(|candidate| candidate.scale),
+        })
+    }
+}
+
+// Represents an emote set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmoteSet {
+    pub id: String,
+    pub name: String,
+    pub flags: u32,
+    pub tags: Vec<Value>, // unknown type becomes Value
+    pub immutable: bool,
+    pub privileged: bool,
+    pub emotes: Vec<Emote>,
+    pub emote_count: u32,
+    pub capacity: u32,
+    // pub owner: Owner,
+}
+
+enum Fetched {
+    Modified(EmoteSet, Validators),
+    /// The cached set is still up to date
+    NotModified,
+}
+
+/// Downloads an emote set from the v4 api and falls back to v3 if that fails.
+/// Sets cached with validators are refreshed with a conditional v3 request instead,
+/// v4 sets whose hash didn't change count as unchanged too,
+/// and the cached set is used if the api can't be reached.
+pub fn get_emotes(emote_id: &str) -> Result<EmoteSet> {
+    let cached = cache::load(emote_id);
+    let fetched = match &cached {
+        Some(cached) if !cached.validators.is_empty() => {
+            get_emotes_v3(emote_id, Some(&cached.validators))
+        }
+        _ => v4::get_emotes(emote_id)
+            .map(|set| {
+                let hash = cache::content_hash(&set);
+                match &cached {
+                    Some(cached) if cached.validators.content_hash == Some(hash) => {
+                        Fetched::NotModified
+                    }
+                    _ => Fetched::Modified(
+                        set,
+                        Validators {
+                            content_hash: Some(hash),
+                            ..Validators::default()
+                        },
+                    ),
+                }
+            })
+            .or_else(|e| {
+                log::warn!("7tv v4 api failed for {emote_id}, falling back to v3: {e}");
+                get_emotes_v3(emote_id, None)
+            }),
+    };
+    match (fetched, cached) {
+        (Ok(Fetched::Modified(set, validators)), _) => {
+            cache::store(emote_id, &validators, &set);
+            Ok(set)
+        }
+        (Ok(Fetched::NotModified), Some(cached)) => {
+            log::info!("Emote set {emote_id} is unchanged");
+            Ok(cached.set)
+        }
+        (Ok(Fetched::NotModified), None) => {
+            anyhow::bail!("Emote set {emote_id} is not modified, but not cached either")
+        }
+        (Err(e), Some(cached)) => {
+            log::warn!("Using the cached emote set {emote_id}: {e}");
+            Ok(cached.set)
+        }
+        (Err(e), None) => Err(e),
+    }
+}
+
+fn header(response: &ureq::http::Response<ureq::Body>, name: &str) -> Option<String> {
+    let value = response.headers().get(name)?.to_str().ok()?;
+    Some(value.to_string())
+}
+
+fn get_emotes_v3(emote_id: &str, validators: Option<&Validators>) -> Result<Fetched> {
+    log::info!("Downloading emote set {emote_id}");
+    let url = format!("https://7tv.io/v3/emote-sets/{emote_id}");
+
+    let request = || {
+        API_LIMIT.acquire();
+        let mut request = net::agent().get(&url);
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header("If-Modified-Since", last_modified);
+            }
+        }
+        request.call()
+    };
+    API_BREAKER.check()?;
+    let response = Retry::default().run(&url, request);
+    API_BREAKER.record(&response);
+    let mut response = match response {
+        Ok(response) if response.status() == 304 => return Ok(Fetched::NotModified),
+        Err(ureq::Error::StatusCode(304)) => return Ok(Fetched::NotModified),
+        response => response?,
+    };
+    let validators = Validators {
+        etag: header(&response, "etag"),
+        last_modified: header(&response, "last-modified"),
+        content_hash: None,
+    };
+    let mut emote_set = response.body_mut().read_json()?;
+    // who even needs more error handling setps
+    fill_missing_data(&mut emote_set);
+
+    Ok(Fetched::Modified(emote_set, validators))
+}
+
+const USER_SEARCH: &str = r#"
+query SearchUsers($query: String!) {
+  users(query: $query) {
+    id
+    username
+  }
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+struct UserSearch {
+    data: Option<UserSearchData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserSearchData {
+    users: Vec<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+    username: String,
+}
+
+/// 7tv user named like a game account, the account `Name.1234` belongs to the user `name`
+fn find_user(account_name: &str) -> Result<Option<String>> {
+    let Some((name, _)) = account_name.rsplit_once('.') else {
+        return Ok(None);
+    };
+    let name = name.replace(' ', "").to_lowercase();
+    let url = "https://7tv.io/v3/gql";
+    API_BREAKER.check()?;
+    let response = Retry::default().run(url, || {
+        API_LIMIT.acquire();
+        net::agent()
+            .post(url)
+            .send_json(json!({
+                "query": USER_SEARCH,
+                "variables": { "query": name },
+            }))?
+            .body_mut()
+            .read_json::<UserSearch>()
+    });
+    API_BREAKER.record(&response);
+    Ok(response?
+        .data
+        .into_iter()
+        .flat_map(|data| data.users)
+        .find(|user| user.username == name)
+        .map(|user| user.id))
+}
+
+/// The v3 api leaves out the emote data of large sets, request it per emote instead
+fn fill_missing_data(emote_set: &mut EmoteSet) {
+    let missing = emote_set.emotes.iter().filter(|e| e.data.is_none()).count();
+    if missing == 0 {
+        return;
+    }
+    log::info!(
+        "Requesting {missing} emotes of {} individually",
+        emote_set.name
+    );
+    let ids = emote_set
+        .emotes
+        .iter()
+        .filter(|e| e.data.is_none())
+        .map(|e| e.id.clone())
+        .collect::<Vec<_>>();
+    // while a batch of sets downloads, this runs on the thread of the set instead of more threads
+    let data = net::fetch_all(&ids, |id| {
+        let url = format!("https://7tv.io/v3/emotes/{id}");
+        // sets like these have hundreds of emotes, a failing one shouldn't stall the whole set
+        API_BREAKER.check()?;
+        let data = Retry::default().attempts(2).run(&url, || {
+            API_LIMIT.acquire();
+            net::agent().get(&url).call()?.body_mut().read_json()
+        });
+        API_BREAKER.record(&data);
+        anyhow::Ok(data?)
+    });
+    let missing = emote_set.emotes.iter_mut().filter(|e| e.data.is_none());
+    for (emote, data) in missing.zip(data) {
+        match data {
+            Ok(data) => emote.data = Some(data),
+            Err(e) => log::error!("Failed to download emote {}: {e}", emote.name),
+        }
+    }
+}
+
+impl EmoteSet {
+    fn into_provider_set(self, id: &str) -> provider::EmoteSet {
+        let selection = provider::file_selection();
+        let emotes = self
+            .emotes
+            .iter()
+            .filter_map(|emote| match emote.resolve(&selection) {
+                Ok(emote) => Some(emote),
+                Err(e) => {
+                    log::error!("Failed to resolve emote {}: {e}", emote.name);
+                    None
+                }
+            })
+            .collect();
+        provider::EmoteSet {
+            // keep the requested id, so aliases like `global` can be matched again
+            id: id.to_string(),
+            name: self.name,
+            emotes,
+            declared_count: self.emote_count as usize,
+        }
+    }
+}
+
+pub struct SevenTv;
+
+impl EmoteProvider for SevenTv {
+    fn name(&self) -> &'static str {
+        "7tv"
+    }
+
+    fn handles(&self, id: &str) -> bool {
+        !provider::is_local(id)
+    }
+
+    fn fetch_set(&self, id: &str) -> Result<provider::EmoteSet> {
+        Ok(get_emotes(id)?.into_provider_set(id))
+    }
+
+    fn set_url(&self, id: &str) -> Option<String> {
+        Some(format!("https://7tv.app/emote-sets/{id}"))
+    }
+
+    /// The cdn has every emote in every format and scale, without the emote data it isn't
+    /// known whether it's animated, so formats that can't be animated are skipped
+    fn emote_url(&self, emote_id: &str, selection: &FileSelection) -> Option<String> {
+        let format = selection
+            .formats
+            .iter()
+            .find(|format| format.decodable(true))?;
+        let extension = match format {
+            ImageFormat::Avif => "avif",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Png => "png",
+        };
+        Some(format!(
+            "https://cdn.7tv.app/emote/{emote_id}/{}x.{extension}",
+            selection.scale.clamp(1, 4)
+        ))
+    }
+
+    fn download_limits(&self) -> provider::DownloadLimits {
+        provider::DownloadLimits {
+            max_concurrent: 4,
+            interval_ms: 50,
+        }
+    }
+
+    /// The emotes of a user are requested with their user id
+    fn personal_set_id(&self, account_name: &str) -> Result<Option<String>> {
+        find_user(account_name)
+    }
+}
+
+/// Emote sets stored on disk in the same shape as the 7tv api response
+pub struct SevenTvFile;
+
+impl EmoteProvider for SevenTvFile {
+    fn name(&self) -> &'static str {
+        "7tv file"
+    }
+
+    fn handles(&self, id: &str) -> bool {
+        provider::is_local(id)
+    }
+
+    fn fetch_set(&self, id: &str) -> Result<provider::EmoteSet> {
+        log::info!("Reading emote set {id}");
+        let emote_set: EmoteSet = serde_json::from_str(&std::fs::read_to_string(id)?)?;
+        Ok(emote_set.into_provider_set(id))
+    }
+}
@@ -0,0 +1,227 @@
+//! Client for the graphql based v4 api.
+//! Responses get normalized into the v3 types, so the rest of the crate only deals with one shape.
+use crate::net::{self, Retry};
+use crate::seventv::{self, EmoteData, File, FileFormat, Host};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+const URL: &str = "https://7tv.io/v4/gql";
+/// Large sets get truncated by the api if requested in one go
+const PER_PAGE: u32 = 250;
+
+const QUERY: &str = r#"
+query EmoteSet($id: Id!, $page: Int!, $perPage: Int!) {
+  emoteSets {
+    emoteSet(id: $id) {
+      id
+      name
+      capacity
+      emotes(page: $page, perPage: $perPage) {
+        totalCount
+        items {
+          id
+          alias
+          flags { zeroWidth }
+          emote {
+            id
+            defaultName
+            flags { animated }
+            images { url mime size width height frameCount }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    data: Option<Data>,
+    #[serde(default)]
+    errors: Vec<GqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Data {
+    emote_sets: EmoteSets,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmoteSets {
+    emote_set: Option<EmoteSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmoteSet {
+    id: String,
+    name: String,
+    capacity: Option<u32>,
+    emotes: EmoteSetEmotes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmoteSetEmotes {
+    total_count: u32,
+    items: Vec<EmoteSetEmote>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmoteSetEmote {
+    id: String,
+    alias: String,
+    flags: EmoteSetEmoteFlags,
+    emote: Option<Emote>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmoteSetEmoteFlags {
+    zero_width: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Emote {
+    id: String,
+    default_name: String,
+    flags: EmoteFlags,
+    images: Vec<Image>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmoteFlags {
+    animated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Image {
+    url: String,
+    mime: String,
+    size: u32,
+    width: u32,
+    height: u32,
+    frame_count: u32,
+}
+
+pub fn get_emotes(emote_id: &str) -> Result<seventv::EmoteSet> {
+    log::info!("Downloading emote set {emote_id} (v4)");
+    let mut emote_set = get_page(emote_id, 1)?;
+    let mut page = 1;
+    while emote_set.emotes.items.len() < emote_set.emotes.total_count as usize {
+        page += 1;
+        let next = get_page(emote_id, page)?;
+        if next.emotes.items.is_empty() {
+            log::warn!(
+                "Emote set {emote_id} ended after {} of {} emotes",
+                emote_set.emotes.items.len(),
+                emote_set.emotes.total_count
+            );
+            break;
+        }
+        emote_set.emotes.items.extend(next.emotes.items);
+    }
+    Ok(emote_set.into())
+}
+
+fn get_page(emote_id: &str, page: u32) -> Result<EmoteSet> {
+    seventv::API_BREAKER.check()?;
+    let response = Retry::default().run(emote_id, || {
+        seventv::API_LIMIT.acquire();
+        net::agent()
+            .post(URL)
+            .send_json(json!({
+                "query": QUERY,
+                "variables": { "id": emote_id, "page": page, "perPage": PER_PAGE },
+            }))?
+            .body_mut()
+            .read_json::<Response>()
+    });
+    seventv::API_BREAKER.record(&response);
+    let response = response?;
+    if let Some(error) = response.errors.first() {
+        anyhow::bail!("graphql error: {}", error.message);
+    }
+    response
+        .data
+        .and_then(|d| d.emote_sets.emote_set)
+        .ok_or_else(|| anyhow::anyhow!("Emote set {emote_id} not found"))
+}
+
+impl From<EmoteSet> for seventv::EmoteSet {
+    fn from(set: EmoteSet) -> Self {
+        let emotes = set
+            .emotes
+            .items
+            .into_iter()
+            .filter_map(seventv::Emote::from_v4)
+            .collect::<Vec<_>>();
+        Self {
+            id: set.id,
+            name: set.name,
+            flags: 0,
+            tags: Vec::new(),
+            immutable: false,
+            privileged: false,
+            emote_count: set.emotes.total_count,
+            capacity: set.capacity.unwrap_or_default(),
+            emotes,
+        }
+    }
+}
+
+impl seventv::Emote {
+    fn from_v4(item: EmoteSetEmote) -> Option<Self> {
+        let emote = item.emote?;
+        // v3 splits image urls into the host and the file name
+        let (host, _) = emote.images.first()?.url.rsplit_once('/')?;
+        let host = host.trim_start_matches("https:").to_string();
+        let files = emote
+            .images
+            .into_iter()
+            .filter_map(|image| {
+                let (_, name) = image.url.rsplit_once('/')?;
+                Some(File {
+                    name: name.to_string(),
+                    static_name: name.to_string(),
+                    width: image.width,
+                    height: image.height,
+                    frame_count: image.frame_count,
+                    size: image.size,
+                    format: match image.mime.as_str() {
+                        "image/avif" => FileFormat::Avif,
+                        "image/webp" => FileFormat::Webp,
+                        "image/gif" => FileFormat::Gif,
+                        "image/png" => FileFormat::Png,
+                        _ => FileFormat::Unknown,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        Some(Self {
+            id: item.id,
+            name: item.alias,
+            flags: item.flags.zero_width as u32,
+            timestamp: 0,
+            actor_id: Value::Null,
+            data: Some(EmoteData {
+                id: emote.id,
+                name: emote.default_name,
+                state: Vec::new(),
+                listed: true,
+                animated: emote.flags.animated,
+                host: Host { url: host, files },
+            }),
+        })
+    }
+}
@@ -0,0 +1,60 @@
+//! Keybinds that switch emote sets on and off, e.g. a meme set only during downtime
+use crate::setting_path;
+use crate::settings::Settings;
+use crate::util::e;
+use nexus::alert::send_alert;
+use nexus::keybind::{keybind_handler, register_keybind_with_string};
+
+/// Number of keybinds registered with Nexus, each one toggles the set assigned to it
+pub const SET_SLOTS: usize = 5;
+const KEYBIND_PREFIX: &str = "KB_EMOTES_SET_SLOT_";
+
+pub fn register() {
+    for slot in 1..=SET_SLOTS {
+        register_keybind_with_string(
+            format!("{KEYBIND_PREFIX}{slot}"),
+            keybind_handler!(|identifier, is_release| {
+                if !is_release {
+                    toggle(identifier);
+                }
+            }),
+            "(null)",
+        )
+        .revert_on_unload();
+    }
+}
+
+fn toggle(identifier: &str) {
+    let Some(slot) = identifier
+        .strip_prefix(KEYBIND_PREFIX)
+        .and_then(|slot| slot.parse::<usize>().ok())
+    else {
+        return;
+    };
+    let mut settings = Settings::get();
+    let Some(id) = settings
+        .set_slots
+        .get(slot - 1)
+        .filter(|id| !id.is_empty())
+        .cloned()
+    else {
+        return;
+    };
+    // muted sets stay loaded, the next message already ignores them
+    let muted = if settings.muted_sets.contains(&id) {
+        settings.muted_sets.retain(|m| m != &id);
+        false
+    } else {
+        settings.muted_sets.push(id.clone());
+        true
+    };
+    if let Err(e) = settings.save(&setting_path()) {
+        log::error!("Failed to save settings: {e}");
+    }
+    log::info!("Emote set {id} muted: {muted}");
+    send_alert(if muted {
+        format!("{} {id}", e("Emote set off:"))
+    } else {
+        format!("{} {id}", e("Emote set on:"))
+    });
+}
@@ -0,0 +1,188 @@
+//! Diagnostics mode that listens to both chat sources and compares when and what they deliver,
+//! so users can pick the faster and more complete one
+use crate::chat_events::{Message, MessageSource};
+use crate::settings::ChatMessageSource;
+use crate::util::{UiExt, e};
+use nexus::imgui::Ui;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use strum::VariantNames;
+
+static RUNNING: AtomicBool = const { AtomicBool::new(false) };
+static COMPARISON: Mutex<Comparison> = const { Mutex::new(Comparison::new()) };
+/// Messages the other source didn't deliver within this time count as missed
+const MATCH_WINDOW: Duration = Duration::from_secs(5);
+
+struct Arrival {
+    source: usize,
+    author: String,
+    content: String,
+    at: Instant,
+}
+
+#[derive(Clone, Copy)]
+struct SourceStats {
+    received: u32,
+    /// Matched messages this source delivered before the other one
+    first: u32,
+    /// Summed time this source was ahead
+    lead: Duration,
+    /// Messages only the other source delivered
+    missed: u32,
+}
+
+struct Comparison {
+    pending: Vec<Arrival>,
+    stats: [SourceStats; 2],
+    matched: u32,
+    /// Matched messages with a different text on both sources
+    differing: u32,
+}
+
+impl Comparison {
+    const fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            stats: [SourceStats {
+                received: 0,
+                first: 0,
+                lead: Duration::ZERO,
+                missed: 0,
+            }; 2],
+            matched: 0,
+            differing: 0,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let stats = &mut self.stats;
+        self.pending.retain(|arrival| {
+            let expired = now.duration_since(arrival.at) > MATCH_WINDOW;
+            if expired {
+                stats[1 - arrival.source].missed += 1;
+            }
+            !expired
+        });
+    }
+}
+
+fn index(source: ChatMessageSource) -> usize {
+    match source {
+        ChatMessageSource::UnofficialExtras => 0,
+        ChatMessageSource::ChatEvents => 1,
+    }
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+pub fn stop() {
+    RUNNING.store(false, Ordering::Relaxed);
+    *COMPARISON.lock().unwrap() = Comparison::new();
+}
+
+/// Records a message of either source while the comparison runs
+pub fn observe(source: ChatMessageSource, message: &Message) {
+    if !is_running() {
+        return;
+    }
+    // unofficial extras only delivers party and squad chat
+    if !matches!(
+        message.source,
+        MessageSource::Party(_) | MessageSource::Squad(_)
+    ) {
+        return;
+    }
+    let (Some(author), Some(content)) = (message.author(), message.content()) else {
+        return;
+    };
+    let source = index(source);
+    let now = Instant::now();
+    let mut comparison = COMPARISON.lock().unwrap();
+    comparison.expire(now);
+    comparison.stats[source].received += 1;
+    // both sources deliver the messages of a player in order, the oldest one is the match
+    let Some(i) = comparison
+        .pending
+        .iter()
+        .position(|a| a.source != source && a.author == author)
+    else {
+        comparison.pending.push(Arrival {
+            source,
+            author: author.to_string(),
+            content: content.to_string(),
+            at: now,
+        });
+        return;
+    };
+    let other = comparison.pending.remove(i);
+    comparison.matched += 1;
+    comparison.stats[other.source].first += 1;
+    comparison.stats[other.source].lead += now.duration_since(other.at);
+    if other.content != content {
+        comparison.differing += 1;
+    }
+}
+
+pub fn render(ui: &Ui) {
+    if !is_running() {
+        if ui.button(e("Compare chat sources")) {
+            RUNNING.store(true, Ordering::Relaxed);
+        }
+        ui.help_marker(|| {
+            ui.tooltip_text(e(
+                "Listens to both chat sources and compares which one delivers party and squad messages first and complete",
+            ));
+        });
+        return;
+    }
+    if ui.button(e("Stop comparing")) {
+        stop();
+        return;
+    }
+    let mut comparison = COMPARISON.lock().unwrap();
+    comparison.expire(Instant::now());
+    for (i, name) in <ChatMessageSource as VariantNames>::VARIANTS
+        .iter()
+        .enumerate()
+    {
+        let stats = comparison.stats[i];
+        let average_lead = stats.lead.as_millis() / stats.first.max(1) as u128;
+        ui.text(format!(
+            "{name}: {} {}, {} {} ({} {average_lead}ms), {} {}",
+            stats.received,
+            e("received"),
+            stats.first,
+            e("first"),
+            e("avg"),
+            stats.missed,
+            e("missed"),
+        ));
+    }
+    ui.text(format!(
+        "{}: {}, {}: {}",
+        e("Matched"),
+        comparison.matched,
+        e("Different text"),
+        comparison.differing
+    ));
+    let [ue, ce] = comparison.stats;
+    if ue.received + ce.received == 0 {
+        ui.text_disabled(e("Waiting for party or squad messages"));
+        return;
+    }
+    // a missed message weighs more than being a bit later
+    let score = |stats: SourceStats| stats.first as i64 - 2 * stats.missed as i64;
+    let better = if score(ue) >= score(ce) {
+        ChatMessageSource::UnofficialExtras
+    } else {
+        ChatMessageSource::ChatEvents
+    };
+    ui.text(format!(
+        "{}: {}",
+        e("Recommended source"),
+        <ChatMessageSource as VariantNames>::VARIANTS[index(better)]
+    ));
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+/// Default of the on screen limit in the settings
+pub const DEFAULT_MAX_ACTIVE_EMOTES: u32 = 50;
+/// Every emote is its own window, beyond this the game stutters no matter the setting
+pub const MAX_ACTIVE_EMOTES_LIMIT: u32 = 500;
+/// Spawns waiting for a free slot, a spam wave would otherwise keep emotes coming for minutes
+pub const MAX_QUEUED_SPAWNS: usize = 100;
+
+/// Pending spawns grouped by message author.
+/// Authors take turns, so a single spammer can't fill up all emote slots.
+pub struct SpawnQueue<T> {
+    authors: VecDeque<(String, VecDeque<T>)>,
+}
+
+impl<T> SpawnQueue<T> {
+    pub const fn new() -> Self {
+        Self {
+            authors: VecDeque::new(),
+        }
+    }
+
+    /// Queues `item`, a full queue drops the oldest spawn of the author with the most of them.
+    /// Returns whether a spawn was dropped
+    pub fn push(&mut self, author: &str, item: T) -> bool {
+        let dropped = self.len() >= MAX_QUEUED_SPAWNS;
+        if dropped {
+            let longest = self
+                .authors
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, queue))| queue.len())
+                .map(|(i, _)| i);
+            if let Some(i) = longest {
+                self.authors[i].1.pop_front();
+                if self.authors[i].1.is_empty() {
+                    self.authors.remove(i);
+                }
+            }
+        }
+        if let Some((_, queue)) = self.authors.iter_mut().find(|(a, _)| a == author) {
+            queue.push_back(item);
+        } else {
+            self.authors
+                .push_back((author.to_string(), VecDeque::from([item])));
+        }
+        dropped
+    }
+
+    /// Takes the next spawn of the author whose turn it is
+    pub fn pop(&mut self) -> Option<T> {
+        let (author, mut queue) = self.authors.pop_front()?;
+        let item = queue.pop_front();
+        if !queue.is_empty() {
+            self.authors.push_back((author, queue));
+        }
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.authors.iter().map(|(_, queue)| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.authors.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.authors.clear();
+    }
+}
@@ -0,0 +1,86 @@
+//! Squad state from unofficial extras and emote sets adopted from the commander
+use crate::{EMOTE_SETS, cancel, fetch_emote_set, filter, progress};
+use nexus::arcdps::extras::{SquadUpdate, UserRole};
+use std::sync::Mutex;
+
+/// Squad members by account name
+static MEMBERS: Mutex<Vec<(String, UserRole)>> = const { Mutex::new(Vec::new()) };
+/// Emote sets adopted from the commander, they are not part of the settings
+static TEMPORARY_SETS: Mutex<Vec<String>> = const { Mutex::new(Vec::new()) };
+
+fn is_member(role: UserRole) -> bool {
+    matches!(
+        role,
+        UserRole::SquadLeader | UserRole::Lieutenant | UserRole::Member
+    )
+}
+
+pub fn update(update: &SquadUpdate) {
+    let own = filter::self_account();
+    let mut members = MEMBERS.lock().unwrap();
+    let mut left = false;
+    for user in update.iter() {
+        let Some(account_name) = user.account_name else {
+            continue;
+        };
+        let account_name = filter::normalize_account(account_name);
+        members.retain(|(name, _)| name != account_name);
+        if is_member(user.role) {
+            members.push((account_name.to_string(), user.role));
+        } else {
+            left |= own.as_deref() == Some(account_name);
+        }
+    }
+    if left {
+        log::info!("Left the squad");
+        members.clear();
+        drop(members);
+        drop_temporary_sets();
+    }
+}
+
+/// Number of squad or party members including ourself, 0 if not in a group
+pub fn size() -> usize {
+    MEMBERS.lock().unwrap().len()
+}
+
+pub fn is_commander(account_name: &str) -> bool {
+    MEMBERS.lock().unwrap().iter().any(|(name, role)| {
+        name == filter::normalize_account(account_name) && *role == UserRole::SquadLeader
+    })
+}
+
+/// Temporarily loads the emote sets of the commander
+pub fn adopt(ids: &[&str], configured: &[String]) {
+    let mut temporary = TEMPORARY_SETS.lock().unwrap();
+    for id in ids {
+        if configured.iter().any(|c| c == id) || temporary.iter().any(|t| t == id) {
+            continue;
+        }
+        log::info!("Adopting squad emote set {id}");
+        temporary.push(id.to_string());
+        fetch_emote_set(id.to_string());
+    }
+}
+
+pub fn temporary_sets() -> Vec<String> {
+    TEMPORARY_SETS.lock().unwrap().clone()
+}
+
+pub fn drop_temporary_sets() {
+    let temporary = std::mem::take(&mut *TEMPORARY_SETS.lock().unwrap());
+    // sets still downloading are dropped when they arrive
+    for id in &temporary {
+        cancel::SET_DOWNLOADS.cancel(id);
+        progress::SETS.remove(id);
+    }
+    EMOTE_SETS
+        .lock()
+        .unwrap()
+        .retain(|set| !temporary.contains(&set.id));
+}
+
+pub fn clear() {
+    MEMBERS.lock().unwrap().clear();
+    TEMPORARY_SETS.lock().unwrap().clear();
+}
@@ -0,0 +1,258 @@
+//! Counts of spawned emotes, shown in the statistics window
+use crate::provider::EmoteSet;
+use crate::settings::{Channel, DisabledEmote, Settings};
+use crate::util::e;
+use crate::{EMOTE_SETS, privacy, setting_path};
+use nexus::imgui::{MouseButton, Ui, Window};
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::sync::Mutex;
+use std::time::Instant;
+use strum::{VariantArray, VariantNames};
+
+static STATS: Mutex<Stats> = const { Mutex::new(Stats::new()) };
+
+const TOP_EMOTES: usize = 10;
+const TOP_AUTHORS: usize = 3;
+const BAR_HEIGHT: f32 = 20.0;
+/// One color per channel in the order of [`Channel::VARIANTS`], the last one for other messages
+const COLORS: [[f32; 4]; 9] = [
+    [0.40, 0.80, 0.40, 1.0],
+    [0.85, 0.85, 0.85, 1.0],
+    [0.90, 0.70, 0.30, 1.0],
+    [0.40, 0.60, 0.95, 1.0],
+    [0.55, 0.85, 0.95, 1.0],
+    [0.90, 0.40, 0.40, 1.0],
+    [0.80, 0.50, 0.90, 1.0],
+    [0.95, 0.55, 0.80, 1.0],
+    [0.50, 0.50, 0.50, 1.0],
+];
+
+struct Stats {
+    spawned: usize,
+    by_emote: Vec<(String, EmoteStats)>,
+    by_channel: Vec<(Option<Channel>, usize)>,
+}
+
+struct EmoteStats {
+    count: usize,
+    by_author: Vec<(String, usize)>,
+    last_seen: Instant,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            spawned: 0,
+            by_emote: Vec::new(),
+            by_channel: Vec::new(),
+        }
+    }
+}
+
+fn increment<K: PartialEq>(counts: &mut Vec<(K, usize)>, key: K) {
+    match counts.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, count)) => *count += 1,
+        None => counts.push((key, 1)),
+    }
+}
+
+pub fn record(emote: &str, channel: Option<Channel>, author: Option<&str>) {
+    let mut stats = STATS.lock().unwrap();
+    stats.spawned += 1;
+    increment(&mut stats.by_channel, channel);
+    let index = match stats.by_emote.iter().position(|(name, _)| name == emote) {
+        Some(index) => index,
+        None => {
+            stats.by_emote.push((
+                emote.to_string(),
+                EmoteStats {
+                    count: 0,
+                    by_author: Vec::new(),
+                    last_seen: Instant::now(),
+                },
+            ));
+            stats.by_emote.len() - 1
+        }
+    };
+    let emote_stats = &mut stats.by_emote[index].1;
+    emote_stats.count += 1;
+    emote_stats.last_seen = Instant::now();
+    if let Some(author) = author {
+        increment(&mut emote_stats.by_author, author.to_string());
+    }
+}
+
+/// Changes made from the context menu of an emote, applied once the stats are unlocked
+enum Action {
+    Block(String),
+    SetSize(String, f32),
+}
+
+fn channel_index(channel: Option<Channel>) -> usize {
+    channel
+        .and_then(|channel| {
+            <Channel as VariantArray>::VARIANTS
+                .iter()
+                .position(|c| *c == channel)
+        })
+        .unwrap_or(COLORS.len() - 1)
+}
+
+fn channel_name(channel: Option<Channel>) -> String {
+    match channel {
+        Some(_) => e(<Channel as VariantNames>::VARIANTS[channel_index(channel)]),
+        None => e("Other"),
+    }
+}
+
+pub fn render(ui: &Ui) {
+    let (show_stats, size_overrides) = {
+        let settings = Settings::get();
+        (settings.show_stats, settings.emote_size_overrides.clone())
+    };
+    if !show_stats {
+        return;
+    }
+    let stats = STATS.lock().unwrap();
+    let mut action = None;
+    Window::new(e("Emote Statistics"))
+        .always_auto_resize(true)
+        .build(ui, || {
+            ui.text(format!("{}: {}", e("Spawned emotes"), stats.spawned));
+            if stats.spawned == 0 {
+                return;
+            }
+            channel_bar(ui, &stats);
+            ui.separator();
+            let mut top = stats.by_emote.iter().collect::<Vec<_>>();
+            top.sort_by_key(|(_, emote_stats)| Reverse(emote_stats.count));
+            for (name, emote_stats) in top.into_iter().take(TOP_EMOTES) {
+                ui.text(format!("{:>5} {name}", emote_stats.count));
+                let popup = format!("emotestats{name}");
+                if ui.is_item_clicked_with_button(MouseButton::Right) {
+                    ui.open_popup(&popup);
+                }
+                let size = size_overrides.get(name).copied().unwrap_or(1.0);
+                ui.popup(&popup, || {
+                    action = emote_menu(ui, name, emote_stats, size);
+                });
+            }
+        });
+    drop(stats);
+    let Some(action) = action else {
+        return;
+    };
+    let mut settings = Settings::get();
+    match action {
+        // the statistics only know the name, so it is blocked in every set providing it
+        Action::Block(name) => {
+            let custom = EmoteSet::custom(&settings.custom_emotes);
+            let emote_sets = EMOTE_SETS.lock().unwrap();
+            let set_ids = emote_sets
+                .iter()
+                .chain(std::iter::once(&custom))
+                .filter(|set| set.emotes.iter().any(|emote| emote.name == name))
+                .map(|set| set.id.clone())
+                .collect::<Vec<_>>();
+            drop(emote_sets);
+            for set_id in set_ids {
+                if !settings.is_disabled(&set_id, &name) {
+                    settings.disabled_emotes.push(DisabledEmote {
+                        set_id,
+                        name: name.clone(),
+                    });
+                }
+            }
+        }
+        // the default size doesn't need an override
+        Action::SetSize(name, size) => {
+            if size == 1.0 {
+                settings.emote_size_overrides.remove(&name);
+            } else {
+                settings.emote_size_overrides.insert(name, size);
+            }
+        }
+    }
+    if let Err(e) = settings.save(&setting_path()) {
+        log::error!("Failed to save settings: {e}");
+    }
+}
+
+/// Context menu of a single emote with its top users, blocking and a size override
+fn emote_menu(ui: &Ui, name: &str, emote_stats: &EmoteStats, size: f32) -> Option<Action> {
+    thread_local! {
+        /// Size of the slider while it is dragged, saved once it is released
+        static DRAGGED_SIZE: Cell<Option<f32>> = const { Cell::new(None) };
+    }
+    ui.text(name);
+    ui.separator();
+    ui.text(format!(
+        "{}: {}s {}",
+        e("Last seen"),
+        emote_stats.last_seen.elapsed().as_secs(),
+        e("ago")
+    ));
+    if !emote_stats.by_author.is_empty() {
+        ui.text(e("Used most by"));
+        let mut authors = emote_stats.by_author.iter().collect::<Vec<_>>();
+        authors.sort_by_key(|(_, count)| Reverse(*count));
+        for (author, count) in authors.into_iter().take(TOP_AUTHORS) {
+            ui.text(format!("{count:>5} {}", privacy::display_name(author)));
+        }
+    }
+    ui.separator();
+    let mut action = None;
+    let mut size = DRAGGED_SIZE.get().unwrap_or(size);
+    if ui.slider(e("Size"), 0.5, 3.0, &mut size) {
+        DRAGGED_SIZE.set(Some(size));
+    }
+    if ui.is_item_deactivated_after_edit() {
+        DRAGGED_SIZE.set(None);
+        action = Some(Action::SetSize(name.to_string(), size));
+    }
+    if ui.button(e("Reset size")) {
+        action = Some(Action::SetSize(name.to_string(), 1.0));
+    }
+    ui.same_line();
+    if ui.button(e("Block")) {
+        action = Some(Action::Block(name.to_string()));
+        ui.close_current_popup();
+    }
+    action
+}
+
+/// Single bar stacked by the share of every channel
+fn channel_bar(ui: &Ui, stats: &Stats) {
+    let mut by_channel = stats.by_channel.iter().collect::<Vec<_>>();
+    by_channel.sort_by_key(|(channel, _)| channel_index(*channel));
+    let width = ui.content_region_avail()[0].max(200.0);
+    let [mut x, y] = ui.cursor_screen_pos();
+    let draw_list = ui.get_window_draw_list();
+    for (channel, count) in &by_channel {
+        let segment = width * *count as f32 / stats.spawned as f32;
+        draw_list
+            .add_rect(
+                [x, y],
+                [x + segment, y + BAR_HEIGHT],
+                COLORS[channel_index(*channel)],
+            )
+            .filled(true)
+            .build();
+        x += segment;
+    }
+    ui.dummy([width, BAR_HEIGHT]);
+    for (i, (channel, count)) in by_channel.into_iter().enumerate() {
+        if i > 0 {
+            ui.same_line();
+        }
+        ui.text_colored(
+            COLORS[channel_index(*channel)],
+            format!("{} {count}", channel_name(*channel)),
+        );
+    }
+}
+
+pub fn clear() {
+    *STATS.lock().unwrap() = Stats::new();
+}
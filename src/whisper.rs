@@ -0,0 +1,67 @@
+//! Popup to answer whispers with emotes, lists quick emote names to copy
+use crate::privacy;
+use crate::settings::Settings;
+use crate::util::e;
+use nexus::imgui::{Ui, Window};
+use std::sync::Mutex;
+
+/// Latest whisper that contained emotes, waiting for a reply
+static LAST_WHISPER: Mutex<Option<EmoteWhisper>> = const { Mutex::new(None) };
+
+struct EmoteWhisper {
+    author: String,
+    emotes: Vec<String>,
+}
+
+/// Remembers a whisper with emotes, replaces the previous one
+pub fn received(author: &str, emotes: Vec<String>) {
+    *LAST_WHISPER.lock().unwrap() = Some(EmoteWhisper {
+        author: author.to_string(),
+        emotes,
+    });
+}
+
+/// Click copies the emote name, the whisper emotes come first, then the favorites
+pub fn render(ui: &Ui) {
+    let favorites = Settings::get().favorites.clone();
+    let mut last_whisper = LAST_WHISPER.lock().unwrap();
+    let Some(whisper) = last_whisper.as_ref() else {
+        return;
+    };
+    let mut names = whisper.emotes.clone();
+    for favorite in favorites {
+        if !names.contains(&favorite) {
+            names.push(favorite);
+        }
+    }
+    let mut close = false;
+    Window::new(e("Reply with emote"))
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(ui, || {
+            ui.text(format!(
+                "{} {}",
+                privacy::display_name(&whisper.author),
+                e("whispered you emotes, click one to copy it")
+            ));
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    ui.same_line();
+                }
+                if ui.button(format!("{name}##whisperreply{i}")) {
+                    ui.set_clipboard_text(name);
+                    close = true;
+                }
+            }
+            if ui.button(e("Close") + "##whisperreplyclose") {
+                close = true;
+            }
+        });
+    if close {
+        *last_whisper = None;
+    }
+}
+
+pub fn clear() {
+    LAST_WHISPER.lock().unwrap().take();
+}